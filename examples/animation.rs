@@ -0,0 +1,74 @@
+use std::f64::consts::PI;
+
+use ray_tracer::{
+    animation::{render_sequence, Animation, Easing, MaterialProperty, SequenceSettings, Track},
+    color::Color,
+    math::{matrix4::Matrix4, tuple::Tuple},
+    progress::TerminalProgress,
+};
+
+#[allow(dead_code)]
+mod chapter_7;
+
+const WIDTH: usize = 200;
+const HEIGHT: usize = 100;
+
+// Renders an orbit around the chapter 7 scene. Accepts `--frames FIRST-LAST`,
+// `--fps RATE` and `--output PATTERN`, e.g. `--output output/frame_####.ppm`,
+// or `--scene FILE` with `# frames`, `# fps` and `# output` lines.
+pub fn main() {
+    let settings = match SequenceSettings::from_args(std::env::args().skip(1)) {
+        Ok(settings) => settings,
+        Err(message) => {
+            eprintln!("{}", message);
+            std::process::exit(1);
+        }
+    };
+
+    let (camera, mut world) = chapter_7::scene(WIDTH, HEIGHT);
+    let duration = settings.frame_time(settings.last_frame);
+
+    let orbit = (0..=8).fold(Track::new(), |track, step| {
+        let angle = step as f64 / 8. * 2. * PI;
+        let position = Tuple::point(-5. * angle.sin(), 1.5, -5. * angle.cos());
+
+        track.key(step as f64 / 8. * duration, position, Easing::Linear)
+    });
+
+    let mut animation = Animation::new();
+    animation.camera(
+        orbit,
+        Track::constant(Tuple::point(0., 1., 0.)),
+        Tuple::vector(0., 1., 0.),
+    );
+    animation.light_intensity(
+        0,
+        Track::new()
+            .key(0., Color::new(0.2, 0.2, 0.3), Easing::ease_in_out())
+            .key(duration, Color::white(), Easing::Linear),
+    );
+    // The middle sphere bobs up and down and turns glossy
+    animation.transform(
+        3,
+        Track::new()
+            .key(0., Matrix4::translation(-0.5, 1., 0.5), Easing::Smoothstep)
+            .key(duration / 2., Matrix4::translation(-0.5, 2., 0.5), Easing::Smoothstep)
+            .key(duration, Matrix4::translation(-0.5, 1., 0.5), Easing::Linear),
+    );
+    animation.material(
+        3,
+        MaterialProperty::Reflective,
+        Track::new()
+            .key(0., 0., Easing::Linear)
+            .key(duration, 0.6, Easing::Linear),
+    );
+
+    render_sequence(
+        &animation,
+        camera,
+        &mut world,
+        &settings,
+        &mut TerminalProgress,
+    )
+    .expect("Error writing frames");
+}
@@ -46,7 +46,7 @@ pub fn scene(width: usize, height: usize) -> (Camera, World) {
     west_wall.transform = Matrix4::translation(-5., 0., 0.)
         * Matrix4::rotation_z(1.5708)
         * Matrix4::rotation_y(1.5708);
-    material = wall_material;
+    material = wall_material.clone();
     west_wall.set_material(material);
     world.add_object(west_wall);
 
@@ -54,13 +54,13 @@ pub fn scene(width: usize, height: usize) -> (Camera, World) {
     east_wall.transform = Matrix4::translation(5., 0., 0.)
         * Matrix4::rotation_z(1.5708)
         * Matrix4::rotation_y(1.5708);
-    material = wall_material;
+    material = wall_material.clone();
     east_wall.set_material(material);
     world.add_object(east_wall);
 
     let mut north_wall = Object::plane();
     north_wall.transform = Matrix4::translation(0., 0., 5.) * Matrix4::rotation_x(1.5708);
-    material = wall_material;
+    material = wall_material.clone();
     north_wall.set_material(material);
     world.add_object(north_wall);
 
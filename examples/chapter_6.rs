@@ -39,13 +39,13 @@ pub fn scene(width: usize, height: usize) -> Canvas {
                 let eye = -r.direction;
 
                 let pixel_color = material::lighting(
-                    material,
+                    &material,
                     hit.object,
                     light,
                     hit_point,
                     eye,
                     hit_normal_vector,
-                    false,
+                    Color::white(),
                 );
 
                 canvas.write_pixel(x as i32, y as i32, pixel_color);
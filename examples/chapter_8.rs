@@ -14,7 +14,7 @@ pub fn scene(width: usize, height: usize) -> (Camera, World) {
     let mut floor_material = Material::new();
     floor_material.color = Color::new(1., 0.9, 0.9);
     floor_material.specular = 0.;
-    floor.set_material(floor_material);
+    floor.set_material(floor_material.clone());
     world.add_object(floor);
 
     let mut left_wall = Object::sphere();
@@ -22,7 +22,7 @@ pub fn scene(width: usize, height: usize) -> (Camera, World) {
         * Matrix4::rotation_y(-PI / 4.)
         * Matrix4::rotation_x(PI / 2.)
         * Matrix4::scaling(10., 0.01, 10.);
-    left_wall.set_material(floor_material);
+    left_wall.set_material(floor_material.clone());
     world.add_object(left_wall);
 
     let mut right_wall = Object::sphere();
@@ -0,0 +1,140 @@
+use std::{
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use examples::{distributed_scene, load_distributed_scene};
+use ray_tracer::{
+    distributed::{accept_workers, render_distributed, serve_worker, Connection},
+    progress::TerminalProgress,
+    tiles::TileSettings,
+};
+
+const USAGE: &str = "\
+Usage:
+  distributed coordinator --scene FILE.obj (--local N | --listen ADDR --workers N)
+                          [--size WxH] [--tile-size N] [--checkpoints DIR] [--output FILE]
+  distributed worker (--connect ADDR | --stdio)";
+
+// Renders an OBJ model on a floor, split into tiles across worker processes.
+// With `--local N` the coordinator starts N workers itself and talks to them
+// over their stdin and stdout; otherwise workers started anywhere connect to
+// `--listen`, e.g. `distributed worker --connect 127.0.0.1:7878`.
+pub fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let result = match args.first().map(String::as_str) {
+        Some("coordinator") => coordinator(&args[1..]),
+        Some("worker") => worker(&args[1..]),
+        _ => Err(USAGE.to_owned()),
+    };
+
+    if let Err(message) = result {
+        eprintln!("{}", message);
+        std::process::exit(1);
+    }
+}
+
+fn coordinator(args: &[String]) -> Result<(), String> {
+    let mut obj_path = None;
+    let mut size = (400, 300);
+    let mut local = None;
+    let mut listen = None;
+    let mut workers = 1;
+    let mut settings = TileSettings::default();
+    let mut output = "./output/distributed.ppm".to_owned();
+
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("Missing value for {}", flag))
+        };
+        match flag.as_str() {
+            "--scene" => obj_path = Some(value()?.clone()),
+            "--size" => {
+                let value = value()?;
+                size = value
+                    .split_once('x')
+                    .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+                    .ok_or_else(|| format!("Invalid size: {}", value))?;
+            }
+            "--local" => local = Some(number(value()?)?),
+            "--listen" => listen = Some(value()?.clone()),
+            "--workers" => workers = number(value()?)?,
+            "--tile-size" => settings.tile_size = number(value()?)?,
+            "--checkpoints" => settings.checkpoint_dir = Some(PathBuf::from(value()?)),
+            "--output" => output = value()?.clone(),
+            _ => return Err(format!("Unknown option {}\n{}", flag, USAGE)),
+        }
+    }
+
+    let obj_path = obj_path.ok_or_else(|| USAGE.to_owned())?;
+    let obj = std::fs::read_to_string(&obj_path)
+        .map_err(|error| format!("Error reading {}: {}", obj_path, error))?;
+    let scene = distributed_scene(size, &obj);
+
+    let mut children = vec![];
+    let connections = match (local, listen) {
+        (Some(count), None) => {
+            let exe = std::env::current_exe().map_err(|error| error.to_string())?;
+            for _ in 0..count {
+                children.push(
+                    Command::new(&exe)
+                        .args(["worker", "--stdio"])
+                        .stdin(Stdio::piped())
+                        .stdout(Stdio::piped())
+                        .spawn()
+                        .map_err(|error| format!("Error starting worker: {}", error))?,
+                );
+            }
+            children
+                .iter_mut()
+                .map(Connection::child)
+                .collect::<std::io::Result<Vec<_>>>()
+        }
+        (None, Some(address)) => {
+            let listener = TcpListener::bind(&address).map_err(|error| error.to_string())?;
+            eprintln!("Waiting for {} workers on {}", workers, address);
+            accept_workers(&listener, workers)
+        }
+        _ => return Err(USAGE.to_owned()),
+    }
+    .map_err(|error| error.to_string())?;
+
+    let canvas = render_distributed(
+        &scene,
+        &load_distributed_scene,
+        &settings,
+        connections,
+        &mut TerminalProgress,
+    )
+    .map_err(|error| format!("Render failed: {}", error))?;
+    for mut child in children {
+        let _ = child.wait();
+    }
+
+    println!("Writing to: {}", output);
+    std::fs::write(&output, canvas.to_ppm()).map_err(|error| error.to_string())
+}
+
+fn worker(args: &[String]) -> Result<(), String> {
+    let connection = match args {
+        [flag, address] if flag == "--connect" => {
+            let stream = TcpStream::connect(address).map_err(|error| error.to_string())?;
+            Connection::tcp(stream).map_err(|error| error.to_string())?
+        }
+        [flag] if flag == "--stdio" => Connection::stdio(),
+        _ => return Err(USAGE.to_owned()),
+    };
+
+    serve_worker(connection, &load_distributed_scene)
+        .map_err(|error| format!("Worker failed: {}", error))
+}
+
+fn number(value: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Expected a number, found {}", value))
+}
@@ -0,0 +1,72 @@
+use std::f64::consts::PI;
+
+use examples;
+use ray_tracer::{
+    camera::Camera,
+    color::Color,
+    light::Light,
+    material::Material,
+    math::{matrix4::Matrix4, transformations, tuple::Tuple},
+    medium::{Fog, Medium},
+    shape::Object,
+    world::World,
+};
+
+// Light shafts from a lamp behind a slatted wall, in hazy air, plus a ball
+// of smoke.
+pub fn scene(width: usize, height: usize) -> (Camera, World) {
+    let mut world = World::new();
+
+    let mut floor = Object::plane();
+    let mut material = Material::new();
+    material.color = Color::new(0.8, 0.8, 0.7);
+    material.specular = 0.;
+    floor.set_material(material);
+    world.add_object(floor);
+
+    let mut slat_material = Material::new();
+    slat_material.color = Color::new(0.3, 0.2, 0.2);
+    for i in 0..6 {
+        let mut slat = Object::cube();
+        slat.transform =
+            Matrix4::translation(-2.5 + i as f64, 2., 3.) * Matrix4::scaling(0.3, 2., 0.1);
+        slat.set_material(slat_material.clone());
+        world.add_object(slat);
+    }
+
+    let mut smoke = Object::sphere();
+    smoke.transform = Matrix4::translation(1.5, 1., -0.5) * Matrix4::scaling(0.8, 0.8, 0.8);
+    let mut medium = Medium::new(0.6, 1.2);
+    medium.color = Color::new(0.7, 0.7, 0.8);
+    smoke.set_material(Material::volume(medium));
+    world.add_object(smoke);
+
+    let mut haze = Medium::new(0.01, 0.08);
+    haze.anisotropy = 0.5;
+    haze.steps = 24;
+    world.fog = Some(Fog::new(haze, 30.));
+
+    world.add_light(Light::point_light(
+        Tuple::point(0., 3., 8.),
+        Color::new(3., 2.8, 2.5),
+    ));
+
+    let mut camera = Camera::new(width as i32, height as i32, PI / 3.);
+    camera.transform = transformations::view_transform(
+        Tuple::point(0., 1.5, -6.),
+        Tuple::point(0., 1.5, 0.),
+        Tuple::vector(0., 1., 0.),
+    );
+
+    (camera, world)
+}
+
+const ASPECT: f64 = 16. / 9.;
+
+const WIDTH: usize = 400;
+const HEIGHT: usize = (WIDTH as f64 / ASPECT) as usize;
+
+pub fn main() {
+    let (camera, world) = scene(WIDTH, HEIGHT);
+    examples::run_and_save_scene("fog", camera, world);
+}
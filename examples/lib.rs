@@ -1,6 +1,15 @@
-use std::{fs::File, io::Write};
+use std::{f64::consts::PI, fs::File, io::Write};
 
-use ray_tracer::{camera::Camera, world::World};
+use ray_tracer::{
+    camera::Camera,
+    color::Color,
+    light::Light,
+    material::Material,
+    math::{matrix4::Matrix4, transformations, tuple::Tuple},
+    obj::WavefrontObj,
+    shape::Object,
+    world::World,
+};
 
 pub fn output_file_path(example_name: &str) -> String {
     format!("./output/{}.ppm", example_name)
@@ -15,3 +24,62 @@ pub fn run_and_save_scene(example_name: &str, camera: Camera, world: World) {
     let mut f = File::create(&file_name).expect("Error creating file");
     f.write_all(ppm.as_bytes()).expect("Error writing data");
 }
+
+// The scene file the distributed example sends to its workers.
+pub fn distributed_scene((width, height): (usize, usize), obj: &str) -> Vec<u8> {
+    format!("# size {} {}\n{}", width, height, obj).into_bytes()
+}
+
+// The scene file is a `# size W H` line followed by the model, which is
+// placed on a floor and framed by the camera.
+pub fn load_distributed_scene(bytes: &[u8]) -> Result<(Camera, World), String> {
+    let text = std::str::from_utf8(bytes).map_err(|_| "Scene is not UTF-8")?;
+    let (header, obj) = text.split_once('\n').ok_or("Empty scene")?;
+    let size: Vec<i32> = header
+        .strip_prefix("# size ")
+        .map(|size| size.split(' ').filter_map(|n| n.parse().ok()).collect())
+        .unwrap_or_default();
+    let [width, height] = size[..] else {
+        return Err(format!("Invalid scene header: {}", header));
+    };
+
+    let mut model = WavefrontObj::from_file_contents(obj)
+        .map_err(|error| error.to_string())?
+        .to_group();
+    let bounds = model.bounding_box();
+    let (min, max) = (bounds.min(), bounds.max());
+    let extent = (max.x - min.x).max(max.y - min.y).max(max.z - min.z);
+    let center = (min + max) * 0.5;
+
+    // Stand the model on the floor at the origin, at most 2 units across
+    let scale = 2. / extent;
+    model.transform =
+        Matrix4::scaling(scale, scale, scale) * Matrix4::translation(-center.x, -min.y, -center.z);
+    let mut material = Material::new();
+    material.color = Color::new(0.8, 0.4, 0.3);
+    model.set_material(material);
+
+    let mut world = World::new();
+    world.add_object(model);
+
+    let mut floor = Object::plane();
+    let mut material = Material::new();
+    material.color = Color::new(0.8, 0.8, 0.7);
+    material.specular = 0.;
+    floor.set_material(material);
+    world.add_object(floor);
+
+    world.add_light(Light::point_light(
+        Tuple::point(-4., 6., -6.),
+        Color::white(),
+    ));
+
+    let mut camera = Camera::new(width, height, PI / 3.);
+    camera.transform = transformations::view_transform(
+        Tuple::point(0., 1.5, -3.5),
+        Tuple::point(0., 0.6, 0.),
+        Tuple::vector(0., 1., 0.),
+    );
+
+    Ok((camera, world))
+}
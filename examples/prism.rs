@@ -0,0 +1,85 @@
+use std::f64::consts::{FRAC_PI_4, PI};
+
+use examples;
+use ray_tracer::{
+    camera::Camera,
+    color::Color,
+    light::Light,
+    material::Material,
+    math::{matrix4::Matrix4, transformations, tuple::Tuple},
+    pattern::Pattern,
+    shape::Object,
+    spectrum::Dispersion,
+    world::World,
+};
+
+fn glass(dispersion: Dispersion) -> Material {
+    let mut material = Material::new();
+    material.color = Color::black();
+    material.ambient = 0.;
+    material.diffuse = 0.1;
+    material.specular = 1.;
+    material.shininess = 300.;
+    material.reflective = 0.9;
+    material.transparency = 0.9;
+    material.dispersion = Some(dispersion);
+    material
+}
+
+// A flint glass prism and a diamond-like ball in front of thin white stripes.
+// The stripes seen through the glass break up into rainbows.
+pub fn scene(width: usize, height: usize) -> (Camera, World) {
+    let mut world = World::new();
+
+    let mut stripes = Pattern::striped(Color::white(), Color::new(0.05, 0.05, 0.05));
+    *stripes.transform_mut() = Matrix4::scaling(0.15, 1., 1.);
+    let mut backdrop = Object::plane();
+    backdrop.transform = Matrix4::translation(0., 0., 4.) * Matrix4::rotation_x(PI / 2.);
+    let mut material = Material::with_pattern(stripes);
+    material.ambient = 0.9;
+    material.diffuse = 0.1;
+    material.specular = 0.;
+    backdrop.set_material(material);
+    world.add_object(backdrop);
+
+    let mut prism = Object::cube();
+    prism.transform = Matrix4::translation(-1.2, 0., 0.)
+        * Matrix4::rotation_y(FRAC_PI_4)
+        * Matrix4::scaling(0.7, 1.6, 0.7);
+    let mut material = glass(Dispersion::flint_glass());
+    material.refractive_index = 1.78;
+    prism.set_material(material);
+    world.add_object(prism);
+
+    let mut ball = Object::sphere();
+    ball.transform = Matrix4::translation(1.2, 0., 0.);
+    let mut material = glass(Dispersion::diamond());
+    material.refractive_index = 2.42;
+    ball.set_material(material);
+    world.add_object(ball);
+
+    world.add_light(Light::point_light(
+        Tuple::point(-5., 5., -8.),
+        Color::white(),
+    ));
+
+    let mut camera = Camera::new(width as i32, height as i32, PI / 3.);
+    camera.transform = transformations::view_transform(
+        Tuple::point(0., 0.5, -5.),
+        Tuple::point(0., 0., 0.),
+        Tuple::vector(0., 1., 0.),
+    );
+    camera.spectral_samples = 16;
+
+    (camera, world)
+}
+
+const ASPECT: f64 = 16. / 9.;
+
+const WIDTH: usize = 400;
+const HEIGHT: usize = (WIDTH as f64 / ASPECT) as usize;
+
+pub fn main() {
+    let (camera, world) = scene(WIDTH, HEIGHT);
+    examples::run_and_save_scene("prism", camera, world);
+}
@@ -0,0 +1,41 @@
+use std::process::Command;
+
+use examples::{distributed_scene, load_distributed_scene};
+
+// A tetrahedron, small enough to render quickly in every worker.
+const MODEL: &str = "\
+v 0 0 0
+v 1 0 0
+v 0 1 0
+v 0 0 1
+f 1 3 2
+f 1 2 4
+f 1 4 3
+f 2 3 4
+";
+
+#[test]
+fn worker_processes_render_the_same_image_as_a_single_process() {
+    let directory =
+        std::env::temp_dir().join(format!("ray_tracer_distributed_{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let model = directory.join("model.obj");
+    let output = directory.join("output.ppm");
+    std::fs::write(&model, MODEL).unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_distributed"))
+        .arg("coordinator")
+        .arg("--scene")
+        .arg(&model)
+        .args(["--local", "3", "--size", "24x16", "--tile-size", "5"])
+        .arg("--output")
+        .arg(&output)
+        .status()
+        .unwrap();
+    let distributed = std::fs::read_to_string(&output).unwrap();
+    let _ = std::fs::remove_dir_all(&directory);
+
+    let (camera, world) = load_distributed_scene(&distributed_scene((24, 16), MODEL)).unwrap();
+    assert!(status.success());
+    assert_eq!(distributed, camera.render(&world).to_ppm());
+}
@@ -0,0 +1,686 @@
+use std::{
+    io::{Error, ErrorKind},
+    path::Path,
+};
+
+use crate::{
+    camera::Camera,
+    color::Color,
+    math::{
+        matrix4::Matrix4,
+        transformations::{view_transform, DecomposedTransform},
+        tuple::Tuple,
+    },
+    progress::ProgressObserver,
+    world::World,
+};
+
+pub use crate::material::MaterialProperty;
+
+// How the value moves between two keyframes. `t` goes from 0 at the first
+// keyframe to 1 at the next one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Easing {
+    Linear,
+    Smoothstep,
+    // Cubic Bezier through (0, 0) and (1, 1) with two control points, as in
+    // CSS `cubic-bezier(x1, y1, x2, y2)`.
+    Bezier { x1: f64, y1: f64, x2: f64, y2: f64 },
+}
+
+impl Easing {
+    pub fn ease_in_out() -> Self {
+        Easing::Bezier {
+            x1: 0.42,
+            y1: 0.,
+            x2: 0.58,
+            y2: 1.,
+        }
+    }
+
+    pub fn apply(self, t: f64) -> f64 {
+        let t = t.clamp(0., 1.);
+
+        match self {
+            Easing::Linear => t,
+            Easing::Smoothstep => t * t * (3. - 2. * t),
+            Easing::Bezier { x1, y1, x2, y2 } => {
+                let s = solve_bezier(x1, x2, t);
+
+                bezier(y1, y2, s)
+            }
+        }
+    }
+}
+
+// One coordinate of a cubic Bezier whose end points are 0 and 1.
+fn bezier(p1: f64, p2: f64, s: f64) -> f64 {
+    let inv = 1. - s;
+
+    3. * inv * inv * s * p1 + 3. * inv * s * s * p2 + s * s * s
+}
+
+// Finds the curve parameter whose x coordinate is `x`. The x coordinate is
+// monotonic for control points in [0, 1], so bisection always converges.
+fn solve_bezier(x1: f64, x2: f64, x: f64) -> f64 {
+    let (mut low, mut high) = (0., 1.);
+
+    for _ in 0..64 {
+        let mid = (low + high) / 2.;
+
+        if bezier(x1, x2, mid) < x {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+
+    (low + high) / 2.
+}
+
+pub trait Interpolate: Copy {
+    fn interpolate(self, other: Self, t: f64) -> Self;
+}
+
+impl Interpolate for f64 {
+    fn interpolate(self, other: Self, t: f64) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Interpolate for Tuple {
+    fn interpolate(self, other: Self, t: f64) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Interpolate for Color {
+    fn interpolate(self, other: Self, t: f64) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Interpolate for Matrix4 {
+    fn interpolate(self, other: Self, t: f64) -> Self {
+        DecomposedTransform::from_matrix(&self)
+            .interpolate(&DecomposedTransform::from_matrix(&other), t)
+            .to_matrix()
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Key<T> {
+    time: f64,
+    value: T,
+    easing: Easing,
+}
+
+// Keyframed values of a single property. The easing of a key shapes the
+// segment that starts at it.
+#[derive(Clone, Debug)]
+pub struct Track<T> {
+    keys: Vec<Key<T>>,
+}
+
+impl<T: Interpolate> Track<T> {
+    pub fn new() -> Self {
+        Self { keys: vec![] }
+    }
+
+    pub fn constant(value: T) -> Self {
+        Self::new().key(0., value, Easing::Linear)
+    }
+
+    pub fn key(mut self, time: f64, value: T, easing: Easing) -> Self {
+        let index = self.keys.partition_point(|key| key.time <= time);
+        self.keys.insert(
+            index,
+            Key {
+                time,
+                value,
+                easing,
+            },
+        );
+
+        self
+    }
+
+    // Times outside the keys hold the first or last value.
+    pub fn value_at(&self, time: f64) -> Option<T> {
+        let first = self.keys.first()?;
+        let last = self.keys.last()?;
+
+        if time <= first.time {
+            return Some(first.value);
+        }
+        if time >= last.time {
+            return Some(last.value);
+        }
+
+        let next_index = self.keys.iter().position(|key| key.time > time)?;
+        let from = &self.keys[next_index - 1];
+        let to = &self.keys[next_index];
+        let t = from
+            .easing
+            .apply((time - from.time) / (to.time - from.time));
+
+        Some(from.value.interpolate(to.value, t))
+    }
+}
+
+impl<T: Interpolate> Default for Track<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Debug)]
+struct CameraTracks {
+    from: Track<Tuple>,
+    to: Track<Tuple>,
+    up: Tuple,
+}
+
+// Tracks applied to a camera and world before rendering each frame. Objects
+// and lights are referred to by the index `World::add_object` and
+// `World::add_light` gave them.
+#[derive(Clone, Debug, Default)]
+pub struct Animation {
+    camera: Option<CameraTracks>,
+    light_intensities: Vec<(usize, Track<Color>)>,
+    material_colors: Vec<(usize, Track<Color>)>,
+    material_values: Vec<(usize, MaterialProperty, Track<f64>)>,
+    transforms: Vec<(usize, Track<Matrix4>)>,
+}
+
+impl Animation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn camera(&mut self, from: Track<Tuple>, to: Track<Tuple>, up: Tuple) {
+        self.camera = Some(CameraTracks { from, to, up });
+    }
+
+    pub fn light_intensity(&mut self, light: usize, track: Track<Color>) {
+        self.light_intensities.push((light, track));
+    }
+
+    pub fn material_color(&mut self, object: usize, track: Track<Color>) {
+        self.material_colors.push((object, track));
+    }
+
+    pub fn material(&mut self, object: usize, property: MaterialProperty, track: Track<f64>) {
+        self.material_values.push((object, property, track));
+    }
+
+    pub fn transform(&mut self, object: usize, track: Track<Matrix4>) {
+        self.transforms.push((object, track));
+    }
+
+    // Makes sure every track refers to an object or light in `world`.
+    pub fn check(&self, world: &World) -> Result<(), String> {
+        let mut lights = self.light_intensities.iter().map(|(light, _)| *light);
+        if let Some(light) = lights.find(|light| *light >= world.lights().len()) {
+            return Err(format!(
+                "Animation refers to light {} but the world has {} lights",
+                light,
+                world.lights().len()
+            ));
+        }
+
+        let mut objects = self
+            .material_colors
+            .iter()
+            .map(|(object, _)| *object)
+            .chain(self.material_values.iter().map(|(object, _, _)| *object))
+            .chain(self.transforms.iter().map(|(object, _)| *object));
+        if let Some(object) = objects.find(|object| *object >= world.objects.len()) {
+            return Err(format!(
+                "Animation refers to object {} but the world has {} objects",
+                object,
+                world.objects.len()
+            ));
+        }
+
+        Ok(())
+    }
+
+    // Panics if a track refers to something `check` would reject.
+    pub fn apply(&self, time: f64, camera: &mut Camera, world: &mut World) {
+        if let Some(CameraTracks { from, to, up }) = &self.camera {
+            if let (Some(from), Some(to)) = (from.value_at(time), to.value_at(time)) {
+                camera.transform = view_transform(from, to, *up);
+            }
+        }
+
+        for (light, track) in &self.light_intensities {
+            if let Some(intensity) = track.value_at(time) {
+                world.lights_mut()[*light].intensity = intensity;
+            }
+        }
+
+        for (object, track) in &self.material_colors {
+            if let Some(color) = track.value_at(time) {
+                world.objects[*object].update_material(&|material| material.color = color);
+            }
+        }
+
+        for (object, property, track) in &self.material_values {
+            if let Some(value) = track.value_at(time) {
+                world.objects[*object].update_material(&|material| property.set(material, value));
+            }
+        }
+
+        for (object, track) in &self.transforms {
+            if let Some(transform) = track.value_at(time) {
+                world.objects[*object].transform = transform;
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SequenceSettings {
+    pub first_frame: usize,
+    pub last_frame: usize,
+    pub frame_rate: f64,
+    // Path of each frame. The last run of `#` is replaced by the zero padded
+    // frame number, so `frame_####.ppm` gives `frame_0001.ppm`.
+    pub output_pattern: String,
+}
+
+impl Default for SequenceSettings {
+    fn default() -> Self {
+        Self {
+            first_frame: 1,
+            last_frame: 24,
+            frame_rate: 24.,
+            output_pattern: "./output/frame_####.ppm".to_owned(),
+        }
+    }
+}
+
+impl SequenceSettings {
+    // Reads `--frames FIRST-LAST`, `--fps RATE`, `--output PATTERN` and
+    // `--scene FILE` on top of the defaults, later arguments winning over
+    // earlier ones. Unknown arguments are an error.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut settings = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("Missing value for {}", arg));
+
+            match arg.as_str() {
+                "--frames" => settings.set_frames(&value()?)?,
+                "--fps" => settings.frame_rate = parse(&value()?)?,
+                "--output" => settings.output_pattern = value()?,
+                "--scene" => {
+                    let path = value()?;
+                    let scene = std::fs::read_to_string(&path)
+                        .map_err(|error| format!("Error reading {}: {}", path, error))?;
+
+                    settings.read_scene(&scene)?;
+                }
+                _ => return Err(format!("Unknown argument: {}", arg)),
+            }
+        }
+
+        settings.validated()
+    }
+
+    // Reads `# frames FIRST-LAST`, `# fps RATE` and `# output PATTERN` lines
+    // of a scene file, such as the header of an OBJ file, on top of the
+    // defaults. Other lines are left to the scene loader.
+    pub fn from_scene(scene: &str) -> Result<Self, String> {
+        let mut settings = Self::default();
+        settings.read_scene(scene)?;
+
+        settings.validated()
+    }
+
+    fn read_scene(&mut self, scene: &str) -> Result<(), String> {
+        for line in scene.lines() {
+            let Some(setting) = line.strip_prefix('#') else {
+                continue;
+            };
+
+            match setting.trim().split_once(' ') {
+                Some(("frames", range)) => self.set_frames(range)?,
+                Some(("fps", rate)) => self.frame_rate = parse(rate)?,
+                Some(("output", pattern)) => self.output_pattern = pattern.trim().to_owned(),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn set_frames(&mut self, range: &str) -> Result<(), String> {
+        let (first, last) = range.split_once('-').unwrap_or((range, range));
+        self.first_frame = parse(first)?;
+        self.last_frame = parse(last)?;
+
+        Ok(())
+    }
+
+    fn validated(self) -> Result<Self, String> {
+        if self.first_frame > self.last_frame {
+            return Err(format!(
+                "First frame {} is after last frame {}",
+                self.first_frame, self.last_frame
+            ));
+        }
+        if self.frame_rate <= 0. {
+            return Err("Frame rate must be positive".to_owned());
+        }
+
+        Ok(self)
+    }
+
+    pub fn frame_time(&self, frame: usize) -> f64 {
+        frame as f64 / self.frame_rate
+    }
+
+    pub fn frame_path(&self, frame: usize) -> String {
+        let pattern = &self.output_pattern;
+
+        match pattern.rfind('#') {
+            Some(end) => {
+                let start = pattern[..end].trim_end_matches('#').len();
+                let width = end + 1 - start;
+
+                format!(
+                    "{}{:0width$}{}",
+                    &pattern[..start],
+                    frame,
+                    &pattern[end + 1..],
+                    width = width
+                )
+            }
+            None => pattern.clone(),
+        }
+    }
+}
+
+fn parse<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid value: {}", value))
+}
+
+// Renders every frame in the settings' range into its own PPM file,
+// telling `observer` about the rows of each frame in turn. Cancelling
+// returns an `Interrupted` error and keeps the frames already written.
+pub fn render_sequence(
+    animation: &Animation,
+    mut camera: Camera,
+    world: &mut World,
+    settings: &SequenceSettings,
+    observer: &mut dyn ProgressObserver,
+) -> std::io::Result<()> {
+    animation
+        .check(world)
+        .map_err(|message| Error::new(ErrorKind::InvalidInput, message))?;
+
+    for frame in settings.first_frame..=settings.last_frame {
+        let path = settings.frame_path(frame);
+
+        animation.apply(settings.frame_time(frame), &mut camera, world);
+        let ppm = camera
+            .render_with(world, observer)
+            .map_err(|_| Error::new(ErrorKind::Interrupted, "Render cancelled"))?
+            .to_ppm();
+
+        if let Some(parent) = Path::new(&path).parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, ppm)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        light::Light,
+        misc::approx_equal,
+        progress::{CancelToken, Progress, Silent},
+        shape::{Object, ShapeOrGroup},
+    };
+
+    #[test]
+    fn easings_keep_their_end_points() {
+        let easings = [Easing::Linear, Easing::Smoothstep, Easing::ease_in_out()];
+
+        for easing in easings {
+            assert!(approx_equal(easing.apply(0.), 0.));
+            assert!(approx_equal(easing.apply(1.), 1.));
+            assert!(approx_equal(easing.apply(0.5), 0.5));
+        }
+    }
+
+    #[test]
+    fn easing_in_and_out_starts_slowly() {
+        assert!(approx_equal(Easing::Smoothstep.apply(0.25), 0.15625));
+        assert!(Easing::ease_in_out().apply(0.1) < 0.1);
+        assert!(Easing::ease_in_out().apply(0.9) > 0.9);
+    }
+
+    #[test]
+    fn a_linear_bezier_is_the_identity() {
+        let easing = Easing::Bezier {
+            x1: 1. / 3.,
+            y1: 1. / 3.,
+            x2: 2. / 3.,
+            y2: 2. / 3.,
+        };
+
+        assert!(approx_equal(easing.apply(0.3), 0.3));
+        assert!(approx_equal(easing.apply(0.8), 0.8));
+    }
+
+    #[test]
+    fn a_track_interpolates_between_its_keys() {
+        let track = Track::new()
+            .key(2., 10., Easing::Linear)
+            .key(0., 0., Easing::Smoothstep)
+            .key(1., 4., Easing::Linear);
+
+        assert_eq!(track.value_at(-1.), Some(0.));
+        assert_eq!(track.value_at(0.5), Some(2.));
+        assert_eq!(track.value_at(1.5), Some(7.));
+        assert_eq!(track.value_at(3.), Some(10.));
+        assert_eq!(Track::<f64>::new().value_at(0.), None);
+    }
+
+    #[test]
+    fn applying_an_animation_updates_the_scene() {
+        let mut world = World::new();
+        let sphere = world.add_object(Object::sphere());
+        let light = world.add_light(Light::point_light(
+            Tuple::point(0., 10., 0.),
+            Color::white(),
+        ));
+        let mut camera = Camera::new(10, 10, 1.);
+
+        let mut animation = Animation::new();
+        animation.camera(
+            Track::constant(Tuple::point(0., 0., -5.)),
+            Track::constant(Tuple::point(0., 0., 0.)),
+            Tuple::vector(0., 1., 0.),
+        );
+        animation.light_intensity(
+            light,
+            Track::new().key(0., Color::black(), Easing::Linear).key(
+                2.,
+                Color::white(),
+                Easing::Linear,
+            ),
+        );
+        animation.material(
+            sphere,
+            MaterialProperty::Reflective,
+            Track::new()
+                .key(0., 0., Easing::Linear)
+                .key(1., 0.8, Easing::Linear),
+        );
+        animation.transform(
+            sphere,
+            Track::new()
+                .key(0., Matrix4::identity(), Easing::Linear)
+                .key(1., Matrix4::translation(2., 0., 0.), Easing::Linear),
+        );
+
+        animation.apply(0.5, &mut camera, &mut world);
+
+        assert_eq!(
+            camera.transform,
+            view_transform(
+                Tuple::point(0., 0., -5.),
+                Tuple::point(0., 0., 0.),
+                Tuple::vector(0., 1., 0.)
+            )
+        );
+        assert_eq!(
+            world.lights()[light].intensity,
+            Color::new(0.25, 0.25, 0.25)
+        );
+        assert_eq!(
+            world.objects[sphere].transform,
+            Matrix4::translation(1., 0., 0.)
+        );
+
+        match &world.objects[sphere].shape {
+            ShapeOrGroup::Shape { material, .. } => {
+                assert!(approx_equal(material.reflective, 0.4))
+            }
+            ShapeOrGroup::Group(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn frame_paths_are_zero_padded() {
+        let settings = SequenceSettings::default();
+
+        assert_eq!(settings.frame_path(1), "./output/frame_0001.ppm");
+        assert_eq!(settings.frame_path(12345), "./output/frame_12345.ppm");
+
+        let settings = SequenceSettings {
+            output_pattern: "renders/##/shot.ppm".to_owned(),
+            ..SequenceSettings::default()
+        };
+        assert_eq!(settings.frame_path(7), "renders/07/shot.ppm");
+    }
+
+    #[test]
+    fn reading_sequence_settings_from_arguments() {
+        let args = [
+            "--frames",
+            "10-20",
+            "--fps",
+            "30",
+            "--output",
+            "out/f_###.ppm",
+        ];
+        let settings = SequenceSettings::from_args(args.map(String::from)).unwrap();
+
+        assert_eq!(settings.first_frame, 10);
+        assert_eq!(settings.last_frame, 20);
+        assert_eq!(settings.frame_rate, 30.);
+        assert_eq!(settings.frame_path(10), "out/f_010.ppm");
+        assert!(approx_equal(settings.frame_time(15), 0.5));
+    }
+
+    #[test]
+    fn invalid_sequence_arguments_are_rejected() {
+        let parse = |args: &[&str]| SequenceSettings::from_args(args.iter().map(|a| a.to_string()));
+
+        assert!(parse(&["--frames", "20-10"]).is_err());
+        assert!(parse(&["--fps", "0"]).is_err());
+        assert!(parse(&["--fps"]).is_err());
+        assert!(parse(&["--frobnicate"]).is_err());
+    }
+
+    #[test]
+    fn reading_sequence_settings_from_a_scene_file() {
+        let scene = "# frames 5-8\n# fps 12\n# output out/walk ####.ppm\nv 0 0 0\n";
+
+        let settings = SequenceSettings::from_scene(scene).unwrap();
+
+        assert_eq!(settings.first_frame, 5);
+        assert_eq!(settings.last_frame, 8);
+        assert_eq!(settings.frame_rate, 12.);
+        assert_eq!(settings.frame_path(6), "out/walk 0006.ppm");
+        assert!(SequenceSettings::from_scene("# fps -1\n").is_err());
+    }
+
+    #[test]
+    fn cancelling_a_sequence_keeps_the_frames_already_written() {
+        let directory =
+            std::env::temp_dir().join(format!("ray_tracer_sequence_{}", std::process::id()));
+        let settings = SequenceSettings {
+            first_frame: 1,
+            last_frame: 3,
+            output_pattern: directory.join("frame_#.ppm").to_string_lossy().into_owned(),
+            ..SequenceSettings::default()
+        };
+        let token = CancelToken::new();
+        let cancel = token.clone();
+        let mut observer = (move |progress: Progress| {
+            if progress.done == progress.total {
+                cancel.cancel()
+            }
+        })
+        .with_cancel(token);
+
+        let error = render_sequence(
+            &Animation::new(),
+            Camera::new(2, 2, 1.),
+            &mut World::new(),
+            &settings,
+            &mut observer,
+        )
+        .unwrap_err();
+
+        assert_eq!(error.kind(), ErrorKind::Interrupted);
+        assert!(Path::new(&settings.frame_path(1)).exists());
+        assert!(!Path::new(&settings.frame_path(2)).exists());
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn tracks_for_missing_objects_are_rejected_before_rendering() {
+        let mut world = World::new();
+        let sphere = world.add_object(Object::sphere());
+        let light = world.add_light(Light::point_light(
+            Tuple::point(0., 10., 0.),
+            Color::white(),
+        ));
+        let mut animation = Animation::new();
+        animation.transform(sphere, Track::constant(Matrix4::identity()));
+        animation.light_intensity(light, Track::constant(Color::white()));
+        assert!(animation.check(&world).is_ok());
+
+        animation.material_color(sphere + 1, Track::constant(Color::black()));
+        let settings = SequenceSettings {
+            output_pattern: "no_such_directory/frame_#.ppm".to_owned(),
+            ..SequenceSettings::default()
+        };
+        let error = render_sequence(
+            &animation,
+            Camera::new(2, 2, 1.),
+            &mut world,
+            &settings,
+            &mut Silent,
+        )
+        .unwrap_err();
+
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert!(!Path::new("no_such_directory").exists());
+    }
+}
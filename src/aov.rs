@@ -0,0 +1,403 @@
+use std::path::Path;
+
+use crate::{
+    canvas::Canvas, color::Color, material::Material, math::tuple::Tuple, sampling::Rng,
+    tone::ToneMapping,
+};
+
+// Keeps ID colors apart from the other uses of the RNG.
+const ID_SEED: u64 = 0x1d5;
+
+// Extra per-pixel buffers rendered alongside the image. Pixels whose rays
+// hit nothing are black in every pass.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aov {
+    // Distance from the camera to the first hit, in world units and the
+    // same in every channel.
+    Depth,
+    // World space shading normal, mapped from [-1, 1] to [0, 1] like a
+    // normal map.
+    Normal,
+    // Surface color before lighting.
+    Albedo,
+    // A flat color per top-level object in the world.
+    ObjectId,
+    // A flat color per distinct material.
+    MaterialId,
+    // Light arriving straight from the lights, ambient included, and the
+    // light fog scatters towards the camera.
+    Direct,
+    // Reflection and refraction together.
+    Indirect,
+    Reflection,
+    Refraction,
+    // Share of the light that reaches the surface, white where nothing
+    // casts a shadow on it.
+    Shadow,
+    // Share of the sky left open above the surface, estimated with the
+    // world's `ambient_occlusion` settings or the defaults if it has none.
+    AmbientOcclusion,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 11] = [
+        Aov::Depth,
+        Aov::Normal,
+        Aov::Albedo,
+        Aov::ObjectId,
+        Aov::MaterialId,
+        Aov::Direct,
+        Aov::Indirect,
+        Aov::Reflection,
+        Aov::Refraction,
+        Aov::Shadow,
+        Aov::AmbientOcclusion,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+            Aov::Direct => "direct",
+            Aov::Indirect => "indirect",
+            Aov::Reflection => "reflection",
+            Aov::Refraction => "refraction",
+            Aov::Shadow => "shadow",
+            Aov::AmbientOcclusion => "ambient_occlusion",
+        }
+    }
+}
+
+// What a single ray sees, split up for the passes. Everything but `color`
+// describes the first surface hit and is empty for rays that miss.
+#[derive(Clone, Copy, Debug)]
+pub struct AovSample<'a> {
+    pub color: Color,
+    pub depth: Option<f64>,
+    pub normal: Option<Tuple>,
+    pub albedo: Color,
+    // Index of the hit object in `World::objects`.
+    pub object: Option<usize>,
+    pub material: Option<&'a Material>,
+    pub direct: Color,
+    pub reflection: Color,
+    pub refraction: Color,
+    pub shadow: Color,
+    // Only estimated when the AO pass is rendered.
+    pub occlusion: Option<f64>,
+}
+
+impl<'a> AovSample<'a> {
+    pub(crate) fn miss() -> Self {
+        Self {
+            color: Color::black(),
+            depth: None,
+            normal: None,
+            albedo: Color::black(),
+            object: None,
+            material: None,
+            direct: Color::black(),
+            reflection: Color::black(),
+            refraction: Color::black(),
+            shadow: Color::black(),
+            occlusion: None,
+        }
+    }
+
+    // The sample's value in a pass, with material IDs being positions in
+    // `materials` from `World::materials`.
+    pub fn value(&self, aov: Aov, materials: &[&Material]) -> Color {
+        match aov {
+            Aov::Depth => self.depth.map_or(Color::black(), |t| Color::new(t, t, t)),
+            Aov::Normal => self.normal.map_or(Color::black(), |n| {
+                Color::new((n.x + 1.) / 2., (n.y + 1.) / 2., (n.z + 1.) / 2.)
+            }),
+            Aov::Albedo => self.albedo,
+            Aov::ObjectId => id_color(self.object),
+            Aov::MaterialId => id_color(
+                self.material
+                    .and_then(|material| materials.iter().position(|m| *m == material)),
+            ),
+            Aov::Direct => self.direct,
+            Aov::Indirect => self.reflection + self.refraction,
+            Aov::Reflection => self.reflection,
+            Aov::Refraction => self.refraction,
+            Aov::Shadow => {
+                if self.depth.is_some() {
+                    self.shadow
+                } else {
+                    Color::black()
+                }
+            }
+            Aov::AmbientOcclusion => self
+                .occlusion
+                .map_or(Color::black(), |open| Color::new(open, open, open)),
+        }
+    }
+}
+
+// A bright color that only depends on the ID.
+pub fn id_color(id: Option<usize>) -> Color {
+    match id {
+        Some(id) => {
+            let mut rng = Rng::from_seeds(&[id as u64, ID_SEED]);
+
+            Color::new(
+                0.2 + 0.8 * rng.next_f64(),
+                0.2 + 0.8 * rng.next_f64(),
+                0.2 + 0.8 * rng.next_f64(),
+            )
+        }
+        None => Color::black(),
+    }
+}
+
+// The image and the passes rendered with it.
+#[derive(Clone, Debug)]
+pub struct RenderPasses {
+    pub beauty: Canvas,
+    pub passes: Vec<(Aov, Canvas)>,
+}
+
+impl RenderPasses {
+    pub fn pass(&self, aov: Aov) -> Option<&Canvas> {
+        self.passes
+            .iter()
+            .find(|(pass, _)| *pass == aov)
+            .map(|(_, canvas)| canvas)
+    }
+
+    // Writes `<stem>.ppm` and a `<stem>_<pass>.ppm` file for each pass into
+    // `directory`. Data passes are written without sRGB encoding, and depth
+    // goes to a float map since it isn't limited to [0, 1].
+    pub fn save(&self, directory: impl AsRef<Path>, stem: &str) -> std::io::Result<()> {
+        let directory = directory.as_ref();
+        std::fs::create_dir_all(directory)?;
+
+        std::fs::write(
+            directory.join(format!("{}.ppm", stem)),
+            self.beauty.to_ppm(),
+        )?;
+        for (aov, canvas) in &self.passes {
+            let path = directory.join(format!("{}_{}", stem, aov.name()));
+
+            match aov {
+                Aov::Depth => std::fs::write(path.with_extension("pfm"), canvas.to_pfm())?,
+                Aov::Normal
+                | Aov::ObjectId
+                | Aov::MaterialId
+                | Aov::Shadow
+                | Aov::AmbientOcclusion => std::fs::write(
+                    path.with_extension("ppm"),
+                    canvas.to_ppm_with(&ToneMapping::linear()),
+                )?,
+                _ => std::fs::write(path.with_extension("ppm"), canvas.to_ppm())?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        camera::Camera,
+        light::Light,
+        math::matrix4::Matrix4,
+        math::transformations::view_transform,
+        medium::{Fog, Medium},
+        occlusion::AmbientOcclusion,
+        shape::Object,
+        world::World,
+    };
+
+    // Two spheres side by side under a light, seen from 5 units away by a
+    // 2x1 orthographic camera so each pixel sees one sphere.
+    fn two_spheres() -> (Camera, World) {
+        let mut world = World::new();
+        world.add_light(Light::point_light(
+            Tuple::point(0., 0., 10.),
+            Color::white(),
+        ));
+        for (x, reflective) in [(-1., 0.), (1., 0.5)] {
+            let mut sphere = Object::sphere();
+            sphere.transform = Matrix4::translation(x, 0., 0.);
+            let mut material = Material::new();
+            material.color = Color::new(1., 1., (x + 1.) / 2.);
+            material.reflective = reflective;
+            sphere.set_material(material);
+            world.add_object(sphere);
+        }
+
+        let mut camera = Camera::orthographic(2, 1, 4.);
+        camera.transform = view_transform(
+            Tuple::point(0., 0., 5.),
+            Tuple::point(0., 0., 0.),
+            Tuple::vector(0., 1., 0.),
+        );
+
+        (camera, world)
+    }
+
+    #[test]
+    fn only_the_requested_passes_are_rendered() {
+        let (camera, world) = two_spheres();
+
+        let passes = camera.render_aovs(&world, &[Aov::Depth]);
+
+        assert!(passes.pass(Aov::Depth).is_some());
+        assert!(passes.pass(Aov::Normal).is_none());
+        assert_eq!(
+            passes.beauty.pixel_at(0, 0),
+            camera.pixel_color(&world, 0, 0)
+        );
+    }
+
+    #[test]
+    fn depth_and_normal_passes_describe_the_first_hit() {
+        let (camera, world) = two_spheres();
+
+        let passes = camera.render_aovs(&world, &[Aov::Depth, Aov::Normal]);
+
+        assert_eq!(
+            passes.pass(Aov::Depth).unwrap().pixel_at(0, 0),
+            Color::new(4., 4., 4.)
+        );
+        assert_eq!(
+            passes.pass(Aov::Normal).unwrap().pixel_at(0, 0),
+            Color::new(0.5, 0.5, 1.)
+        );
+    }
+
+    #[test]
+    fn objects_and_materials_get_their_own_ids() {
+        let (camera, world) = two_spheres();
+
+        let passes = camera.render_aovs(&world, &[Aov::ObjectId, Aov::MaterialId]);
+        let objects = passes.pass(Aov::ObjectId).unwrap();
+        let materials = passes.pass(Aov::MaterialId).unwrap();
+
+        // Looking down -z, the sphere at x = 1 is on the left
+        assert_eq!(objects.pixel_at(0, 0), id_color(Some(1)));
+        assert_eq!(objects.pixel_at(1, 0), id_color(Some(0)));
+        assert_eq!(materials.pixel_at(0, 0), id_color(Some(1)));
+        assert_eq!(materials.pixel_at(1, 0), id_color(Some(0)));
+    }
+
+    #[test]
+    fn materials_that_only_differ_in_reflectivity_get_their_own_ids() {
+        let (camera, mut world) = two_spheres();
+        let plain = Material::new();
+        let mut mirror = plain.clone();
+        mirror.reflective = 0.5;
+        world.objects[0].set_material(plain);
+        world.objects[1].set_material(mirror);
+
+        let passes = camera.render_aovs(&world, &[Aov::MaterialId]);
+        let materials = passes.pass(Aov::MaterialId).unwrap();
+
+        assert_eq!(materials.pixel_at(0, 0), id_color(Some(1)));
+        assert_eq!(materials.pixel_at(1, 0), id_color(Some(0)));
+    }
+
+    #[test]
+    fn shapes_sharing_a_material_get_the_same_id() {
+        let (camera, mut world) = two_spheres();
+        let mut group = Object::group(std::mem::take(&mut world.objects));
+        group.set_material(Material::new());
+        world.add_object(group);
+
+        let passes = camera.render_aovs(&world, &[Aov::MaterialId]);
+        let materials = passes.pass(Aov::MaterialId).unwrap();
+
+        assert_eq!(materials.pixel_at(0, 0), id_color(Some(0)));
+        assert_eq!(materials.pixel_at(1, 0), id_color(Some(0)));
+    }
+
+    #[test]
+    fn direct_and_indirect_add_up_to_the_image() {
+        let (camera, world) = two_spheres();
+
+        let passes = camera.render_aovs(&world, &[Aov::Direct, Aov::Indirect, Aov::Shadow]);
+
+        for x in 0..2 {
+            assert_eq!(
+                passes.beauty.pixel_at(x, 0),
+                passes.pass(Aov::Direct).unwrap().pixel_at(x, 0)
+                    + passes.pass(Aov::Indirect).unwrap().pixel_at(x, 0)
+            );
+            assert_eq!(
+                passes.pass(Aov::Shadow).unwrap().pixel_at(x, 0),
+                Color::white()
+            );
+        }
+    }
+
+    #[test]
+    fn the_ambient_occlusion_pass_uses_the_world_settings() {
+        let (mut camera, mut world) = two_spheres();
+        let mut floor = Object::plane();
+        floor.transform = Matrix4::translation(0., -1., 0.);
+        world.add_object(floor);
+        // Low on the spheres, where the floor hides part of the sky
+        camera.transform = camera.transform * Matrix4::translation(0., 0.7, 0.);
+
+        let passes = camera.render_aovs(&world, &[Aov::AmbientOcclusion]);
+        let occlusion = camera.render_ambient_occlusion(&world, AmbientOcclusion::default());
+        for x in 0..2 {
+            assert_eq!(
+                passes.pass(Aov::AmbientOcclusion).unwrap().pixel_at(x, 0),
+                occlusion.pixel_at(x, 0)
+            );
+        }
+
+        let settings = AmbientOcclusion::new(32, 3.);
+        world.ambient_occlusion = Some(settings);
+        let passes = camera.render_aovs(&world, &[Aov::AmbientOcclusion]);
+        let occlusion = camera.render_ambient_occlusion(&world, settings);
+        for x in 0..2 {
+            assert_eq!(
+                passes.pass(Aov::AmbientOcclusion).unwrap().pixel_at(x, 0),
+                occlusion.pixel_at(x, 0)
+            );
+        }
+    }
+
+    #[test]
+    fn the_parts_still_add_up_under_fog_and_at_single_wavelengths() {
+        let (mut camera, mut world) = two_spheres();
+        world.fog = Some(Fog::new(Medium::new(0.1, 0.1), f64::INFINITY));
+        camera.spectral_samples = 4;
+
+        let passes = camera.render_aovs(&world, &[Aov::Direct, Aov::Indirect]);
+
+        for x in 0..2 {
+            assert_eq!(
+                passes.beauty.pixel_at(x, 0),
+                camera.pixel_color(&world, x, 0)
+            );
+            assert_eq!(
+                passes.beauty.pixel_at(x, 0),
+                passes.pass(Aov::Direct).unwrap().pixel_at(x, 0)
+                    + passes.pass(Aov::Indirect).unwrap().pixel_at(x, 0)
+            );
+        }
+    }
+
+    #[test]
+    fn rays_that_miss_leave_the_passes_black() {
+        let (mut camera, world) = two_spheres();
+        camera.transform = Matrix4::translation(0., 10., 0.) * camera.transform;
+
+        let passes = camera.render_aovs(&world, &Aov::ALL);
+
+        for (_, canvas) in &passes.passes {
+            assert_eq!(canvas.pixel_at(0, 0), Color::black());
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use std::sync::Arc;
+
+use crate::{canvas::Canvas, color::Color, math::tuple::Tuple, misc::EPSILON, noise::Noise};
+
+// Surface parametrisation at a hit, in object space. `dpdu` and `dpdv` point
+// the way the surface moves as u and v grow.
+#[derive(Clone, Copy, Debug)]
+pub struct SurfaceFrame {
+    pub uv: (f64, f64),
+    pub dpdu: Tuple,
+    pub dpdv: Tuple,
+}
+
+// Tilts the shading normal without moving the surface.
+#[derive(Clone, Debug)]
+pub enum Bump {
+    // Bumps follow the height `noise.fbm(point * scale) / scale`, so `scale`
+    // changes their size but not their slope, and get steeper as `strength`
+    // grows. Works on every shape since it only needs the object space
+    // point.
+    Noise {
+        noise: Noise,
+        scale: f64,
+        strength: f64,
+    },
+    // Tangent space normal map: red, green and blue encode the normal along
+    // u, v and the surface normal. Needs a shape with UV coordinates
+    // (spheres, planes and triangles); other shapes keep their normal.
+    NormalMap {
+        map: Arc<Canvas>,
+        strength: f64,
+    },
+}
+
+// Materials sharing one map check its address before walking the pixels.
+impl PartialEq for Bump {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (
+                Bump::Noise {
+                    noise,
+                    scale,
+                    strength,
+                },
+                Bump::Noise {
+                    noise: other_noise,
+                    scale: other_scale,
+                    strength: other_strength,
+                },
+            ) => noise == other_noise && scale == other_scale && strength == other_strength,
+            (
+                Bump::NormalMap { map, strength },
+                Bump::NormalMap {
+                    map: other_map,
+                    strength: other_strength,
+                },
+            ) => (Arc::ptr_eq(map, other_map) || map == other_map) && strength == other_strength,
+            _ => false,
+        }
+    }
+}
+
+impl Bump {
+    pub fn noise(noise: Noise, scale: f64, strength: f64) -> Self {
+        Bump::Noise {
+            noise,
+            scale,
+            strength,
+        }
+    }
+
+    pub fn normal_map(map: Canvas) -> Self {
+        Bump::NormalMap {
+            map: Arc::new(map),
+            strength: 1.,
+        }
+    }
+
+    // Both normals are in object space.
+    pub(crate) fn perturb(
+        &self,
+        point: Tuple,
+        normal: Tuple,
+        frame: Option<SurfaceFrame>,
+    ) -> Tuple {
+        let normal = normal.normalize();
+
+        match self {
+            Bump::Noise {
+                noise,
+                scale,
+                strength,
+            } => {
+                let gradient = height_gradient(|p| noise.fbm(p * *scale) / *scale, point);
+                let along_surface = gradient - normal * gradient.dot(normal);
+
+                (normal - along_surface * *strength).normalize()
+            }
+            Bump::NormalMap { map, strength } => {
+                let Some(SurfaceFrame {
+                    uv: (u, v),
+                    dpdu,
+                    dpdv,
+                }) = frame
+                else {
+                    return normal;
+                };
+
+                let (tangent, bitangent) = tangent_frame(normal, dpdu, dpdv);
+                let Color { red, green, blue } = sample(map, u, v);
+
+                (tangent * ((2. * red - 1.) * strength)
+                    + bitangent * ((2. * green - 1.) * strength)
+                    + normal * (2. * blue - 1.))
+                    .normalize()
+            }
+        }
+    }
+}
+
+// Unit tangent and bitangent perpendicular to `normal`, following the UV
+// directions where the surface provides them.
+fn tangent_frame(normal: Tuple, dpdu: Tuple, dpdv: Tuple) -> (Tuple, Tuple) {
+    let mut tangent = dpdu - normal * dpdu.dot(normal);
+    if tangent.magnitude() < EPSILON {
+        // Degenerate parametrisation, e.g. at the poles of a sphere
+        let axis = if normal.x.abs() < 0.9 {
+            Tuple::vector(1., 0., 0.)
+        } else {
+            Tuple::vector(0., 1., 0.)
+        };
+        tangent = axis - normal * axis.dot(normal);
+    }
+    let tangent = tangent.normalize();
+
+    let bitangent = normal.cross(tangent);
+    if bitangent.dot(dpdv) < 0. {
+        (tangent, -bitangent)
+    } else {
+        (tangent, bitangent)
+    }
+}
+
+fn height_gradient(height: impl Fn(Tuple) -> f64, point: Tuple) -> Tuple {
+    const DELTA: f64 = 1e-4;
+    let slope = |offset: Tuple| (height(point + offset) - height(point - offset)) / (2. * DELTA);
+
+    Tuple::vector(
+        slope(Tuple::vector(DELTA, 0., 0.)),
+        slope(Tuple::vector(0., DELTA, 0.)),
+        slope(Tuple::vector(0., 0., DELTA)),
+    )
+}
+
+// Bilinear lookup with the texture repeating in both directions. v grows
+// upwards while canvas rows grow downwards.
+fn sample(map: &Canvas, u: f64, v: f64) -> Color {
+    let x = u.rem_euclid(1.) * map.width() as f64 - 0.5;
+    let y = (1. - v.rem_euclid(1.)) * map.height() as f64 - 0.5;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+
+    let texel = |dx: i32, dy: i32| {
+        let px = (x0 as i32 + dx).rem_euclid(map.width() as i32);
+        let py = (y0 as i32 + dy).rem_euclid(map.height() as i32);
+
+        map.pixel_at(px, py)
+    };
+
+    let top = texel(0, 0) * (1. - fx) + texel(1, 0) * fx;
+    let bottom = texel(0, 1) * (1. - fx) + texel(1, 1) * fx;
+
+    top * (1. - fy) + bottom * fy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        intersection::Intersection, material::Material, math::matrix4::Matrix4, ray::Ray,
+        shape::Object,
+    };
+
+    // A 1x1 map encoding the tangent space normal (x, y, z).
+    fn flat_map(x: f64, y: f64, z: f64) -> Canvas {
+        let ppm = format!(
+            "P3\n1 1\n255\n{} {} {}\n",
+            ((x + 1.) / 2. * 255.).round(),
+            ((y + 1.) / 2. * 255.).round(),
+            ((z + 1.) / 2. * 255.).round()
+        );
+
+        Canvas::from_ppm(ppm.as_bytes()).unwrap()
+    }
+
+    fn shading_normal(object: &Object, ray: Ray) -> Tuple {
+        let xs = object.intersect(ray);
+        let hit = Intersection::hit(&xs).unwrap();
+
+        hit.prepare_computations(ray, &xs).normal_vector
+    }
+
+    #[test]
+    fn a_flat_normal_map_keeps_the_normal() {
+        let mut plane = Object::plane();
+        let mut material = Material::new();
+        material.bump = Some(Bump::normal_map(flat_map(0., 0., 1.)));
+        plane.set_material(material);
+
+        let ray = Ray::new(Tuple::point(0.3, 1., 0.6), Tuple::vector(0., -1., 0.));
+
+        let normal = shading_normal(&plane, ray);
+
+        // 8 bit colors cannot encode 0 exactly
+        assert!((normal - Tuple::vector(0., 1., 0.)).magnitude() < 0.01);
+    }
+
+    #[test]
+    fn a_normal_map_tilts_the_normal_along_the_tangent() {
+        let tilt = std::f64::consts::FRAC_1_SQRT_2;
+        let mut plane = Object::plane();
+        plane.transform = Matrix4::rotation_y(std::f64::consts::FRAC_PI_2);
+        let mut material = Material::new();
+        material.bump = Some(Bump::normal_map(flat_map(tilt, 0., tilt)));
+        plane.set_material(material);
+
+        let ray = Ray::new(Tuple::point(0., 1., 0.), Tuple::vector(0., -1., 0.));
+        let normal = shading_normal(&plane, ray);
+
+        // u runs along the plane's x axis, which the rotation turns into -z
+        assert!((normal.x).abs() < 0.01);
+        assert!((normal.y - tilt).abs() < 0.01);
+        assert!((normal.z + tilt).abs() < 0.01);
+    }
+
+    #[test]
+    fn a_normal_map_on_a_sphere_follows_its_tangent_frame() {
+        let mut sphere = Object::sphere();
+        let mut material = Material::new();
+        material.bump = Some(Bump::normal_map(flat_map(0., 0.6, 0.8)));
+        sphere.set_material(material);
+
+        // At the front of the sphere v grows towards +y
+        let ray = Ray::new(Tuple::point(0., 0., 5.), Tuple::vector(0., 0., -1.));
+        let normal = shading_normal(&sphere, ray);
+
+        assert!((normal.y - 0.6).abs() < 0.02);
+        assert!((normal.z - 0.8).abs() < 0.02);
+    }
+
+    #[test]
+    fn noise_bumps_tilt_the_shading_normal() {
+        let mut sphere = Object::sphere();
+        let mut material = Material::new();
+        material.bump = Some(Bump::noise(Noise::perlin(3), 4., 0.5));
+        sphere.set_material(material);
+
+        let ray = Ray::new(Tuple::point(0.2, 0.3, -5.), Tuple::vector(0., 0., 1.));
+        let xs = sphere.intersect(ray);
+        let comps = Intersection::hit(&xs)
+            .unwrap()
+            .prepare_computations(ray, &xs);
+        let geometric = xs[0].object.normal_at(xs[0], ray.position(xs[0].t));
+
+        assert!(comps.normal_vector != geometric);
+        assert!(approx_unit(comps.normal_vector));
+        assert!(comps.normal_vector.dot(geometric) > 0.5);
+    }
+
+    fn approx_unit(vector: Tuple) -> bool {
+        (vector.magnitude() - 1.).abs() < 1e-9
+    }
+}
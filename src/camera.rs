@@ -1,6 +1,44 @@
-use std::io::Write;
+use std::f64::consts::PI;
+use std::time::Instant;
 
-use crate::{canvas::Canvas, math::matrix4::Matrix4, math::tuple::Tuple, ray::Ray, world::World};
+use crate::{
+    aov::{Aov, AovSample, RenderPasses},
+    canvas::{Canvas, Region},
+    color::Color,
+    math::matrix4::Matrix4,
+    math::tuple::Tuple,
+    occlusion::AmbientOcclusion,
+    progress::{Progress, ProgressObserver, TerminalProgress},
+    ray::Ray,
+    sampling::Rng,
+    spectrum,
+    world::World,
+};
+
+// A render stopped through its observer, with the part that was done.
+#[derive(Clone, Debug)]
+pub struct Cancelled<T = Canvas>(pub T);
+
+// Keeps the wavelength jitter independent from the pixel jitter.
+const SPECTRAL_SEED: u64 = 0x5eed;
+
+// How pixels are mapped to rays in camera space. Every projection looks down
+// the negative z axis with positive y up, so `view_transform` orients them all
+// the same way.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    Perspective,
+    // Parallel rays over a view `view_width` world units wide.
+    Orthographic { view_width: f64 },
+    // Equidistant fisheye: the angle off the view axis grows linearly with the
+    // distance from the image centre, reaching `field_of_view / 2` at the edge
+    // of the circle inscribed in the image. Pixels outside the circle see
+    // nothing.
+    Fisheye,
+    // Full 360 by 180 degree panorama, longitude along x and latitude along y.
+    // Ignores `field_of_view`.
+    Equirectangular,
+}
 
 #[derive(Clone, Copy)]
 pub struct Camera {
@@ -8,6 +46,15 @@ pub struct Camera {
     pub vsize: i32,
     pub field_of_view: f64,
     pub transform: Matrix4,
+    pub projection: Projection,
+    // Rays are spread over [shutter_open, shutter_close] so moving objects
+    // blur. Needs more than one sample per pixel to show.
+    pub shutter_open: f64,
+    pub shutter_close: f64,
+    pub samples_per_pixel: usize,
+    // When non-zero every sample is traced at this many wavelengths instead
+    // of in RGB, so dispersive materials split light into colors.
+    pub spectral_samples: usize,
 }
 
 impl Camera {
@@ -17,11 +64,38 @@ impl Camera {
             vsize,
             field_of_view,
             transform: Matrix4::identity(),
+            projection: Projection::Perspective,
+            shutter_open: 0.,
+            shutter_close: 0.,
+            samples_per_pixel: 1,
+            spectral_samples: 0,
         }
     }
 
-    fn half_extents(self) -> (f64, f64) {
-        let half_view = (self.field_of_view / 2.).tan();
+    pub fn orthographic(hsize: i32, vsize: i32, view_width: f64) -> Self {
+        Self {
+            projection: Projection::Orthographic { view_width },
+            ..Self::new(hsize, vsize, 0.)
+        }
+    }
+
+    pub fn fisheye(hsize: i32, vsize: i32, field_of_view: f64) -> Self {
+        Self {
+            projection: Projection::Fisheye,
+            ..Self::new(hsize, vsize, field_of_view)
+        }
+    }
+
+    pub fn equirectangular(hsize: i32, vsize: i32) -> Self {
+        Self {
+            projection: Projection::Equirectangular,
+            ..Self::new(hsize, vsize, 2. * PI)
+        }
+    }
+
+    // Half the width and height of a flat film whose longer side spans
+    // `2 * half_view`.
+    fn half_extents(self, half_view: f64) -> (f64, f64) {
         let aspect = self.hsize as f64 / self.vsize as f64;
 
         let (half_width, half_height) = if aspect > 1. {
@@ -33,51 +107,807 @@ impl Camera {
         (half_width, half_height)
     }
 
-    fn pixel_size(self) -> f64 {
-        let (half_width, _) = self.half_extents();
+    // Rays for pixels that see nothing, like the corners of a fisheye image,
+    // are `None`.
+    pub fn ray_for_pixel(self, px: i32, py: i32) -> Option<Ray> {
+        self.ray_through(px as f64 + 0.5, py as f64 + 0.5)
+    }
 
-        2. * half_width / self.hsize as f64
+    // Ray for the `sample`-th of `samples` jittered samples of a pixel. The
+    // jitter only depends on the arguments, so any sample can be recomputed.
+    pub fn ray_for_sample(self, px: i32, py: i32, sample: usize, samples: usize) -> Option<Ray> {
+        let mut rng = Rng::from_seeds(&[px as u64, py as u64, sample as u64]);
+
+        // Stratify time so each sample covers its own slice of the shutter
+        let time_fraction = (sample as f64 + rng.next_f64()) / samples as f64;
+        let time = self.shutter_open + (self.shutter_close - self.shutter_open) * time_fraction;
+
+        self.ray_through(px as f64 + rng.next_f64(), py as f64 + rng.next_f64())
+            .map(|ray| ray.at_time(time))
     }
 
-    pub fn ray_for_pixel(self, px: i32, py: i32) -> Ray {
-        let x_offset = (px as f64 + 0.5) * self.pixel_size();
-        let y_offset = (py as f64 + 0.5) * self.pixel_size();
+    pub fn pixel_color(self, world: &World, px: i32, py: i32) -> Color {
+        let samples = self.samples_per_pixel.max(1);
+        let total = (0..samples)
+            .map(|sample| self.sample_color(world, px, py, sample, samples))
+            .fold(Color::black(), |c1, c2| c1 + c2);
 
-        let (half_width, half_height) = self.half_extents();
-        let world_x = half_width - x_offset;
-        let world_y = half_height - y_offset;
+        total * (1. / samples as f64)
+    }
+
+    // Color of one of the `samples` samples of a pixel. A lone sample goes
+    // through the pixel centre at shutter open.
+    pub fn sample_color(
+        self,
+        world: &World,
+        px: i32,
+        py: i32,
+        sample: usize,
+        samples: usize,
+    ) -> Color {
+        let Some(ray) = self.sample_ray(px, py, sample, samples) else {
+            return Color::black();
+        };
+
+        if self.spectral_samples == 0 {
+            return world.color_at(ray);
+        }
+
+        let traced: Vec<(f64, Color)> = self
+            .wavelengths(px, py, sample)
+            .into_iter()
+            .map(|wavelength| (wavelength, world.color_at(ray.with_wavelength(wavelength))))
+            .collect();
+
+        spectrum::combine(&traced)
+    }
+
+    // Like `sample_color` with what the `aovs` record about the first hit.
+    fn sample_aovs<'w>(
+        self,
+        world: &'w World,
+        px: i32,
+        py: i32,
+        sample: usize,
+        samples: usize,
+        aovs: &[Aov],
+    ) -> AovSample<'w> {
+        let Some(ray) = self.sample_ray(px, py, sample, samples) else {
+            return AovSample::miss();
+        };
+
+        if self.spectral_samples == 0 {
+            return world.trace_aovs(ray, aovs);
+        }
+
+        // The surface itself looks the same at every wavelength, so only the
+        // first trace estimates occlusion
+        let traced: Vec<(f64, AovSample)> = self
+            .wavelengths(px, py, sample)
+            .into_iter()
+            .enumerate()
+            .map(|(i, wavelength)| {
+                let aovs = if i == 0 { aovs } else { &[] };
+                (
+                    wavelength,
+                    world.trace_aovs(ray.with_wavelength(wavelength), aovs),
+                )
+            })
+            .collect();
+        let combine = |part: fn(&AovSample) -> Color| {
+            let colors: Vec<(f64, Color)> = traced
+                .iter()
+                .map(|(wavelength, traced)| (*wavelength, part(traced)))
+                .collect();
+            spectrum::combine(&colors)
+        };
+
+        AovSample {
+            color: combine(|traced| traced.color),
+            direct: combine(|traced| traced.direct),
+            reflection: combine(|traced| traced.reflection),
+            refraction: combine(|traced| traced.refraction),
+            shadow: combine(|traced| traced.shadow),
+            ..traced[0].1
+        }
+    }
+
+    fn wavelengths(self, px: i32, py: i32, sample: usize) -> Vec<f64> {
+        let mut rng = Rng::from_seeds(&[px as u64, py as u64, sample as u64, SPECTRAL_SEED]);
+
+        spectrum::sample_wavelengths(self.spectral_samples, &mut rng)
+    }
+
+    fn sample_ray(self, px: i32, py: i32, sample: usize, samples: usize) -> Option<Ray> {
+        if samples <= 1 {
+            self.ray_for_pixel(px, py)
+                .map(|ray| ray.at_time(self.shutter_open))
+        } else {
+            self.ray_for_sample(px, py, sample, samples)
+        }
+    }
+
+    // `x` and `y` are film coordinates in pixels, measured from the top-left
+    // corner of the image.
+    fn ray_through(self, x: f64, y: f64) -> Option<Ray> {
+        let (origin, direction) = match self.projection {
+            Projection::Perspective => self.perspective_ray(x, y),
+            Projection::Orthographic { view_width } => self.orthographic_ray(view_width, x, y),
+            Projection::Fisheye => self.fisheye_ray(x, y)?,
+            Projection::Equirectangular => self.equirectangular_ray(x, y),
+        };
 
         let inverse_transform = self.transform.inverse().unwrap();
-        let pixel = inverse_transform * Tuple::point(world_x, world_y, -1.);
-        let origin = inverse_transform * Tuple::point(0., 0., 0.);
+        let origin = inverse_transform * origin;
+        let direction = (inverse_transform * direction).normalize();
 
-        let direction = (pixel - origin).normalize();
+        Some(Ray::new(origin, direction))
+    }
 
-        Ray::new(origin, direction)
+    // Offsets of a film position from the centre of a flat film, in camera
+    // space units.
+    fn film_position(self, half_view: f64, x: f64, y: f64) -> (f64, f64) {
+        let (half_width, half_height) = self.half_extents(half_view);
+        let pixel_size = 2. * half_width / self.hsize as f64;
+
+        (half_width - x * pixel_size, half_height - y * pixel_size)
+    }
+
+    fn perspective_ray(self, px: f64, py: f64) -> (Tuple, Tuple) {
+        let half_view = (self.field_of_view / 2.).tan();
+        let (world_x, world_y) = self.film_position(half_view, px, py);
+        let pixel = Tuple::point(world_x, world_y, -1.);
+        let origin = Tuple::point(0., 0., 0.);
+
+        (origin, pixel - origin)
     }
 
+    fn orthographic_ray(self, view_width: f64, px: f64, py: f64) -> (Tuple, Tuple) {
+        let (world_x, world_y) = self.film_position(view_width / 2., px, py);
+
+        (
+            Tuple::point(world_x, world_y, 0.),
+            Tuple::vector(0., 0., -1.),
+        )
+    }
+
+    fn fisheye_ray(self, px: f64, py: f64) -> Option<(Tuple, Tuple)> {
+        let radius = self.hsize.min(self.vsize) as f64 / 2.;
+        let dx = (self.hsize as f64 / 2. - px) / radius;
+        let dy = (self.vsize as f64 / 2. - py) / radius;
+
+        let distance = dx.hypot(dy);
+        if distance > 1. {
+            return None;
+        }
+
+        let theta = distance * self.field_of_view / 2.;
+        let phi = dy.atan2(dx);
+
+        let direction = Tuple::vector(
+            theta.sin() * phi.cos(),
+            theta.sin() * phi.sin(),
+            -theta.cos(),
+        );
+
+        Some((Tuple::point(0., 0., 0.), direction))
+    }
+
+    fn equirectangular_ray(self, px: f64, py: f64) -> (Tuple, Tuple) {
+        let u = px / self.hsize as f64;
+        let v = py / self.vsize as f64;
+
+        let longitude = (u - 0.5) * 2. * PI;
+        let latitude = (0.5 - v) * PI;
+
+        let direction = Tuple::vector(
+            -longitude.sin() * latitude.cos(),
+            latitude.sin(),
+            -longitude.cos() * latitude.cos(),
+        );
+
+        (Tuple::point(0., 0., 0.), direction)
+    }
+
+    // Renders with a progress bar on stderr.
     pub fn render(self, world: &World) -> Canvas {
+        match self.render_with(world, &mut TerminalProgress) {
+            Ok(canvas) => canvas,
+            Err(Cancelled(canvas)) => canvas,
+        }
+    }
+
+    // Renders row by row, telling `observer` after each one. A cancelled
+    // render returns the rows finished so far.
+    pub fn render_with(
+        self,
+        world: &World,
+        observer: &mut dyn ProgressObserver,
+    ) -> Result<Canvas, Cancelled> {
+        self.render_region_with(world, self.frame(), observer)
+    }
+
+    // Renders a crop window of the image with a progress bar on stderr. The
+    // canvas is the size of the region; paste it into a full-size canvas at
+    // the region's corner to place it in the frame.
+    pub fn render_region(self, world: &World, region: Region) -> Canvas {
+        match self.render_region_with(world, region, &mut TerminalProgress) {
+            Ok(canvas) => canvas,
+            Err(Cancelled(canvas)) => canvas,
+        }
+    }
+
+    // Like `render_with` for the part of `region` inside the image. Pixels
+    // come out exactly as in a full render.
+    pub fn render_region_with(
+        self,
+        world: &World,
+        region: Region,
+        observer: &mut dyn ProgressObserver,
+    ) -> Result<Canvas, Cancelled> {
+        let region = region.clipped(self.hsize as usize, self.vsize as usize);
+        let mut canvas = Canvas::new(region.width, region.height);
+
+        let finished = row_by_row(region.height, observer, |row| {
+            for column in 0..region.width {
+                let x = (region.x + column) as i32;
+                let y = (region.y + row) as i32;
+                let color = self.pixel_color(world, x, y);
+
+                canvas.write_pixel(column as i32, row as i32, color);
+            }
+        });
+
+        if finished {
+            Ok(canvas)
+        } else {
+            Err(Cancelled(canvas))
+        }
+    }
+
+    // The whole image as a region.
+    pub fn frame(self) -> Region {
+        Region::new(0, 0, self.hsize as usize, self.vsize as usize)
+    }
+
+    // Renders the image together with the requested passes, each averaged
+    // over the same samples as the image, with a progress bar on stderr.
+    pub fn render_aovs(self, world: &World, aovs: &[Aov]) -> RenderPasses {
+        match self.render_aovs_with(world, aovs, &mut TerminalProgress) {
+            Ok(passes) => passes,
+            Err(Cancelled(passes)) => passes,
+        }
+    }
+
+    // Like `render_with` for `render_aovs`. A cancelled render returns the
+    // rows of the image and passes finished so far.
+    pub fn render_aovs_with(
+        self,
+        world: &World,
+        aovs: &[Aov],
+        observer: &mut dyn ProgressObserver,
+    ) -> Result<RenderPasses, Cancelled<RenderPasses>> {
+        let (width, height) = (self.hsize as usize, self.vsize as usize);
+        let mut beauty = Canvas::new(width, height);
+        let mut passes: Vec<(Aov, Canvas)> = aovs
+            .iter()
+            .map(|aov| (*aov, Canvas::new(width, height)))
+            .collect();
+        let materials = world.materials();
+
+        let samples = self.samples_per_pixel.max(1);
+        let scale = 1. / samples as f64;
+        let finished = row_by_row(height, observer, |y| {
+            let y = y as i32;
+            for x in 0..self.hsize {
+                let mut color = Color::black();
+                let mut values = vec![Color::black(); passes.len()];
+
+                for sample in 0..samples {
+                    let traced = self.sample_aovs(world, x, y, sample, samples, aovs);
+
+                    color = color + traced.color;
+                    for (value, (aov, _)) in values.iter_mut().zip(&passes) {
+                        *value = *value + traced.value(*aov, &materials);
+                    }
+                }
+
+                beauty.write_pixel(x, y, color * scale);
+                for (value, (_, canvas)) in values.into_iter().zip(&mut passes) {
+                    canvas.write_pixel(x, y, value * scale);
+                }
+            }
+        });
+
+        let passes = RenderPasses { beauty, passes };
+        if finished {
+            Ok(passes)
+        } else {
+            Err(Cancelled(passes))
+        }
+    }
+
+    // Standalone grayscale image of the ambient occlusion seen through the
+    // pixel centres, with a progress bar on stderr.
+    pub fn render_ambient_occlusion(self, world: &World, settings: AmbientOcclusion) -> Canvas {
+        match self.render_ambient_occlusion_with(world, settings, &mut TerminalProgress) {
+            Ok(canvas) => canvas,
+            Err(Cancelled(canvas)) => canvas,
+        }
+    }
+
+    // Like `render_with` for `render_ambient_occlusion`.
+    pub fn render_ambient_occlusion_with(
+        self,
+        world: &World,
+        settings: AmbientOcclusion,
+        observer: &mut dyn ProgressObserver,
+    ) -> Result<Canvas, Cancelled> {
         let mut canvas = Canvas::new(self.hsize as usize, self.vsize as usize);
-        let total_pixels = self.vsize * self.hsize;
 
-        let mut total_done = 0;
-        for y in 0..self.vsize {
+        let finished = row_by_row(self.vsize as usize, observer, |y| {
+            let y = y as i32;
             for x in 0..self.hsize {
-                let ray = self.ray_for_pixel(x, y);
-                let color = world.color_at(ray);
+                let Some(ray) = self.ray_for_pixel(x, y) else {
+                    continue;
+                };
+                let ray = ray.at_time(self.shutter_open);
+
+                canvas.write_pixel(x, y, world.ambient_occlusion_along(ray, settings));
+            }
+        });
+
+        if finished {
+            Ok(canvas)
+        } else {
+            Err(Cancelled(canvas))
+        }
+    }
+}
+
+// Calls `render_row` for each of `rows` rows, telling `observer` after each
+// one. False if the observer cancelled before the last row.
+fn row_by_row(
+    rows: usize,
+    observer: &mut dyn ProgressObserver,
+    mut render_row: impl FnMut(usize),
+) -> bool {
+    let started = Instant::now();
+    let progress = |done: usize| Progress {
+        done,
+        total: rows,
+        elapsed: started.elapsed(),
+    };
+
+    observer.update(progress(0));
+    for row in 0..rows {
+        if observer.is_cancelled() {
+            return false;
+        }
+
+        render_row(row);
+        observer.update(progress(row + 1));
+    }
+    observer.finish(progress(rows));
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        light::Light,
+        material::Material,
+        math::transformations::view_transform,
+        motion::Motion,
+        pattern::Pattern,
+        progress::{CancelToken, Silent},
+        shape::Object,
+        spectrum::Dispersion,
+    };
+    use std::f64::consts::{FRAC_PI_2, FRAC_PI_4};
+
+    // A single pixel looking straight at the origin, and a world where any
+    // hit is pure white.
+    fn motion_blur_scene(motion: Option<Motion>) -> (Camera, World) {
+        let mut world = World::new();
+        world.add_light(Light::point_light(
+            Tuple::point(0., 0., 10.),
+            Color::white(),
+        ));
+
+        let mut sphere = Object::sphere();
+        let mut material = Material::new();
+        material.ambient = 1.;
+        material.diffuse = 0.;
+        material.specular = 0.;
+        sphere.set_material(material);
+        sphere.motion = motion;
+        world.add_object(sphere);
+
+        let mut camera = Camera::orthographic(1, 1, 0.01);
+        camera.transform = view_transform(
+            Tuple::point(0., 0., 5.),
+            Tuple::point(0., 0., 0.),
+            Tuple::vector(0., 1., 0.),
+        );
+        camera.shutter_open = 0.;
+        camera.shutter_close = 1.;
+
+        (camera, world)
+    }
+
+    #[test]
+    fn constructing_a_ray_through_the_center_of_the_canvas() {
+        let camera = Camera::new(201, 101, FRAC_PI_2);
+        let r = camera.ray_for_pixel(100, 50).unwrap();
+
+        assert_eq!(r.origin, Tuple::point(0., 0., 0.));
+        assert_eq!(r.direction, Tuple::vector(0., 0., -1.));
+    }
+
+    #[test]
+    fn constructing_a_ray_through_a_corner_of_the_canvas() {
+        let camera = Camera::new(201, 101, FRAC_PI_2);
+        let r = camera.ray_for_pixel(0, 0).unwrap();
+
+        assert_eq!(r.origin, Tuple::point(0., 0., 0.));
+        assert_eq!(r.direction, Tuple::vector(0.66519, 0.33259, -0.66851));
+    }
+
+    #[test]
+    fn constructing_a_ray_when_the_camera_is_transformed() {
+        let mut camera = Camera::new(201, 101, FRAC_PI_2);
+        camera.transform = Matrix4::rotation_y(PI / 4.) * Matrix4::translation(0., -2., 5.);
+        let r = camera.ray_for_pixel(100, 50).unwrap();
+
+        assert_eq!(r.origin, Tuple::point(0., 2., -5.));
+        assert_eq!(
+            r.direction,
+            Tuple::vector(2f64.sqrt() / 2., 0., -(2f64.sqrt()) / 2.)
+        );
+    }
+
+    #[test]
+    fn an_orthographic_ray_through_the_center_of_the_canvas() {
+        let camera = Camera::orthographic(201, 101, 4.);
+        let r = camera.ray_for_pixel(100, 50).unwrap();
 
-                canvas.write_pixel(x, y, color);
+        assert_eq!(r.origin, Tuple::point(0., 0., 0.));
+        assert_eq!(r.direction, Tuple::vector(0., 0., -1.));
+    }
+
+    #[test]
+    fn an_orthographic_ray_through_a_corner_of_the_canvas() {
+        let camera = Camera::orthographic(201, 101, 4.);
+        let r = camera.ray_for_pixel(0, 0).unwrap();
+
+        assert_eq!(r.origin, Tuple::point(1.99005, 0.99502, 0.));
+        assert_eq!(r.direction, Tuple::vector(0., 0., -1.));
+    }
+
+    #[test]
+    fn an_orthographic_ray_when_the_camera_is_transformed() {
+        let mut camera = Camera::orthographic(201, 101, 4.);
+        camera.transform = Matrix4::rotation_y(PI / 4.) * Matrix4::translation(0., -2., 5.);
+        let r = camera.ray_for_pixel(100, 50).unwrap();
+
+        assert_eq!(r.origin, Tuple::point(0., 2., -5.));
+        assert_eq!(
+            r.direction,
+            Tuple::vector(2f64.sqrt() / 2., 0., -(2f64.sqrt()) / 2.)
+        );
+    }
+
+    #[test]
+    fn a_fisheye_ray_through_the_center_of_the_canvas() {
+        let camera = Camera::fisheye(201, 201, PI);
+        let r = camera.ray_for_pixel(100, 100).unwrap();
+
+        assert_eq!(r.origin, Tuple::point(0., 0., 0.));
+        assert_eq!(r.direction, Tuple::vector(0., 0., -1.));
+    }
+
+    #[test]
+    fn a_fisheye_ray_near_the_edge_of_the_image_circle_is_almost_perpendicular_to_the_view() {
+        let camera = Camera::fisheye(201, 201, PI);
+        let r = camera.ray_for_pixel(100, 0).unwrap();
+
+        assert_eq!(r.direction, Tuple::vector(0., 0.99997, -0.00781));
+    }
+
+    #[test]
+    fn a_fisheye_camera_sees_nothing_outside_the_image_circle() {
+        let camera = Camera::fisheye(201, 101, PI);
+
+        assert!(camera.ray_for_pixel(0, 0).is_none());
+        assert!(camera.ray_for_pixel(20, 50).is_none());
+        assert!(camera.ray_for_pixel(60, 50).is_some());
+    }
+
+    #[test]
+    fn an_equirectangular_ray_through_the_center_of_the_canvas() {
+        let camera = Camera::equirectangular(201, 101);
+        let r = camera.ray_for_pixel(100, 50).unwrap();
+
+        assert_eq!(r.origin, Tuple::point(0., 0., 0.));
+        assert_eq!(r.direction, Tuple::vector(0., 0., -1.));
+    }
+
+    #[test]
+    fn an_equirectangular_ray_through_a_corner_of_the_canvas() {
+        let camera = Camera::equirectangular(201, 101);
+        let r = camera.ray_for_pixel(0, 0).unwrap();
+
+        assert_eq!(r.origin, Tuple::point(0., 0., 0.));
+        assert_eq!(r.direction, Tuple::vector(0.00024, 0.99988, 0.01555));
+    }
+
+    #[test]
+    fn an_equirectangular_camera_covers_the_full_horizon() {
+        let camera = Camera::equirectangular(4, 1);
+
+        let directions: Vec<Tuple> = (0..4)
+            .map(|x| camera.ray_for_pixel(x, 0).unwrap().direction)
+            .collect();
+
+        let s = 2f64.sqrt() / 2.;
+        assert_eq!(directions[0], Tuple::vector(s, 0., s));
+        assert_eq!(directions[1], Tuple::vector(s, 0., -s));
+        assert_eq!(directions[2], Tuple::vector(-s, 0., -s));
+        assert_eq!(directions[3], Tuple::vector(-s, 0., s));
+    }
+
+    #[test]
+    fn an_equirectangular_ray_when_the_camera_is_transformed() {
+        let mut camera = Camera::equirectangular(201, 101);
+        camera.transform = Matrix4::rotation_y(PI / 4.) * Matrix4::translation(0., -2., 5.);
+        let r = camera.ray_for_pixel(100, 50).unwrap();
+
+        assert_eq!(r.origin, Tuple::point(0., 2., -5.));
+        assert_eq!(
+            r.direction,
+            Tuple::vector(2f64.sqrt() / 2., 0., -(2f64.sqrt()) / 2.)
+        );
+    }
+
+    #[test]
+    fn samples_are_spread_over_the_shutter_interval() {
+        let mut camera = Camera::new(10, 10, FRAC_PI_2);
+        camera.shutter_open = 2.;
+        camera.shutter_close = 3.;
+
+        let times: Vec<f64> = (0..4)
+            .map(|sample| camera.ray_for_sample(5, 5, sample, 4).unwrap().time)
+            .collect();
+
+        for (sample, time) in times.iter().enumerate() {
+            let slice_start = 2. + sample as f64 / 4.;
+            assert!(slice_start <= *time && *time < slice_start + 0.25);
+        }
+    }
+
+    #[test]
+    fn a_static_object_is_not_blurred() {
+        let (mut camera, world) = motion_blur_scene(None);
+        camera.samples_per_pixel = 16;
+
+        assert_eq!(camera.pixel_color(&world, 0, 0), Color::white());
+    }
+
+    #[test]
+    fn a_single_sample_sees_a_moving_object_at_shutter_open() {
+        let motion = Motion::linear(
+            Matrix4::translation(-3., 0., 0.),
+            Matrix4::translation(3., 0., 0.),
+        );
+        let (camera, world) = motion_blur_scene(Some(motion));
+
+        assert_eq!(camera.pixel_color(&world, 0, 0), Color::black());
+    }
+
+    #[test]
+    fn a_fast_moving_object_is_blurred_when_supersampled() {
+        // The sphere covers the pixel for the middle third of the shutter
+        let motion = Motion::linear(
+            Matrix4::translation(-3., 0., 0.),
+            Matrix4::translation(3., 0., 0.),
+        );
+        let (mut camera, world) = motion_blur_scene(Some(motion));
+        camera.samples_per_pixel = 60;
+
+        let color = camera.pixel_color(&world, 0, 0);
+
+        assert!((color.red - 1. / 3.).abs() < 0.05);
+        assert_eq!(color.red, color.green);
+        assert_eq!(color.red, color.blue);
+    }
+
+    const GLASS: Dispersion = Dispersion::Cauchy { a: 1.5, b: 0.05 };
+    const RAY_X: f64 = -0.5;
+
+    // How far the slab below moves a ray sideways: it enters and leaves
+    // through parallel faces 2 units apart at 45 degrees.
+    fn slab_shift(wavelength: f64) -> f64 {
+        let refracted = (FRAC_PI_4.sin() / GLASS.refractive_index(wavelength)).asin();
+
+        2. * (FRAC_PI_4 - refracted).sin() / refracted.cos()
+    }
+
+    // A thick slab of strongly dispersive glass tilted in front of a
+    // backdrop. Short wavelengths bend more and land on the white half,
+    // long ones on the black half.
+    fn prism_scene() -> (Camera, World) {
+        let mut world = World::new();
+        world.add_light(Light::point_light(
+            Tuple::point(0., 0., -10.),
+            Color::white(),
+        ));
+
+        let mut camera = Camera::orthographic(1, 1, 0.01);
+        camera.transform = view_transform(
+            Tuple::point(RAY_X, 0., -5.),
+            Tuple::point(RAY_X, 0., 0.),
+            Tuple::vector(0., 1., 0.),
+        );
+
+        let mut glass = Object::cube();
+        glass.transform = Matrix4::rotation_y(FRAC_PI_4);
+        let mut material = Material::new();
+        material.color = Color::black();
+        material.ambient = 0.;
+        material.diffuse = 0.;
+        material.specular = 0.;
+        material.transparency = 1.;
+        material.refractive_index = 1.65;
+        material.dispersion = Some(GLASS);
+        glass.set_material(material);
+        world.add_object(glass);
+
+        let mut backdrop = Object::plane();
+        backdrop.transform = Matrix4::translation(0., 0., 5.) * Matrix4::rotation_x(FRAC_PI_2);
+        let mut pattern = Pattern::striped(Color::white(), Color::black());
+        let stripe_edge = RAY_X + (slab_shift(450.) + slab_shift(650.)) / 2.;
+        *pattern.transform_mut() = Matrix4::translation(stripe_edge, 0., 0.);
+        let mut material = Material::with_pattern(pattern);
+        material.ambient = 1.;
+        material.diffuse = 0.;
+        material.specular = 0.;
+        backdrop.set_material(material);
+        world.add_object(backdrop);
+
+        (camera, world)
+    }
+
+    #[test]
+    fn spectral_rendering_matches_rgb_without_dispersion() {
+        // A shiny colored sphere in front of a glass one, seen against the
+        // sky and partly through the glass
+        let mut world = World::new();
+        world.add_light(Light::point_light(
+            Tuple::point(-10., 10., -10.),
+            Color::white(),
+        ));
+        let mut sphere = Object::sphere();
+        sphere.transform = Matrix4::translation(0.5, 0., 2.);
+        let mut material = Material::new();
+        material.color = Color::new(0.8, 0.3, 0.1);
+        material.reflective = 0.3;
+        sphere.set_material(material);
+        world.add_object(sphere);
+        let mut glass = Object::sphere();
+        glass.transform = Matrix4::translation(-0.5, 0., -1.) * Matrix4::scaling(0.7, 0.7, 0.7);
+        let mut material = Material::new();
+        material.transparency = 0.9;
+        material.refractive_index = 1.5;
+        glass.set_material(material);
+        world.add_object(glass);
+
+        let mut camera = Camera::new(5, 3, FRAC_PI_2 / 2.);
+        camera.transform = view_transform(
+            Tuple::point(0., 0., -6.),
+            Tuple::point(0., 0., 0.),
+            Tuple::vector(0., 1., 0.),
+        );
+        let mut spectral = camera;
+        spectral.spectral_samples = 8;
+
+        for y in 0..3 {
+            for x in 0..5 {
+                assert_eq!(
+                    spectral.pixel_color(&world, x, y),
+                    camera.pixel_color(&world, x, y)
+                );
             }
-            total_done += self.hsize;
-            print!(
-                "Computed: {}/{} ({}%) pixels.\r",
-                total_done,
-                total_pixels,
-                (100. * (total_done as f64 / total_pixels as f64)).round()
-            );
-            std::io::stdout().flush().unwrap();
         }
+    }
+
+    // Every ray hits a plain white wall, so rendered pixels stand out from
+    // the black of rows that were never rendered.
+    fn white_wall(hsize: i32, vsize: i32) -> (Camera, World) {
+        let mut world = World::new();
+        world.add_light(Light::point_light(Tuple::point(0., 0., 0.), Color::white()));
+        let mut wall = Object::plane();
+        wall.transform = Matrix4::translation(0., 0., -5.) * Matrix4::rotation_x(FRAC_PI_2);
+        let mut material = Material::new();
+        material.ambient = 1.;
+        material.diffuse = 0.;
+        material.specular = 0.;
+        wall.set_material(material);
+        world.add_object(wall);
+
+        (Camera::new(hsize, vsize, FRAC_PI_2), world)
+    }
+
+    #[test]
+    fn a_dispersive_slab_splits_white_light() {
+        let (mut camera, world) = prism_scene();
+        let rgb = camera.pixel_color(&world, 0, 0);
+        camera.spectral_samples = 16;
+        let spectral = camera.pixel_color(&world, 0, 0);
+
+        assert_eq!(rgb.red, rgb.blue);
+        assert!(spectral.blue > 0.8);
+        assert!(spectral.red < 0.2);
+    }
+
+    #[test]
+    fn observers_hear_about_every_row() {
+        let (camera, world) = white_wall(2, 3);
+        let mut rows = vec![];
 
-        canvas
+        let canvas = camera
+            .render_with(&world, &mut |progress: Progress| rows.push(progress.done))
+            .unwrap();
+
+        assert_eq!(rows, vec![0, 1, 2, 3]);
+        assert_eq!(canvas.pixel_at(1, 2), camera.pixel_color(&world, 1, 2));
+        assert!(camera.render_with(&world, &mut Silent).is_ok());
+    }
+
+    #[test]
+    fn a_cancelled_render_keeps_the_finished_rows() {
+        let (camera, world) = white_wall(1, 3);
+        let token = CancelToken::new();
+        let cancel = token.clone();
+        let mut observer = (move |progress: Progress| {
+            if progress.done == 1 {
+                cancel.cancel()
+            }
+        })
+        .with_cancel(token);
+
+        let Err(Cancelled(partial)) = camera.render_with(&world, &mut observer) else {
+            panic!("the render should have been cancelled");
+        };
+
+        assert_eq!(partial.pixel_at(0, 0), Color::white());
+        assert_eq!(partial.pixel_at(0, 1), Color::black());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn pass_and_occlusion_renders_can_be_cancelled_too() {
+        let (camera, world) = white_wall(1, 3);
+        let token = CancelToken::new();
+        token.cancel();
+
+        let Err(Cancelled(passes)) = camera.render_aovs_with(
+            &world,
+            &[Aov::Depth],
+            &mut Silent.with_cancel(token.clone()),
+        ) else {
+            panic!("the render should have been cancelled");
+        };
+        assert_eq!(
+            passes.pass(Aov::Depth).unwrap().pixel_at(0, 0),
+            Color::black()
+        );
+
+        let settings = AmbientOcclusion::new(4, 1.);
+        let mut observer = Silent.with_cancel(token);
+        assert!(camera
+            .render_ambient_occlusion_with(&world, settings, &mut observer)
+            .is_err());
+    }
+}
@@ -1,13 +1,68 @@
 use crate::color::Color;
+use crate::tone::ToneMapping;
 
+#[derive(Clone, Debug, PartialEq)]
 pub struct Canvas {
     width: usize,
     height: usize,
     pixels: Vec<Color>,
 }
 
+// Rectangle of pixels, `x` and `y` being its top-left corner.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Region {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    // The part of the region inside a `width` by `height` image.
+    pub fn clipped(self, width: usize, height: usize) -> Self {
+        let x = self.x.min(width);
+        let y = self.y.min(height);
+
+        Self {
+            x,
+            y,
+            width: self.width.min(width - x),
+            height: self.height.min(height - y),
+        }
+    }
+
+    // Splits the region into tiles of at most `size` by `size` pixels, row
+    // by row.
+    pub fn tiles(self, size: usize) -> Vec<Region> {
+        let size = size.max(1);
+        let mut tiles = vec![];
+        for y in (self.y..self.y + self.height).step_by(size) {
+            for x in (self.x..self.x + self.width).step_by(size) {
+                tiles.push(Region::new(
+                    x,
+                    y,
+                    size.min(self.x + self.width - x),
+                    size.min(self.y + self.height - y),
+                ));
+            }
+        }
+
+        tiles
+    }
+}
+
 const MAX_COLOR_VALUE: i32 = 255;
 const MAX_PPM_LINE_LENGTH: usize = 70;
+const MAX_PPM_BINARY_VALUE: usize = 65535;
 
 impl Canvas {
     pub fn new(width: usize, height: usize) -> Self {
@@ -26,6 +81,14 @@ impl Canvas {
         }
     }
 
+    // Black outside of the canvas.
+    pub fn pixel_at(&self, x: i32, y: i32) -> Color {
+        match self.get_index(x, y) {
+            Some(index) => self.pixels[index],
+            None => Color::black(),
+        }
+    }
+
     pub fn width(&self) -> usize {
         self.width
     }
@@ -34,6 +97,29 @@ impl Canvas {
         self.height
     }
 
+    // Copy of a region of the canvas, black where it goes past the edges.
+    pub fn crop(&self, region: Region) -> Canvas {
+        let mut cropped = Canvas::new(region.width, region.height);
+        for y in 0..region.height {
+            for x in 0..region.width {
+                let color = self.pixel_at((region.x + x) as i32, (region.y + y) as i32);
+                cropped.write_pixel(x as i32, y as i32, color);
+            }
+        }
+
+        cropped
+    }
+
+    // Copies `other` over this canvas with its top-left corner at (x, y).
+    pub fn paste(&mut self, other: &Canvas, x: usize, y: usize) {
+        for dy in 0..other.height {
+            for dx in 0..other.width {
+                let color = other.pixel_at(dx as i32, dy as i32);
+                self.write_pixel((x + dx) as i32, (y + dy) as i32, color);
+            }
+        }
+    }
+
     fn get_index(&self, x: i32, y: i32) -> Option<usize> {
         let in_bounds = 0 <= x && x < self.width as i32 && 0 <= y && y < self.height as i32;
 
@@ -44,17 +130,97 @@ impl Canvas {
         }
     }
 
+    // Reads both plain (P3) and binary (P6) PPM images.
+    pub fn from_ppm(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = PpmReader { bytes, position: 0 };
+
+        let magic = reader.token()?;
+        if magic != "P3" && magic != "P6" {
+            return Err(format!("Unsupported PPM format {}", magic));
+        }
+        let width = reader.number()?;
+        let height = reader.number()?;
+        let max_value = reader.number()?;
+        if max_value == 0 || max_value > MAX_PPM_BINARY_VALUE {
+            return Err(format!("Invalid maximum color value {}", max_value));
+        }
+
+        let count = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(3))
+            .ok_or_else(|| format!("PPM image of {}x{} pixels is too large", width, height))?;
+        let values = if magic == "P3" {
+            (0..count)
+                .map(|_| reader.number())
+                .collect::<Result<Vec<_>, _>>()?
+        } else {
+            // A single whitespace byte separates the header from the data
+            reader.position += 1;
+            reader.binary(count, max_value > 255)?
+        };
+        if let Some(value) = values.iter().find(|value| **value > max_value) {
+            return Err(format!(
+                "Color value {} is above the maximum {}",
+                value, max_value
+            ));
+        }
+
+        let scale = 1. / max_value as f64;
+        let pixels = values
+            .chunks(3)
+            .map(|rgb| {
+                Color::new(
+                    rgb[0] as f64 * scale,
+                    rgb[1] as f64 * scale,
+                    rgb[2] as f64 * scale,
+                )
+            })
+            .collect();
+
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    // 8-bit output, clamped and sRGB encoded.
     pub fn to_ppm(&self) -> String {
+        self.to_ppm_with(&ToneMapping::new())
+    }
+
+    pub fn to_ppm_with(&self, tone_mapping: &ToneMapping) -> String {
         let ppm_header = format!("P3\n{} {}\n{}", self.width, self.height, MAX_COLOR_VALUE);
 
-        let ppm_body: String = self
+        let pixels: Vec<Color> = self
             .pixels
-            .chunks(self.width)
-            .map(process_row)
-            .collect::<Vec<_>>()
-            .join("\n");
+            .iter()
+            .map(|color| tone_mapping.apply(*color))
+            .collect();
+        // An empty canvas has no rows to chunk
+        let ppm_body: String = pixels
+            .chunks(self.width.max(1))
+            .map(|row| process_row(row) + "\n")
+            .collect();
+
+        ppm_header + "\n" + &ppm_body
+    }
+
+    // Portable float map keeping the linear colors as they are, for HDR
+    // output. Rows go from the bottom of the image up.
+    pub fn to_pfm(&self) -> Vec<u8> {
+        // A negative scale marks the data as little endian
+        let mut pfm = format!("PF\n{} {}\n-1.0\n", self.width, self.height).into_bytes();
 
-        ppm_header + "\n" + &ppm_body + "\n"
+        for row in self.pixels.chunks(self.width.max(1)).rev() {
+            for color in row {
+                for value in [color.red, color.green, color.blue] {
+                    pfm.extend_from_slice(&(value as f32).to_le_bytes());
+                }
+            }
+        }
+
+        pfm
     }
 }
 
@@ -98,3 +264,112 @@ fn format_scaled_color(color_component: f64) -> String {
         color_component.clamp(0., MAX_COLOR_VALUE as f64).round() as i16
     )
 }
+
+struct PpmReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> PpmReader<'a> {
+    // Next whitespace separated token, skipping `#` comments.
+    fn token(&mut self) -> Result<String, String> {
+        loop {
+            match self.bytes.get(self.position) {
+                Some(b'#') => {
+                    while !matches!(self.bytes.get(self.position), Some(b'\n') | None) {
+                        self.position += 1;
+                    }
+                }
+                Some(byte) if byte.is_ascii_whitespace() => self.position += 1,
+                Some(_) => break,
+                None => return Err("Unexpected end of PPM data".to_string()),
+            }
+        }
+
+        let start = self.position;
+        while matches!(self.bytes.get(self.position), Some(byte) if !byte.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+
+        Ok(String::from_utf8_lossy(&self.bytes[start..self.position]).into_owned())
+    }
+
+    fn number(&mut self) -> Result<usize, String> {
+        let token = self.token()?;
+
+        token
+            .parse()
+            .map_err(|_| format!("Expected a number in PPM data, found {}", token))
+    }
+
+    fn binary(&mut self, count: usize, wide: bool) -> Result<Vec<usize>, String> {
+        let size = if wide { 2 } else { 1 };
+        let end = count
+            .checked_mul(size)
+            .and_then(|length| length.checked_add(self.position))
+            .ok_or("Unexpected end of PPM data")?;
+        let data = self
+            .bytes
+            .get(self.position..end)
+            .ok_or("Unexpected end of PPM data")?;
+        self.position = end;
+
+        Ok(data
+            .chunks(size)
+            .map(|value| match value {
+                [high, low] => (*high as usize) << 8 | *low as usize,
+                [value] => *value as usize,
+                _ => unreachable!(),
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reading_a_plain_ppm_image() {
+        let ppm = "P3\n# a comment\n2 1\n255\n255 0 0  0 0 255\n";
+        let canvas = Canvas::from_ppm(ppm.as_bytes()).unwrap();
+
+        assert_eq!(canvas.width(), 2);
+        assert_eq!(canvas.height(), 1);
+        assert_eq!(canvas.pixel_at(0, 0), Color::new(1., 0., 0.));
+        assert_eq!(canvas.pixel_at(1, 0), Color::new(0., 0., 1.));
+    }
+
+    #[test]
+    fn reading_a_binary_ppm_image() {
+        let mut ppm = b"P6\n1 2\n255\n".to_vec();
+        ppm.extend_from_slice(&[255, 255, 255, 0, 255, 0]);
+        let canvas = Canvas::from_ppm(&ppm).unwrap();
+
+        assert_eq!(canvas.pixel_at(0, 0), Color::white());
+        assert_eq!(canvas.pixel_at(0, 1), Color::new(0., 1., 0.));
+    }
+
+    #[test]
+    fn truncated_ppm_images_are_rejected() {
+        assert!(Canvas::from_ppm(b"P3\n2 2\n255\n0 0 0\n").is_err());
+        assert!(Canvas::from_ppm(b"P5\n1 1\n255\n0\n").is_err());
+    }
+
+    #[test]
+    fn ppm_images_with_impossible_values_are_rejected() {
+        assert!(Canvas::from_ppm(b"P3\n1 1\n15\n0 16 0\n").is_err());
+        assert!(Canvas::from_ppm(b"P6\n1 1\n100\n\xff\x00\x00").is_err());
+
+        let huge = format!("P6\n{} 2\n255\n", usize::MAX / 2);
+        assert!(Canvas::from_ppm(huge.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn empty_canvases_are_written_as_a_header() {
+        let canvas = Canvas::new(0, 3);
+
+        assert_eq!(canvas.to_ppm(), "P3\n0 3\n255\n");
+        assert_eq!(canvas.to_pfm(), b"PF\n0 3\n-1.0\n");
+    }
+}
@@ -0,0 +1,579 @@
+use std::{
+    io::{BufRead, BufReader, Error, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    process::Child,
+    sync::{mpsc, Condvar, Mutex},
+    thread,
+    time::Instant,
+};
+
+use crate::{
+    camera::{Camera, Cancelled},
+    canvas::{Canvas, Region},
+    progress::{Progress, ProgressObserver, Silent},
+    tiles::{self, TileSettings},
+    world::World,
+};
+
+// Builds the camera and world from the scene file the coordinator sends.
+// Coordinator and workers must build the same scene from the same bytes.
+pub type SceneLoader = dyn Fn(&[u8]) -> Result<(Camera, World), String> + Sync;
+
+// Largest scene file a worker accepts unless told otherwise. Anything bigger
+// is more likely a garbled length than a real scene.
+pub const DEFAULT_MAX_SCENE_LENGTH: usize = 256 << 20;
+
+// One end of a coordinator/worker conversation, over TCP or pipes.
+//
+// The coordinator starts with `SCENE <length>` and the scene file, then
+// sends `TILE <x> <y> <width> <height>` lines, waiting after each for the
+// worker's `PIXELS <length>` line and that many bytes of tile data, and ends
+// with `DONE`. A worker that can't go on answers `ERROR <message>`.
+//
+// Lengths are checked before anything is read: tiles must have exactly the
+// size of the tile asked for, and scenes can't be over `max_scene_length`.
+pub struct Connection {
+    reader: Box<dyn BufRead + Send>,
+    writer: Box<dyn Write + Send>,
+    max_scene_length: usize,
+}
+
+impl Connection {
+    pub fn new(reader: impl Read + Send + 'static, writer: impl Write + Send + 'static) -> Self {
+        Self {
+            reader: Box::new(BufReader::new(reader)),
+            writer: Box::new(writer),
+            max_scene_length: DEFAULT_MAX_SCENE_LENGTH,
+        }
+    }
+
+    pub fn with_max_scene_length(mut self, max_scene_length: usize) -> Self {
+        self.max_scene_length = max_scene_length;
+        self
+    }
+
+    pub fn tcp(stream: TcpStream) -> std::io::Result<Self> {
+        Ok(Self::new(stream.try_clone()?, stream))
+    }
+
+    // The worker side of a child process started by the coordinator.
+    pub fn stdio() -> Self {
+        Self::new(std::io::stdin(), std::io::stdout())
+    }
+
+    // The coordinator side of a child process started with piped stdin and
+    // stdout.
+    pub fn child(child: &mut Child) -> std::io::Result<Self> {
+        let missing = || Error::other("Worker process has no piped stdio");
+        let stdin = child.stdin.take().ok_or_else(missing)?;
+        let stdout = child.stdout.take().ok_or_else(missing)?;
+
+        Ok(Self::new(stdout, stdin))
+    }
+
+    fn send(&mut self, line: &str, payload: &[u8]) -> std::io::Result<()> {
+        writeln!(self.writer, "{}", line)?;
+        self.writer.write_all(payload)?;
+        self.writer.flush()
+    }
+
+    // The next line split into its command and the rest, or `None` once the
+    // other end has closed the connection.
+    fn receive(&mut self) -> std::io::Result<Option<(String, String)>> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+
+        Ok(Some((command.to_owned(), rest.to_owned())))
+    }
+
+    // The bytes following a line that announced `length` of them, which
+    // can't be more than `limit`.
+    fn payload(&mut self, length: &str, limit: usize) -> std::io::Result<Vec<u8>> {
+        let length = parse(length)?;
+        if length > limit {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Payload of {} bytes is over the limit of {}", length, limit),
+            ));
+        }
+        let mut bytes = vec![0; length];
+        self.reader.read_exact(&mut bytes)?;
+
+        Ok(bytes)
+    }
+}
+
+// Waits for `count` workers to connect.
+pub fn accept_workers(listener: &TcpListener, count: usize) -> std::io::Result<Vec<Connection>> {
+    (0..count)
+        .map(|_| Connection::tcp(listener.accept()?.0))
+        .collect()
+}
+
+// Renders the scene by handing its tiles out to the workers, each getting
+// a new tile as soon as it sends one back, and returns the region's canvas
+// like `tiles::render_tiled`. Checkpointed tiles are loaded instead of
+// sent out, and received tiles are checkpointed. The tiles of a worker that
+// fails go to the others; the render only fails if they all do.
+pub fn render_distributed(
+    scene: &[u8],
+    load_scene: &SceneLoader,
+    settings: &TileSettings,
+    workers: Vec<Connection>,
+    observer: &mut dyn ProgressObserver,
+) -> std::io::Result<Canvas> {
+    let (camera, _) = load_scene(scene).map_err(Error::other)?;
+    let size = (camera.hsize as usize, camera.vsize as usize);
+    let region = settings
+        .region
+        .unwrap_or(camera.frame())
+        .clipped(size.0, size.1);
+    let directory = settings.checkpoint_dir.as_deref();
+    if let Some(directory) = directory {
+        std::fs::create_dir_all(directory)?;
+    }
+
+    let mut canvas = Canvas::new(region.width, region.height);
+    let all_tiles = region.tiles(settings.tile_size);
+    let mut pending = vec![];
+    for tile in &all_tiles {
+        match directory.and_then(|directory| tiles::load_tile(directory, size, *tile)) {
+            Some(saved) => canvas.paste(&saved, tile.x - region.x, tile.y - region.y),
+            None => pending.push(*tile),
+        }
+    }
+
+    let started = Instant::now();
+    let mut done = all_tiles.len() - pending.len();
+    let progress = |done: usize| Progress {
+        done,
+        total: all_tiles.len(),
+        elapsed: started.elapsed(),
+    };
+    observer.update(progress(done));
+
+    let queue = TileQueue::new(pending);
+    let (sender, results) = mpsc::channel();
+    let mut last_error = None;
+
+    thread::scope(|scope| {
+        for mut worker in workers {
+            let queue = &queue;
+            let sender = sender.clone();
+
+            scope.spawn(move || {
+                let result = drive_worker(&mut worker, scene, size, queue, &sender);
+                if let Err(error) = result {
+                    let _ = sender.send(Err(error));
+                }
+            });
+        }
+        drop(sender);
+
+        for result in results {
+            match result {
+                Ok((tile, pixels)) => {
+                    if let Some(directory) = directory {
+                        tiles::save_tile(directory, size, tile, &pixels)?;
+                    }
+                    canvas.paste(&pixels, tile.x - region.x, tile.y - region.y);
+                    done += 1;
+                    observer.update(progress(done));
+
+                    if observer.is_cancelled() {
+                        queue.clear();
+                        return Err(Error::new(ErrorKind::Interrupted, "Render cancelled"));
+                    }
+                }
+                Err(error) => last_error = Some(error),
+            }
+        }
+
+        Ok(())
+    })?;
+
+    if done < all_tiles.len() {
+        return Err(last_error.unwrap_or_else(|| Error::other("No workers")));
+    }
+    observer.finish(progress(done));
+
+    Ok(canvas)
+}
+
+type TileResult = std::io::Result<(Region, Canvas)>;
+
+// Tiles waiting for a worker, and how many workers are busy with one.
+struct TileQueue {
+    state: Mutex<(Vec<Region>, usize)>,
+    changed: Condvar,
+}
+
+impl TileQueue {
+    fn new(mut tiles: Vec<Region>) -> Self {
+        // Tiles are taken from the back, so they go out in order
+        tiles.reverse();
+
+        Self {
+            state: Mutex::new((tiles, 0)),
+            changed: Condvar::new(),
+        }
+    }
+
+    // The next tile, or `None` once every tile is finished. While other
+    // workers are still busy this waits, as their tiles come back if they
+    // fail.
+    fn take(&self) -> Option<Region> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            let (tiles, busy) = &mut *state;
+            if let Some(tile) = tiles.pop() {
+                *busy += 1;
+                return Some(tile);
+            }
+            if *busy == 0 {
+                return None;
+            }
+            state = self.changed.wait(state).unwrap();
+        }
+    }
+
+    // Ends a taken tile, putting it back when it didn't get rendered.
+    fn finish(&self, tile: Region, rendered: bool) {
+        let mut state = self.state.lock().unwrap();
+        if !rendered {
+            state.0.push(tile);
+        }
+        state.1 -= 1;
+        self.changed.notify_all();
+    }
+
+    fn clear(&self) {
+        self.state.lock().unwrap().0.clear();
+        self.changed.notify_all();
+    }
+}
+
+// Feeds one worker tiles until there are none left.
+fn drive_worker(
+    worker: &mut Connection,
+    scene: &[u8],
+    size: (usize, usize),
+    queue: &TileQueue,
+    results: &mpsc::Sender<TileResult>,
+) -> std::io::Result<()> {
+    worker.send(&format!("SCENE {}", scene.len()), scene)?;
+
+    while let Some(tile) = queue.take() {
+        let received = receive_tile(worker, size, tile);
+        queue.finish(tile, received.is_ok());
+
+        let pixels = received?;
+        if results.send(Ok((tile, pixels))).is_err() {
+            // The coordinator has stopped listening
+            return Ok(());
+        }
+    }
+
+    worker.send("DONE", &[])
+}
+
+fn receive_tile(
+    worker: &mut Connection,
+    size: (usize, usize),
+    tile: Region,
+) -> std::io::Result<Canvas> {
+    worker.send(
+        &format!("TILE {} {} {} {}", tile.x, tile.y, tile.width, tile.height),
+        &[],
+    )?;
+
+    match worker.receive()? {
+        Some((command, length)) if command == "PIXELS" => {
+            let bytes = worker.payload(&length, tiles::tile_length(size, tile))?;
+
+            tiles::read_tile(&bytes, size, tile)
+                .map_err(|message| Error::new(ErrorKind::InvalidData, message))
+        }
+        Some((command, message)) if command == "ERROR" => {
+            Err(Error::other(format!("Worker failed: {}", message)))
+        }
+        Some((command, _)) => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Unexpected reply from worker: {}", command),
+        )),
+        None => Err(Error::new(
+            ErrorKind::UnexpectedEof,
+            "Worker closed the connection",
+        )),
+    }
+}
+
+// Answers a coordinator until it says it's done: loads the scene it sends,
+// then renders every tile it asks for.
+pub fn serve_worker(mut connection: Connection, load_scene: &SceneLoader) -> std::io::Result<()> {
+    let mut scene: Option<(Camera, World)> = None;
+
+    while let Some((command, arguments)) = connection.receive()? {
+        match command.as_str() {
+            "SCENE" => {
+                let limit = connection.max_scene_length;
+                let bytes = connection.payload(&arguments, limit)?;
+                match load_scene(&bytes) {
+                    Ok(loaded) => scene = Some(loaded),
+                    Err(message) => {
+                        connection.send(&format!("ERROR {}", message), &[])?;
+                        return Err(Error::new(ErrorKind::InvalidData, message));
+                    }
+                }
+            }
+            "TILE" => {
+                let Some((camera, world)) = &scene else {
+                    connection.send("ERROR No scene loaded", &[])?;
+                    return Err(Error::new(ErrorKind::InvalidData, "Tile before scene"));
+                };
+                let tile = parse_region(&arguments)?;
+                let pixels = camera
+                    .render_region_with(world, tile, &mut Silent)
+                    .unwrap_or_else(|Cancelled(partial)| partial);
+                let size = (camera.hsize as usize, camera.vsize as usize);
+                let bytes = tiles::write_tile(&pixels, size, tile);
+
+                connection.send(&format!("PIXELS {}", bytes.len()), &bytes)?;
+            }
+            "DONE" => break,
+            _ => {
+                connection.send(&format!("ERROR Unknown command {}", command), &[])?;
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Unknown command {}", command),
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_region(arguments: &str) -> std::io::Result<Region> {
+    let values = arguments
+        .split_whitespace()
+        .map(parse)
+        .collect::<std::io::Result<Vec<usize>>>()?;
+
+    match values[..] {
+        [x, y, width, height] => Ok(Region::new(x, y, width, height)),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Invalid tile: {}", arguments),
+        )),
+    }
+}
+
+fn parse(value: &str) -> std::io::Result<usize> {
+    value.trim().parse().map_err(|_| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("Expected a number, found {}", value),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color::Color, light::Light, material::Material, math::transformations::view_transform,
+        math::tuple::Tuple, shape::Object,
+    };
+
+    // The scene file is just the image width.
+    fn load_scene(bytes: &[u8]) -> Result<(Camera, World), String> {
+        let width = std::str::from_utf8(bytes)
+            .ok()
+            .and_then(|text| text.trim().parse().ok())
+            .ok_or("Invalid scene")?;
+
+        let mut world = World::new();
+        world.add_light(Light::point_light(
+            Tuple::point(-10., 10., -10.),
+            Color::white(),
+        ));
+        let mut sphere = Object::sphere();
+        sphere.set_material(Material::new());
+        world.add_object(sphere);
+
+        let mut camera = Camera::new(width, 6, 1.);
+        camera.transform = view_transform(
+            Tuple::point(0., 0., -5.),
+            Tuple::point(0., 0., 0.),
+            Tuple::vector(0., 1., 0.),
+        );
+
+        Ok((camera, world))
+    }
+
+    // Workers on their own threads, connected over local TCP.
+    fn tcp_workers(
+        count: usize,
+    ) -> (
+        Vec<Connection>,
+        Vec<thread::JoinHandle<std::io::Result<()>>>,
+    ) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let handles = (0..count)
+            .map(|_| {
+                thread::spawn(move || {
+                    let stream = TcpStream::connect(address)?;
+                    serve_worker(Connection::tcp(stream)?, &load_scene)
+                })
+            })
+            .collect();
+
+        (accept_workers(&listener, count).unwrap(), handles)
+    }
+
+    // A coordinator and a worker connection talking over pipes, like a
+    // worker process started by the coordinator.
+    fn piped() -> (Connection, Connection) {
+        let (coordinator_reader, worker_writer) = std::io::pipe().unwrap();
+        let (worker_reader, coordinator_writer) = std::io::pipe().unwrap();
+
+        (
+            Connection::new(coordinator_reader, coordinator_writer),
+            Connection::new(worker_reader, worker_writer),
+        )
+    }
+
+    // A worker that hangs up as soon as it gets a tile.
+    fn failing_worker() -> Connection {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut connection = Connection::tcp(TcpStream::connect(address).unwrap()).unwrap();
+            while let Ok(Some((command, arguments))) = connection.receive() {
+                match command.as_str() {
+                    "SCENE" => {
+                        connection.payload(&arguments, usize::MAX).unwrap();
+                    }
+                    _ => return,
+                }
+            }
+        });
+
+        Connection::tcp(listener.accept().unwrap().0).unwrap()
+    }
+
+    fn settings() -> TileSettings {
+        TileSettings {
+            tile_size: 2,
+            ..TileSettings::default()
+        }
+    }
+
+    #[test]
+    fn workers_render_the_same_image_as_a_local_render() {
+        let (workers, handles) = tcp_workers(3);
+
+        let canvas =
+            render_distributed(b"7", &load_scene, &settings(), workers, &mut Silent).unwrap();
+
+        let (camera, world) = load_scene(b"7").unwrap();
+        let local = camera.render_with(&world, &mut Silent).unwrap();
+        assert_eq!((canvas.width(), canvas.height()), (7, 6));
+        assert_eq!(canvas, local);
+        for handle in handles {
+            handle.join().unwrap().unwrap();
+        }
+    }
+
+    #[test]
+    fn workers_can_be_reached_over_pipes() {
+        let (coordinator, worker) = piped();
+        let handle = thread::spawn(move || serve_worker(worker, &load_scene));
+
+        let canvas = render_distributed(
+            b"4",
+            &load_scene,
+            &settings(),
+            vec![coordinator],
+            &mut Silent,
+        )
+        .unwrap();
+
+        let (camera, world) = load_scene(b"4").unwrap();
+        assert_eq!(canvas, camera.render_with(&world, &mut Silent).unwrap());
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn oversized_payloads_are_rejected_before_they_are_read() {
+        let (mut coordinator, worker) = piped();
+        let handle =
+            thread::spawn(move || serve_worker(worker.with_max_scene_length(8), &load_scene));
+        coordinator.send("SCENE 9", b"123456789").unwrap();
+        let error = handle.join().unwrap().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+        // A worker announcing far more pixel data than a tile holds
+        let (coordinator, mut worker) = piped();
+        thread::spawn(move || {
+            let (_, length) = worker.receive().unwrap().unwrap();
+            worker.payload(&length, usize::MAX).unwrap();
+            worker.receive().unwrap();
+            worker.send(&format!("PIXELS {}", usize::MAX), &[]).unwrap();
+        });
+        let error = render_distributed(
+            b"4",
+            &load_scene,
+            &settings(),
+            vec![coordinator],
+            &mut Silent,
+        )
+        .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn tiles_of_a_failed_worker_go_to_the_others() {
+        let (mut workers, _) = tcp_workers(1);
+        workers.insert(0, failing_worker());
+
+        let canvas =
+            render_distributed(b"5", &load_scene, &settings(), workers, &mut Silent).unwrap();
+
+        let (camera, world) = load_scene(b"5").unwrap();
+        assert_eq!(canvas, camera.render_with(&world, &mut Silent).unwrap());
+    }
+
+    #[test]
+    fn the_render_fails_when_every_worker_does() {
+        let workers = vec![failing_worker(), failing_worker()];
+
+        let error =
+            render_distributed(b"5", &load_scene, &settings(), workers, &mut Silent).unwrap_err();
+
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn workers_report_scenes_they_cannot_load() {
+        let (workers, handles) = tcp_workers(1);
+        let mut coordinator = workers.into_iter().next().unwrap();
+
+        coordinator.send("SCENE 3", b"abc").unwrap();
+        coordinator.send("TILE 0 0 1 1", &[]).unwrap();
+
+        let (command, message) = coordinator.receive().unwrap().unwrap();
+        assert_eq!(
+            (command.as_str(), message.as_str()),
+            ("ERROR", "Invalid scene")
+        );
+        assert!(handles.into_iter().next().unwrap().join().unwrap().is_err());
+    }
+}
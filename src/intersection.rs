@@ -1,13 +1,7 @@
 use crate::math::tuple::Tuple;
 use crate::misc::{approx_equal, EPSILON};
 use crate::ray::Ray;
-use crate::shape::triangle::UVT;
-use crate::shape::SimpleObject;
-
-pub(crate) enum TorUVT {
-    JustT { t: f64 },
-    UVT { uvt: UVT },
-}
+use crate::shape::{LocalHit, SimpleObject};
 
 #[derive(Clone, Copy, Debug)]
 pub struct Intersection<'a> {
@@ -17,18 +11,11 @@ pub struct Intersection<'a> {
 }
 
 impl<'a> Intersection<'a> {
-    pub(crate) fn new(t_or_uvt: &TorUVT, object: SimpleObject<'a>) -> Self {
-        match t_or_uvt {
-            &TorUVT::JustT { t } => Self {
-                t,
-                uv: None,
-                object,
-            },
-            &TorUVT::UVT { uvt } => Self {
-                t: uvt.t,
-                uv: Some((uvt.u, uvt.v)),
-                object,
-            },
+    pub(crate) fn new(hit: LocalHit, object: SimpleObject<'a>) -> Self {
+        Self {
+            t: hit.t,
+            uv: hit.uv,
+            object,
         }
     }
 
@@ -43,26 +30,30 @@ impl<'a> Intersection<'a> {
     pub(crate) fn prepare_computations(
         &self,
         ray: Ray,
-        all_intersections: &[Intersection],
-    ) -> ComputedIntersection {
+        all_intersections: &[Intersection<'a>],
+    ) -> ComputedIntersection<'a> {
         let object = self.object;
         let _t = self.t;
         let point = ray.position(self.t);
         let eye_vector = -ray.direction;
 
-        let tentative_normal = self.object.normal_at(*self, point);
+        let geometric_normal = self.object.normal_at(*self, point);
+        let shading_normal = self.object.shading_normal_at(*self, point);
 
-        let (_inside, normal_vector) = if tentative_normal.dot(eye_vector) < 0. {
-            (true, -tentative_normal)
+        // Which side the ray is on depends on the real surface, bumps only
+        // change the shading.
+        let (_inside, geometric_normal, normal_vector) = if geometric_normal.dot(eye_vector) < 0. {
+            (true, -geometric_normal, -shading_normal)
         } else {
-            (false, tentative_normal)
+            (false, geometric_normal, shading_normal)
         };
 
         let reflect_vector = ray.direction.reflect(normal_vector);
-        let over_point = point + normal_vector * EPSILON;
-        let under_point = point - normal_vector * EPSILON;
+        let over_point = point + geometric_normal * EPSILON;
+        let under_point = point - geometric_normal * EPSILON;
 
-        let (n1, n2) = self.compute_refractive_indices(all_intersections);
+        let (n1, n2, container) =
+            self.compute_refractive_indices(all_intersections, ray.wavelength);
 
         ComputedIntersection {
             eye_vector,
@@ -72,7 +63,10 @@ impl<'a> Intersection<'a> {
             under_point,
             n1: n1,
             n2: n2,
+            time: ray.time,
+            wavelength: ray.wavelength,
             object,
+            container,
             #[cfg(test)]
             inside: _inside,
             #[cfg(test)]
@@ -82,24 +76,26 @@ impl<'a> Intersection<'a> {
         }
     }
 
-    fn compute_refractive_indices<'b>(
-        &'a self,
+    // Also returns the innermost object the ray was travelling through to
+    // reach this hit, if any.
+    fn compute_refractive_indices(
+        &self,
         all_intersections: &[Intersection<'a>],
-    ) -> (f64, f64)
-    where
-        'a: 'b,
-    {
-        let mut containers: Vec<SimpleObject<'b>> = vec![];
+        wavelength: Option<f64>,
+    ) -> (f64, f64, Option<SimpleObject<'a>>) {
+        let mut containers: Vec<SimpleObject<'a>> = vec![];
         let mut n1 = 1.0;
         let mut n2 = 1.0;
+        let mut container = None;
 
         for &i in all_intersections {
-
             let is_hit = i == *self;
 
             if is_hit {
+                container = containers.last().copied();
+
                 if let Some(last) = containers.last() {
-                    n1 = last.material().refractive_index;
+                    n1 = last.material().refractive_index_at(wavelength);
                 } else {
                     n1 = 1.0;
                 }
@@ -115,7 +111,7 @@ impl<'a> Intersection<'a> {
 
             if is_hit {
                 if let Some(last) = containers.last() {
-                    n2 = last.material().refractive_index;
+                    n2 = last.material().refractive_index_at(wavelength);
                 } else {
                     n2 = 1.0;
                 }
@@ -123,11 +119,14 @@ impl<'a> Intersection<'a> {
             }
         }
 
-        (n1, n2)
+        (n1, n2, container)
     }
 
-    pub(crate) fn uvt(&self) -> Option<UVT> {
-        self.uv.map(|(u, v)| UVT { t: self.t, u, v })
+    pub(crate) fn local_hit(&self) -> LocalHit {
+        LocalHit {
+            t: self.t,
+            uv: self.uv,
+        }
     }
 }
 
@@ -140,6 +139,8 @@ impl<'a> PartialEq for Intersection<'a> {
 #[derive(Copy, Clone, Debug)]
 pub(crate) struct ComputedIntersection<'a> {
     pub object: SimpleObject<'a>,
+    // Innermost object the ray was inside of on its way to the hit.
+    pub container: Option<SimpleObject<'a>>,
     pub eye_vector: Tuple,
     pub normal_vector: Tuple,
     pub reflect_vector: Tuple,
@@ -147,6 +148,8 @@ pub(crate) struct ComputedIntersection<'a> {
     pub under_point: Tuple,
     pub n1: f64,
     pub n2: f64,
+    pub time: f64,
+    pub wavelength: Option<f64>,
     #[cfg(test)]
     t: f64,
     #[cfg(test)]
@@ -156,6 +159,17 @@ pub(crate) struct ComputedIntersection<'a> {
 }
 
 impl<'a> ComputedIntersection<'a> {
+    // Ray leaving the hit at the same time and wavelength as the one that
+    // arrived.
+    pub fn spawn_ray(&self, origin: Tuple, direction: Tuple) -> Ray {
+        let ray = Ray::new(origin, direction).at_time(self.time);
+
+        match self.wavelength {
+            Some(wavelength) => ray.with_wavelength(wavelength),
+            None => ray,
+        }
+    }
+
     pub fn schlick(&self) -> f64 {
         // find the cosine of the angle between the camera and normal vectors
         let mut cos = self.eye_vector.dot(self.normal_vector);
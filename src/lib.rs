@@ -1,13 +1,29 @@
+pub mod animation;
+pub mod aov;
+pub mod bump;
 pub mod camera;
 pub mod canvas;
 pub mod color;
+pub mod distributed;
 pub mod intersection;
 pub mod light;
 pub mod material;
 pub mod math;
+pub mod medium;
+pub mod mesh;
 pub mod misc;
+pub mod motion;
+pub mod noise;
+pub mod occlusion;
 pub mod pattern;
+pub mod post;
+pub mod progress;
+pub mod progressive;
 pub mod ray;
+pub mod sampling;
 pub mod shape;
+pub mod spectrum;
+pub mod tiles;
+pub mod tone;
 pub mod world;
 pub mod obj;
@@ -1,11 +1,16 @@
+use std::{borrow::Cow, sync::Arc};
+
+use crate::bump::Bump;
 use crate::color::Color;
 use crate::light::Light;
 use crate::math::tuple::Tuple;
+use crate::medium::Medium;
 use crate::misc::approx_equal;
 use crate::pattern::Pattern;
 use crate::shape::SimpleObject;
+use crate::spectrum::Dispersion;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub struct Material {
     pub color: Color,
     pub ambient: f64,
@@ -13,10 +18,74 @@ pub struct Material {
     pub specular: f64,
     pub shininess: f64,
     pub reflective: f64,
+    // Spreads reflected and refracted rays over a cone, from 0 (mirror,
+    // clear glass) to 1 (a full hemisphere). See `World::glossy_samples`.
+    pub roughness: f64,
     pattern: Option<Pattern>,
     pub transparency: f64,
     pub refractive_index: f64,
     pub casts_shadows: bool,
+    // Fills the inside of the object. Only makes sense on closed shapes.
+    pub medium: Option<Medium>,
+    // Light travelling inside the object is filtered down to
+    // `absorption_color` every 1 / `absorption_density` units, so thicker
+    // parts get deeper in color. A density of 0 disables absorption.
+    pub absorption_color: Color,
+    pub absorption_density: f64,
+    // Replaces `refractive_index` for rays traced at a single wavelength.
+    pub dispersion: Option<Dispersion>,
+    pub bump: Option<Bump>,
+    textures: Arc<[(MaterialProperty, ScalarTexture)]>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MaterialProperty {
+    Ambient,
+    Diffuse,
+    Specular,
+    Shininess,
+    Reflective,
+    Roughness,
+    Transparency,
+    RefractiveIndex,
+}
+
+impl MaterialProperty {
+    pub(crate) fn set(self, material: &mut Material, value: f64) {
+        let field = match self {
+            MaterialProperty::Ambient => &mut material.ambient,
+            MaterialProperty::Diffuse => &mut material.diffuse,
+            MaterialProperty::Specular => &mut material.specular,
+            MaterialProperty::Shininess => &mut material.shininess,
+            MaterialProperty::Reflective => &mut material.reflective,
+            MaterialProperty::Roughness => &mut material.roughness,
+            MaterialProperty::Transparency => &mut material.transparency,
+            MaterialProperty::RefractiveIndex => &mut material.refractive_index,
+        };
+
+        *field = value;
+    }
+}
+
+// Drives a scalar property from a pattern. Black maps to `low` and white to
+// `high`, using the mean of the color channels.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScalarTexture {
+    pub pattern: Pattern,
+    pub low: f64,
+    pub high: f64,
+}
+
+impl ScalarTexture {
+    pub fn new(pattern: Pattern, low: f64, high: f64) -> Self {
+        Self { pattern, low, high }
+    }
+
+    fn value_at(&self, object: SimpleObject, point: Tuple) -> f64 {
+        let Color { red, green, blue } = self.pattern.pattern_at_object(object, point);
+
+        self.low + (self.high - self.low) * (red + green + blue) / 3.
+    }
 }
 
 impl Material {
@@ -28,10 +97,30 @@ impl Material {
             specular: 0.9,
             shininess: 200.,
             reflective: 0.,
+            roughness: 0.,
             pattern: None,
             transparency: 0.,
             refractive_index: 1.,
             casts_shadows: true,
+            medium: None,
+            absorption_color: Color::white(),
+            absorption_density: 0.,
+            dispersion: None,
+            bump: None,
+            textures: Arc::new([]),
+        }
+    }
+
+    // An invisible surface that only bounds a medium.
+    pub fn volume(medium: Medium) -> Self {
+        Self {
+            ambient: 0.,
+            diffuse: 0.,
+            specular: 0.,
+            transparency: 1.,
+            refractive_index: 1.,
+            medium: Some(medium),
+            ..Self::new()
         }
     }
 
@@ -43,6 +132,67 @@ impl Material {
     }
 }
 
+impl Material {
+    // Replaces any texture already driving `property`. The refractive index
+    // bends rays for the whole volume of an object, so it can't be
+    // textured and such a texture is ignored.
+    pub fn set_texture(&mut self, property: MaterialProperty, texture: ScalarTexture) {
+        let mut textures: Vec<_> = self
+            .textures
+            .iter()
+            .filter(|(existing, _)| *existing != property)
+            .cloned()
+            .collect();
+        textures.push((property, texture));
+
+        self.textures = textures.into();
+    }
+
+    // The material as seen at `point` on `object`, with the color pattern
+    // and every texture replaced by its value there.
+    pub fn at(&self, object: SimpleObject, point: Tuple) -> Cow<'_, Material> {
+        if self.pattern.is_none() && self.textures.is_empty() {
+            return Cow::Borrowed(self);
+        }
+
+        let mut material = Material {
+            pattern: None,
+            textures: Arc::new([]),
+            ..self.clone()
+        };
+        if let Some(pattern) = &self.pattern {
+            material.color = pattern.pattern_at_object(object, point);
+        }
+        for (property, texture) in self.textures.iter() {
+            if *property != MaterialProperty::RefractiveIndex {
+                property.set(&mut material, texture.value_at(object, point));
+            }
+        }
+
+        Cow::Owned(material)
+    }
+
+    pub fn refractive_index_at(&self, wavelength: Option<f64>) -> f64 {
+        match (self.dispersion, wavelength) {
+            (Some(dispersion), Some(wavelength)) => dispersion.refractive_index(wavelength),
+            _ => self.refractive_index,
+        }
+    }
+
+    // Per-channel fraction of light left after travelling `distance` units
+    // inside the object (Beer-Lambert).
+    pub fn absorption_over(&self, distance: f64) -> Color {
+        let exponent = self.absorption_density * distance;
+        let Color { red, green, blue } = self.absorption_color;
+
+        Color::new(
+            red.powf(exponent),
+            green.powf(exponent),
+            blue.powf(exponent),
+        )
+    }
+}
+
 impl PartialEq for Material {
     fn eq(&self, other: &Self) -> bool {
         self.color == other.color
@@ -50,26 +200,36 @@ impl PartialEq for Material {
             && approx_equal(self.diffuse, other.diffuse)
             && approx_equal(self.specular, other.specular)
             && approx_equal(self.shininess, other.shininess)
+            && approx_equal(self.reflective, other.reflective)
+            && approx_equal(self.roughness, other.roughness)
+            && self.pattern == other.pattern
+            && approx_equal(self.transparency, other.transparency)
+            && approx_equal(self.refractive_index, other.refractive_index)
+            && self.casts_shadows == other.casts_shadows
+            && self.medium == other.medium
+            && self.absorption_color == other.absorption_color
+            && approx_equal(self.absorption_density, other.absorption_density)
+            && self.dispersion == other.dispersion
+            && self.bump == other.bump
+            && self.textures == other.textures
     }
 }
 
 pub fn lighting(
-    material: Material,
+    material: &Material,
     object: SimpleObject,
     light: Light,
     point: Tuple,
     eye_vector: Tuple,
     normal_vector: Tuple,
-    in_shadow: bool,
+    // Share of the light that isn't shadowed, white when nothing is in the
+    // way.
+    shadow: Color,
 ) -> Color {
-    let color = if let Some(pattern) = material.pattern {
-        pattern.pattern_at_object(object, point)
-    } else {
-        material.color
-    };
+    let material = material.at(object, point);
 
     // Combine the surface color with light color and intensity
-    let effective_color = color * light.intensity;
+    let effective_color = material.color * light.intensity;
     // find the direction to the light source
     let light_vector = (light.position - point).normalize();
     // compute ambient contribution
@@ -99,9 +259,160 @@ pub fn lighting(
         }
     };
 
-    if in_shadow {
-        ambient
-    } else {
-        ambient + diffuse + specular
+    ambient + (diffuse + specular) * shadow
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{math::matrix4::Matrix4, ray::Ray, shape::Object, world::World};
+    use std::f64::consts::FRAC_PI_2;
+
+    #[test]
+    fn no_absorption_by_default() {
+        let material = Material::new();
+
+        assert_eq!(material.absorption_over(10.), Color::white());
+    }
+
+    #[test]
+    fn absorption_compounds_with_distance() {
+        let mut material = Material::new();
+        material.absorption_color = Color::new(0.5, 0.8, 1.);
+        material.absorption_density = 1.;
+
+        assert_eq!(material.absorption_over(0.), Color::white());
+        assert_eq!(material.absorption_over(1.), Color::new(0.5, 0.8, 1.));
+        assert_eq!(material.absorption_over(2.), Color::new(0.25, 0.64, 1.));
+    }
+
+    fn color_through_tinted_glass(thickness: f64) -> Color {
+        let mut world = World::new();
+        world.add_light(Light::point_light(
+            Tuple::point(0., 0., -10.),
+            Color::white(),
+        ));
+
+        let mut backdrop = Object::plane();
+        backdrop.transform = Matrix4::translation(0., 0., 10.) * Matrix4::rotation_x(FRAC_PI_2);
+        let mut material = Material::new();
+        material.ambient = 1.;
+        material.diffuse = 0.;
+        material.specular = 0.;
+        backdrop.set_material(material);
+        world.add_object(backdrop);
+
+        // Refractive index 1 keeps the ray straight through the glass
+        let mut glass = Object::sphere();
+        glass.transform = Matrix4::scaling(thickness / 2., thickness / 2., thickness / 2.);
+        let mut material = Material::new();
+        material.ambient = 0.;
+        material.diffuse = 0.;
+        material.specular = 0.;
+        material.transparency = 1.;
+        material.absorption_color = Color::new(0.2, 0.6, 0.9);
+        material.absorption_density = 0.5;
+        glass.set_material(material);
+        world.add_object(glass);
+
+        world.color_at(Ray::new(
+            Tuple::point(0., 0., -5.),
+            Tuple::vector(0., 0., 1.),
+        ))
+    }
+
+    #[test]
+    fn tinted_glass_darkens_as_it_gets_thicker() {
+        let thin = color_through_tinted_glass(1.);
+        let thick = color_through_tinted_glass(2.);
+
+        assert_eq!(
+            thin,
+            Color::new(0.2f64.sqrt(), 0.6f64.sqrt(), 0.9f64.sqrt())
+        );
+        assert_eq!(thick, Color::new(0.2, 0.6, 0.9));
+    }
+
+    #[test]
+    fn textures_are_evaluated_at_the_hit_point() {
+        let mut material = Material::new();
+        material.set_texture(
+            MaterialProperty::Reflective,
+            ScalarTexture::new(Pattern::checkered(Color::white(), Color::black()), 0., 0.8),
+        );
+        material.set_texture(
+            MaterialProperty::Shininess,
+            ScalarTexture::new(Pattern::striped(Color::black(), Color::white()), 10., 200.),
+        );
+        let object = Object::sphere();
+        let object = SimpleObject::from_object(&object).unwrap();
+
+        let white_check = material.at(object, Tuple::point(0.5, 0., 0.5));
+        let black_check = material.at(object, Tuple::point(1.5, 0., 0.5));
+
+        assert!(approx_equal(white_check.reflective, 0.8));
+        assert!(approx_equal(white_check.shininess, 10.));
+        assert!(approx_equal(black_check.reflective, 0.));
+        assert!(approx_equal(black_check.shininess, 200.));
+    }
+
+    #[test]
+    fn setting_a_texture_again_replaces_it() {
+        let mut material = Material::new();
+        let pattern = Pattern::striped(Color::white(), Color::black());
+        material.set_texture(
+            MaterialProperty::Diffuse,
+            ScalarTexture::new(pattern.clone(), 0., 1.),
+        );
+        material.set_texture(
+            MaterialProperty::Diffuse,
+            ScalarTexture::new(pattern, 0.3, 0.3),
+        );
+        let object = Object::sphere();
+        let object = SimpleObject::from_object(&object).unwrap();
+
+        assert!(approx_equal(
+            material.at(object, Tuple::point(0., 0., 0.)).diffuse,
+            0.3
+        ));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn a_floor_that_is_only_glossy_on_the_white_checks() {
+        let mut world = World::new();
+        world.add_light(Light::point_light(
+            Tuple::point(0., 10., 0.),
+            Color::white(),
+        ));
+
+        let mut floor = Object::plane();
+        let mut material = Material::new();
+        material.color = Color::black();
+        material.ambient = 0.;
+        material.diffuse = 0.;
+        material.specular = 0.;
+        material.set_texture(
+            MaterialProperty::Reflective,
+            ScalarTexture::new(Pattern::checkered(Color::white(), Color::black()), 0., 1.),
+        );
+        floor.set_material(material);
+        world.add_object(floor);
+
+        // Something bright above the floor to be reflected
+        let mut ceiling = Object::plane();
+        ceiling.transform = Matrix4::translation(0., 5., 0.);
+        let mut material = Material::new();
+        material.ambient = 1.;
+        material.diffuse = 0.;
+        material.specular = 0.;
+        ceiling.set_material(material);
+        world.add_object(ceiling);
+
+        let down = Tuple::vector(0., -1., 0.);
+        let on_white = world.color_at(Ray::new(Tuple::point(0.5, 1., 0.5), down));
+        let on_black = world.color_at(Ray::new(Tuple::point(1.5, 1., 0.5), down));
+
+        assert_eq!(on_white, Color::white());
+        assert_eq!(on_black, Color::black());
+    }
+}
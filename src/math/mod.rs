@@ -1,5 +1,6 @@
 pub mod matrix2;
 pub mod matrix3;
 pub mod matrix4;
+pub mod quaternion;
 pub mod transformations;
 pub mod tuple;
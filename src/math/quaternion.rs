@@ -0,0 +1,133 @@
+use super::matrix4::Matrix4;
+
+// Unit quaternion used to interpolate rotations.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quaternion {
+    pub w: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Quaternion {
+    pub fn new(w: f64, x: f64, y: f64, z: f64) -> Self {
+        Self { w, x, y, z }
+    }
+
+    pub fn identity() -> Self {
+        Self::new(1., 0., 0., 0.)
+    }
+
+    // Expects the upper 3x3 of `m` to be a pure rotation.
+    pub fn from_rotation_matrix(m: &Matrix4) -> Self {
+        let trace = m.get(0, 0) + m.get(1, 1) + m.get(2, 2);
+
+        let q = if trace > 0. {
+            let s = (trace + 1.).sqrt() * 2.;
+            Self::new(
+                s / 4.,
+                (m.get(2, 1) - m.get(1, 2)) / s,
+                (m.get(0, 2) - m.get(2, 0)) / s,
+                (m.get(1, 0) - m.get(0, 1)) / s,
+            )
+        } else if m.get(0, 0) > m.get(1, 1) && m.get(0, 0) > m.get(2, 2) {
+            let s = (1. + m.get(0, 0) - m.get(1, 1) - m.get(2, 2)).sqrt() * 2.;
+            Self::new(
+                (m.get(2, 1) - m.get(1, 2)) / s,
+                s / 4.,
+                (m.get(0, 1) + m.get(1, 0)) / s,
+                (m.get(0, 2) + m.get(2, 0)) / s,
+            )
+        } else if m.get(1, 1) > m.get(2, 2) {
+            let s = (1. + m.get(1, 1) - m.get(0, 0) - m.get(2, 2)).sqrt() * 2.;
+            Self::new(
+                (m.get(0, 2) - m.get(2, 0)) / s,
+                (m.get(0, 1) + m.get(1, 0)) / s,
+                s / 4.,
+                (m.get(1, 2) + m.get(2, 1)) / s,
+            )
+        } else {
+            let s = (1. + m.get(2, 2) - m.get(0, 0) - m.get(1, 1)).sqrt() * 2.;
+            Self::new(
+                (m.get(1, 0) - m.get(0, 1)) / s,
+                (m.get(0, 2) + m.get(2, 0)) / s,
+                (m.get(1, 2) + m.get(2, 1)) / s,
+                s / 4.,
+            )
+        };
+
+        q.normalize()
+    }
+
+    pub fn to_matrix(self) -> Matrix4 {
+        let Self { w, x, y, z } = self;
+
+        Matrix4::from_rows([
+            [
+                1. - 2. * (y * y + z * z),
+                2. * (x * y - w * z),
+                2. * (x * z + w * y),
+                0.,
+            ],
+            [
+                2. * (x * y + w * z),
+                1. - 2. * (x * x + z * z),
+                2. * (y * z - w * x),
+                0.,
+            ],
+            [
+                2. * (x * z - w * y),
+                2. * (y * z + w * x),
+                1. - 2. * (x * x + y * y),
+                0.,
+            ],
+            [0., 0., 0., 1.],
+        ])
+    }
+
+    pub fn dot(self, other: Self) -> f64 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn normalize(self) -> Self {
+        let length = self.dot(self).sqrt();
+
+        self.scale(1. / length)
+    }
+
+    fn scale(self, s: f64) -> Self {
+        Self::new(self.w * s, self.x * s, self.y * s, self.z * s)
+    }
+
+    fn add(self, other: Self) -> Self {
+        Self::new(
+            self.w + other.w,
+            self.x + other.x,
+            self.y + other.y,
+            self.z + other.z,
+        )
+    }
+
+    // Spherical linear interpolation along the shortest arc.
+    pub fn slerp(self, other: Self, t: f64) -> Self {
+        let mut cos = self.dot(other);
+        let mut other = other;
+
+        if cos < 0. {
+            other = other.scale(-1.);
+            cos = -cos;
+        }
+
+        if cos > 0.9995 {
+            // Nearly parallel, plain linear interpolation is accurate enough
+            return self.scale(1. - t).add(other.scale(t)).normalize();
+        }
+
+        let angle = cos.acos();
+        let sin = angle.sin();
+        let a = ((1. - t) * angle).sin() / sin;
+        let b = (t * angle).sin() / sin;
+
+        self.scale(a).add(other.scale(b))
+    }
+}
@@ -1,4 +1,5 @@
 use super::matrix4::Matrix4;
+use super::quaternion::Quaternion;
 use super::tuple::Tuple;
 
 pub fn view_transform(from: Tuple, to: Tuple, up: Tuple) -> Matrix4 {
@@ -15,3 +16,78 @@ pub fn view_transform(from: Tuple, to: Tuple, up: Tuple) -> Matrix4 {
 
     orientation * Matrix4::translation(-from.x, -from.y, -from.z)
 }
+
+// A transformation split into scale, then rotation, then translation. Shear
+// is not represented, so decomposing a sheared matrix is lossy.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DecomposedTransform {
+    pub translation: Tuple,
+    pub rotation: Quaternion,
+    pub scale: Tuple,
+}
+
+impl DecomposedTransform {
+    pub fn from_matrix(m: &Matrix4) -> Self {
+        let translation = Tuple::vector(m.get(0, 3), m.get(1, 3), m.get(2, 3));
+        let columns =
+            [0, 1, 2].map(|col| Tuple::vector(m.get(0, col), m.get(1, col), m.get(2, col)));
+        let mut scale = columns.map(|column| column.magnitude());
+
+        // A reflection is folded into the scale so the rest is a proper rotation
+        if columns[0].dot(columns[1].cross(columns[2])) < 0. {
+            scale[0] = -scale[0];
+        }
+
+        let mut rotation = Matrix4::identity();
+        for (col, column) in columns.iter().enumerate() {
+            *rotation.get_mut(0, col) = column.x / scale[col];
+            *rotation.get_mut(1, col) = column.y / scale[col];
+            *rotation.get_mut(2, col) = column.z / scale[col];
+        }
+
+        Self {
+            translation,
+            rotation: Quaternion::from_rotation_matrix(&rotation),
+            scale: Tuple::vector(scale[0], scale[1], scale[2]),
+        }
+    }
+
+    pub fn to_matrix(&self) -> Matrix4 {
+        let Self {
+            translation: t,
+            scale: s,
+            ..
+        } = *self;
+
+        Matrix4::translation(t.x, t.y, t.z)
+            * self.rotation.to_matrix()
+            * Matrix4::scaling(s.x, s.y, s.z)
+    }
+
+    pub fn interpolate(&self, other: &Self, t: f64) -> Self {
+        Self {
+            translation: self.translation + (other.translation - self.translation) * t,
+            rotation: self.rotation.slerp(other.rotation, t),
+            scale: self.scale + (other.scale - self.scale) * t,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decomposing_and_recomposing_a_transform() {
+        let transform = Matrix4::translation(1., -2., 3.)
+            * Matrix4::rotation_x(0.3)
+            * Matrix4::rotation_z(1.2)
+            * Matrix4::scaling(2., 0.5, 3.);
+
+        let decomposed = DecomposedTransform::from_matrix(&transform);
+
+        assert_eq!(decomposed.translation, Tuple::vector(1., -2., 3.));
+        assert_eq!(decomposed.scale, Tuple::vector(2., 0.5, 3.));
+        assert_eq!(decomposed.to_matrix(), transform);
+    }
+}
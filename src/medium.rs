@@ -0,0 +1,238 @@
+use std::f64::consts::PI;
+
+use crate::color::Color;
+
+const DEFAULT_STEPS: usize = 16;
+
+// Homogeneous participating medium such as fog, smoke or murky water.
+// Coefficients are per unit of world distance.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Medium {
+    pub absorption: f64,
+    pub scattering: f64,
+    // Henyey-Greenstein asymmetry, from -1 (back scattering) through 0
+    // (isotropic) to 1 (forward scattering).
+    pub anisotropy: f64,
+    // Tint of the light scattered towards the eye.
+    pub color: Color,
+    // Samples taken along each ray segment to estimate single scattering.
+    pub steps: usize,
+}
+
+impl Medium {
+    pub fn new(absorption: f64, scattering: f64) -> Self {
+        Self {
+            absorption,
+            scattering,
+            anisotropy: 0.,
+            color: Color::white(),
+            steps: DEFAULT_STEPS,
+        }
+    }
+
+    pub fn extinction(&self) -> f64 {
+        self.absorption + self.scattering
+    }
+
+    // Fraction of light that makes it through `distance` units (Beer-Lambert).
+    pub fn transmittance(&self, distance: f64) -> f64 {
+        (-self.extinction() * distance).exp()
+    }
+
+    // `cos_theta` is the cosine of the angle between the direction light was
+    // travelling in and the direction it leaves in.
+    pub fn phase(&self, cos_theta: f64) -> f64 {
+        henyey_greenstein(self.anisotropy, cos_theta)
+    }
+}
+
+pub fn henyey_greenstein(g: f64, cos_theta: f64) -> f64 {
+    let denominator = 1. + g * g - 2. * g * cos_theta;
+
+    (1. - g * g) / (4. * PI * denominator * denominator.sqrt())
+}
+
+// Medium filling all of the world outside of objects. Rays that hit nothing
+// are only marched up to `max_distance`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fog {
+    pub medium: Medium,
+    pub max_distance: f64,
+}
+
+impl Fog {
+    pub fn new(medium: Medium, max_distance: f64) -> Self {
+        Self {
+            medium,
+            max_distance,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        light::Light, material::Material, math::matrix4::Matrix4, math::tuple::Tuple,
+        misc::approx_equal, ray::Ray, shape::Object, world::World,
+    };
+
+    fn unlit_white() -> Material {
+        let mut material = Material::new();
+        material.ambient = 1.;
+        material.diffuse = 0.;
+        material.specular = 0.;
+        material
+    }
+
+    #[test]
+    fn an_isotropic_medium_scatters_equally_in_all_directions() {
+        let medium = Medium::new(0., 1.);
+
+        assert!(approx_equal(medium.phase(1.), 1. / (4. * PI)));
+        assert!(approx_equal(medium.phase(-1.), 1. / (4. * PI)));
+    }
+
+    #[test]
+    fn the_henyey_greenstein_phase_function_is_normalized() {
+        for g in [-0.7, 0., 0.3, 0.9] {
+            let steps = 100_000;
+            let integral: f64 = (0..steps)
+                .map(|i| {
+                    let cos = -1. + 2. * (i as f64 + 0.5) / steps as f64;
+                    henyey_greenstein(g, cos) * 2. * PI * (2. / steps as f64)
+                })
+                .sum();
+
+            assert!((integral - 1.).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn forward_scattering_favours_small_angles() {
+        let mut medium = Medium::new(0., 1.);
+        medium.anisotropy = 0.6;
+
+        assert!(medium.phase(1.) > medium.phase(0.));
+        assert!(medium.phase(0.) > medium.phase(-1.));
+    }
+
+    #[test]
+    fn transmittance_follows_beer_lambert() {
+        let medium = Medium::new(0.2, 0.3);
+
+        assert!(approx_equal(medium.transmittance(0.), 1.));
+        assert!(approx_equal(medium.transmittance(2.), (-1f64).exp()));
+    }
+
+    #[test]
+    fn absorbing_fog_attenuates_a_surface_with_distance() {
+        let mut world = World::new();
+        world.add_light(Light::point_light(
+            Tuple::point(0., 0., -10.),
+            Color::white(),
+        ));
+        let mut sphere = Object::sphere();
+        sphere.set_material(unlit_white());
+        world.add_object(sphere);
+        world.fog = Some(Fog::new(Medium::new(0.5, 0.), 100.));
+
+        let ray = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+        let color = world.color_at(ray);
+
+        assert_eq!(color, Color::white() * (-2f64).exp());
+    }
+
+    #[test]
+    fn fog_still_fills_a_closed_room() {
+        let mut world = World::new();
+        world.add_light(Light::point_light(
+            Tuple::point(0., 0., -10.),
+            Color::white(),
+        ));
+        let mut sphere = Object::sphere();
+        sphere.set_material(unlit_white());
+        world.add_object(sphere);
+        let mut room = Object::cube();
+        room.transform = Matrix4::scaling(20., 20., 20.);
+        world.add_object(room);
+        world.fog = Some(Fog::new(Medium::new(0.5, 0.), 100.));
+
+        let ray = Ray::new(Tuple::point(0., 0., -5.), Tuple::vector(0., 0., 1.));
+        let color = world.color_at(ray);
+
+        assert_eq!(color, Color::white() * (-2f64).exp());
+    }
+
+    #[test]
+    fn scattering_fog_glows_towards_a_visible_light() {
+        let mut world = World::new();
+        world.add_light(Light::point_light(
+            Tuple::point(0., 0., 10.),
+            Color::white(),
+        ));
+        world.fog = Some(Fog::new(Medium::new(0., 0.1), 20.));
+
+        let ray = Ray::new(Tuple::point(0., 0., 0.), Tuple::vector(0., 0., 1.));
+        let lit = world.color_at(ray);
+
+        // Block the light with a wall between it and the whole ray
+        let mut wall = Object::plane();
+        wall.transform = Matrix4::translation(0., 0., 9.) * Matrix4::rotation_x(PI / 2.);
+        world.add_object(wall);
+        let ray = Ray::new(Tuple::point(0., 0., 0.), Tuple::vector(0., 0., -1.));
+        let shadowed = world.color_at(ray);
+
+        assert!(lit.red > 0.);
+        assert_eq!(lit.red, lit.blue);
+        assert_eq!(shadowed, Color::black());
+    }
+
+    #[test]
+    fn endless_fog_is_marched_a_finite_distance() {
+        let mut world = World::new();
+        world.add_light(Light::point_light(
+            Tuple::point(0., 10., 0.),
+            Color::white(),
+        ));
+        let ray = Ray::new(Tuple::point(0., 0., 0.), Tuple::vector(0., 0., 1.));
+
+        world.fog = Some(Fog::new(Medium::new(0.05, 0.1), f64::INFINITY));
+        let endless = world.color_at(ray);
+        world.fog = Some(Fog::new(Medium::new(0.05, 0.1), 1000.));
+        let distant = world.color_at(ray);
+
+        assert!(endless.red > 0. && endless.red.is_finite());
+        assert_eq!(endless, distant);
+    }
+
+    #[test]
+    fn a_bounded_medium_only_absorbs_inside_its_object() {
+        let mut world = World::new();
+        world.add_light(Light::point_light(
+            Tuple::point(0., 10., -10.),
+            Color::white(),
+        ));
+
+        let mut backdrop = Object::plane();
+        backdrop.transform = Matrix4::translation(0., 0., 5.) * Matrix4::rotation_x(PI / 2.);
+        backdrop.set_material(unlit_white());
+        world.add_object(backdrop);
+
+        let mut smoke = Object::sphere();
+        smoke.set_material(Material::volume(Medium::new(0.5, 0.)));
+        world.add_object(smoke);
+
+        let through = world.color_at(Ray::new(
+            Tuple::point(0., 0., -5.),
+            Tuple::vector(0., 0., 1.),
+        ));
+        let beside = world.color_at(Ray::new(
+            Tuple::point(2., 0., -5.),
+            Tuple::vector(0., 0., 1.),
+        ));
+
+        assert_eq!(through, Color::white() * (-1f64).exp());
+        assert_eq!(beside, Color::white());
+    }
+}
@@ -0,0 +1,384 @@
+use crate::{
+    math::tuple::Tuple,
+    shape::{triangle::Triangle, Object},
+};
+
+// How much each face around a vertex counts towards its generated normal.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NormalWeighting {
+    // In proportion to the face's area, so small faces barely bend the
+    // normals of the large ones next to them.
+    #[default]
+    Area,
+    // In proportion to the face's angle at the vertex, which doesn't change
+    // when a face is split up.
+    Angle,
+}
+
+// Triangles sharing vertices, as procedural tools generate them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mesh {
+    vertices: Vec<Tuple>,
+    faces: Vec<[usize; 3]>,
+    // One per vertex when given, for smooth shading.
+    normals: Vec<Tuple>,
+    // One per face when given. Generated normals are only shared between
+    // faces in the same group, and faces in group 0 are flat.
+    smoothing_groups: Vec<u32>,
+    weighting: NormalWeighting,
+}
+
+impl Mesh {
+    // Every three indices are the vertices of a face.
+    pub fn new(vertices: &[Tuple], indices: &[usize]) -> Result<Self, String> {
+        if !indices.len().is_multiple_of(3) {
+            return Err(format!(
+                "Mesh has {} indices, not a multiple of 3",
+                indices.len()
+            ));
+        }
+        if let Some(index) = indices.iter().find(|&&index| index >= vertices.len()) {
+            return Err(format!(
+                "Mesh index {} is out of range for {} vertices",
+                index,
+                vertices.len()
+            ));
+        }
+
+        Ok(Self {
+            vertices: vertices.to_vec(),
+            faces: indices
+                .chunks(3)
+                .map(|face| [face[0], face[1], face[2]])
+                .collect(),
+            normals: vec![],
+            smoothing_groups: vec![],
+            weighting: NormalWeighting::default(),
+        })
+    }
+
+    // Shades the mesh smoothly with a normal for every vertex.
+    pub fn with_normals(mut self, normals: &[Tuple]) -> Result<Self, String> {
+        if normals.len() != self.vertices.len() {
+            return Err(format!(
+                "Mesh has {} normals for {} vertices",
+                normals.len(),
+                self.vertices.len()
+            ));
+        }
+        self.normals = normals.to_vec();
+
+        Ok(self)
+    }
+
+    // Keeps generated normals from being shared between faces in different
+    // groups, like the `s` statements of OBJ files.
+    pub fn with_smoothing_groups(mut self, groups: &[u32]) -> Result<Self, String> {
+        if groups.len() != self.faces.len() {
+            return Err(format!(
+                "Mesh has {} smoothing groups for {} faces",
+                groups.len(),
+                self.faces.len()
+            ));
+        }
+        self.smoothing_groups = groups.to_vec();
+
+        Ok(self)
+    }
+
+    pub fn with_weighting(mut self, weighting: NormalWeighting) -> Self {
+        self.weighting = weighting;
+
+        self
+    }
+
+    pub fn vertices(&self) -> &[Tuple] {
+        &self.vertices
+    }
+
+    pub fn faces(&self) -> &[[usize; 3]] {
+        &self.faces
+    }
+
+    // Drops faces that would fail to make a triangle, returning how many.
+    pub fn remove_degenerate(&mut self) -> usize {
+        let before = self.faces.len();
+        let keep: Vec<bool> = self
+            .faces
+            .iter()
+            .map(|&[a, b, c]| {
+                Triangle::new(self.vertices[a], self.vertices[b], self.vertices[c]).is_ok()
+            })
+            .collect();
+
+        let mut kept = keep.iter();
+        self.faces.retain(|_| *kept.next().unwrap());
+        if !self.smoothing_groups.is_empty() {
+            let mut kept = keep.iter();
+            self.smoothing_groups.retain(|_| *kept.next().unwrap());
+        }
+
+        before - self.faces.len()
+    }
+
+    // A normal for each corner of each face, averaging the normals of the
+    // faces around the corner's vertex by the mesh's weighting. Only faces
+    // in the same smoothing group meeting the corner's face at up to
+    // `crease_angle` radians count, so edges sharper than that stay sharp.
+    pub fn generate_normals(&self, crease_angle: f64) -> Vec<[Tuple; 3]> {
+        // Degenerate faces have no direction, so they share with no other
+        let directions: Vec<Tuple> = self
+            .faces
+            .iter()
+            .map(|&[a, b, c]| {
+                let (p1, p2, p3) = (self.vertices[a], self.vertices[b], self.vertices[c]);
+                (p3 - p1).cross(p2 - p1).normalize()
+            })
+            .collect();
+
+        // The faces around each vertex, with how much they count there
+        let mut faces_at = vec![vec![]; self.vertices.len()];
+        for (face, &corners) in self.faces.iter().enumerate() {
+            for corner in 0..3 {
+                let weight = self.corner_weight(corners, corner);
+                faces_at[corners[corner]].push((face, weight));
+            }
+        }
+
+        let group = |face: usize| self.smoothing_groups.get(face).copied().unwrap_or(1);
+        let min_cos = crease_angle.cos();
+        let shared = |face: usize, other: usize| {
+            face == other
+                || (group(face) != 0
+                    && group(face) == group(other)
+                    && directions[face].dot(directions[other]) >= min_cos)
+        };
+
+        self.faces
+            .iter()
+            .enumerate()
+            .map(|(face, vertices)| {
+                vertices.map(|vertex| {
+                    faces_at[vertex]
+                        .iter()
+                        .filter(|&&(other, _)| shared(face, other))
+                        .fold(Tuple::vector(0., 0., 0.), |sum, &(other, weight)| {
+                            sum + directions[other] * weight
+                        })
+                        .normalize()
+                })
+            })
+            .collect()
+    }
+
+    fn corner_weight(&self, corners: [usize; 3], corner: usize) -> f64 {
+        let point = self.vertices[corners[corner]];
+        let next = self.vertices[corners[(corner + 1) % 3]] - point;
+        let previous = self.vertices[corners[(corner + 2) % 3]] - point;
+
+        match self.weighting {
+            NormalWeighting::Area => next.cross(previous).magnitude() / 2.,
+            NormalWeighting::Angle => next
+                .normalize()
+                .dot(previous.normalize())
+                .clamp(-1., 1.)
+                .acos(),
+        }
+    }
+
+    // Smooth shaded with the mesh's normals if it has them, flat otherwise.
+    pub fn triangles(&self) -> Result<Vec<Triangle>, String> {
+        self.build(|face| {
+            if self.normals.is_empty() {
+                None
+            } else {
+                Some(self.faces[face].map(|vertex| self.normals[vertex]))
+            }
+        })
+    }
+
+    // Smooth shaded with `generate_normals`, ignoring the mesh's normals.
+    pub fn smoothed_triangles(&self, crease_angle: f64) -> Result<Vec<Triangle>, String> {
+        let normals = self.generate_normals(crease_angle);
+
+        self.build(|face| Some(normals[face]))
+    }
+
+    pub fn to_group(&self) -> Result<Object, String> {
+        Ok(to_group(self.triangles()?))
+    }
+
+    pub fn to_smoothed_group(&self, crease_angle: f64) -> Result<Object, String> {
+        Ok(to_group(self.smoothed_triangles(crease_angle)?))
+    }
+
+    fn build(
+        &self,
+        normals: impl Fn(usize) -> Option<[Tuple; 3]>,
+    ) -> Result<Vec<Triangle>, String> {
+        self.faces
+            .iter()
+            .enumerate()
+            .map(|(face, &[a, b, c])| {
+                let (p1, p2, p3) = (self.vertices[a], self.vertices[b], self.vertices[c]);
+                match normals(face) {
+                    Some([n1, n2, n3]) => Triangle::smooth(p1, p2, p3, n1, n2, n3),
+                    None => Triangle::new(p1, p2, p3),
+                }
+                .map_err(|message| format!("Face {}: {}", face, message))
+            })
+            .collect()
+    }
+}
+
+fn to_group(triangles: Vec<Triangle>) -> Object {
+    Object::group(triangles.into_iter().map(Object::triangle).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+    use crate::shape::{LocalHit, Primitive};
+
+    fn point(x: f64, y: f64, z: f64) -> Tuple {
+        Tuple::point(x, y, z)
+    }
+
+    // Two faces meeting at a right angle along the z axis, like a roof.
+    fn roof() -> Mesh {
+        let vertices = [
+            point(0., 0., 0.),
+            point(0., 0., 1.),
+            point(-1., -1., 0.),
+            point(1., -1., 0.),
+        ];
+
+        Mesh::new(&vertices, &[0, 1, 2, 1, 0, 3]).unwrap()
+    }
+
+    fn assert_close(a: Tuple, b: Tuple) {
+        assert!((a - b).magnitude() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn degenerate_triangles_are_rejected() {
+        let (a, b) = (point(0., 0., 0.), point(1., 0., 0.));
+
+        assert!(Triangle::new(a, b, point(0., 1., 0.)).is_ok());
+        assert!(Triangle::new(a, b, point(2., 0., 0.)).is_err());
+        assert!(Triangle::new(a, b, point(f64::NAN, 1., 0.)).is_err());
+
+        let up = Tuple::vector(0., 0., 1.);
+        let zero = Tuple::vector(0., 0., 0.);
+        assert!(Triangle::smooth(a, b, point(0., 1., 0.), up, up, zero).is_err());
+    }
+
+    #[test]
+    fn smooth_triangles_without_uv_use_the_face_normal() {
+        let (a, b, c) = (point(0., 0., 0.), point(1., 0., 0.), point(0., 1., 0.));
+        let tilted = Tuple::vector(1., 0., 1.);
+        let triangle = Triangle::smooth(a, b, c, tilted, tilted, tilted).unwrap();
+
+        assert_close(
+            triangle.local_normal_at(point(0.2, 0.2, 0.), LocalHit::new(1.)),
+            Tuple::vector(0., 0., -1.),
+        );
+    }
+
+    #[test]
+    fn meshes_check_their_indices() {
+        let vertices = [point(0., 0., 0.), point(1., 0., 0.), point(0., 1., 0.)];
+
+        assert_eq!(
+            Mesh::new(&vertices, &[0, 1, 2]).unwrap().faces(),
+            &[[0, 1, 2]]
+        );
+        assert!(Mesh::new(&vertices, &[0, 1]).is_err());
+        assert!(Mesh::new(&vertices, &[0, 1, 3]).is_err());
+        assert!(Mesh::new(&vertices, &[0, 1, 2])
+            .unwrap()
+            .with_normals(&[Tuple::vector(0., 0., 1.)])
+            .is_err());
+    }
+
+    #[test]
+    fn degenerate_faces_can_be_removed() {
+        let vertices = [point(0., 0., 0.), point(1., 0., 0.), point(0., 1., 0.)];
+        let mut mesh = Mesh::new(&vertices, &[0, 1, 2, 0, 1, 1]).unwrap();
+
+        let error = mesh.to_group().unwrap_err();
+        assert_eq!(error, "Face 1: Triangle has zero area");
+
+        assert_eq!(mesh.remove_degenerate(), 1);
+        assert!(mesh.to_group().is_ok());
+    }
+
+    #[test]
+    fn normals_are_shared_across_edges_below_the_crease_angle() {
+        let mesh = roof();
+        let (left, right) = (
+            Tuple::vector(-1., 1., 0.).normalize(),
+            Tuple::vector(1., 1., 0.).normalize(),
+        );
+        let sharp = mesh.generate_normals(PI / 4.);
+        assert_close(sharp[0][0], left);
+        assert_close(sharp[1][0], right);
+
+        let smooth = mesh.generate_normals(PI / 2. + 0.01);
+        assert_close(smooth[0][0], Tuple::vector(0., 1., 0.));
+        assert_close(smooth[1][1], Tuple::vector(0., 1., 0.));
+        // Corners only one face touches keep its normal
+        assert_close(smooth[0][2], left);
+    }
+
+    #[test]
+    fn larger_faces_weigh_more() {
+        let vertices = [
+            point(0., 0., 0.),
+            point(0., 0., 1.),
+            point(-1., -1., 0.),
+            point(3., -3., 0.),
+        ];
+        let mesh = Mesh::new(&vertices, &[0, 1, 2, 1, 0, 3]).unwrap();
+
+        let normal = mesh.generate_normals(PI)[0][0];
+
+        // The right face has three times the area
+        let expected = (Tuple::vector(-1., 1., 0.) + Tuple::vector(3., 3., 0.)).normalize();
+        assert_close(normal, expected);
+    }
+
+    #[test]
+    fn angle_weighting_ignores_face_sizes() {
+        let vertices = [
+            point(0., 0., 0.),
+            point(0., 0., 1.),
+            point(-1., -1., 0.),
+            point(3., -3., 0.),
+        ];
+        let mesh = Mesh::new(&vertices, &[0, 1, 2, 1, 0, 3])
+            .unwrap()
+            .with_weighting(NormalWeighting::Angle);
+
+        // Both faces have a right angle at the shared vertex
+        assert_close(mesh.generate_normals(PI)[0][0], Tuple::vector(0., 1., 0.));
+    }
+
+    #[test]
+    fn normals_are_only_shared_within_a_smoothing_group() {
+        let left = Tuple::vector(-1., 1., 0.).normalize();
+        let up = Tuple::vector(0., 1., 0.);
+
+        let together = roof().with_smoothing_groups(&[2, 2]).unwrap();
+        assert_close(together.generate_normals(PI)[0][0], up);
+
+        let apart = roof().with_smoothing_groups(&[1, 2]).unwrap();
+        assert_close(apart.generate_normals(PI)[0][0], left);
+
+        let off = roof().with_smoothing_groups(&[0, 0]).unwrap();
+        assert_close(off.generate_normals(PI)[0][0], left);
+        assert!(roof().with_smoothing_groups(&[1]).is_err());
+    }
+}
@@ -0,0 +1,218 @@
+use std::sync::OnceLock;
+
+use crate::math::{matrix4::Matrix4, transformations::DecomposedTransform, tuple::Tuple};
+use crate::shape::BoundingBox;
+
+// Number of in-between transforms checked per keyframe segment when bounding
+// a moving object. Rotations can sweep outside the keyframe boxes.
+const BOUNDING_STEPS: usize = 8;
+
+// Keyframed transformation of an object over time. Between keyframes the
+// transformation is decomposed into translation, rotation and scale and each
+// part is interpolated on its own, so a spinning object turns instead of
+// shrinking through a blend of matrices.
+#[derive(Clone, Debug)]
+pub struct Motion {
+    keyframes: Vec<Keyframe>,
+    // Keyframes and in-between transforms, in time order.
+    samples: Vec<Matrix4>,
+    // Last box bounded, with its bounds over the whole motion.
+    bounds: OnceLock<(BoundingBox, BoundingBox)>,
+}
+
+impl PartialEq for Motion {
+    fn eq(&self, other: &Self) -> bool {
+        self.keyframes == other.keyframes
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Keyframe {
+    time: f64,
+    transform: Matrix4,
+    decomposed: DecomposedTransform,
+}
+
+impl Motion {
+    // Moves from `start` at time 0 to `end` at time 1.
+    pub fn linear(start: Matrix4, end: Matrix4) -> Self {
+        Self::from_sorted(vec![(0., start), (1., end)])
+    }
+
+    // Keyframes can come in any order, but their times must be finite.
+    pub fn keyframed(mut keyframes: Vec<(f64, Matrix4)>) -> Result<Self, String> {
+        if keyframes.is_empty() {
+            return Err("A motion needs at least one keyframe".to_owned());
+        }
+        if let Some((time, _)) = keyframes.iter().find(|(time, _)| !time.is_finite()) {
+            return Err(format!("Keyframe time {} is not finite", time));
+        }
+
+        keyframes.sort_by(|(t1, _), (t2, _)| t1.total_cmp(t2));
+
+        Ok(Self::from_sorted(keyframes))
+    }
+
+    fn from_sorted(keyframes: Vec<(f64, Matrix4)>) -> Self {
+        let keyframes: Vec<Keyframe> = keyframes
+            .into_iter()
+            .map(|(time, transform)| Keyframe {
+                time,
+                transform,
+                decomposed: DecomposedTransform::from_matrix(&transform),
+            })
+            .collect();
+
+        Self {
+            samples: sampled_transforms(&keyframes),
+            keyframes,
+            bounds: OnceLock::new(),
+        }
+    }
+
+    // Times outside the keyframes hold the first or last transformation.
+    pub fn transform_at(&self, time: f64) -> Matrix4 {
+        let first = &self.keyframes[0];
+        let last = &self.keyframes[self.keyframes.len() - 1];
+
+        if time <= first.time {
+            return first.transform;
+        }
+        if time >= last.time {
+            return last.transform;
+        }
+
+        let next_index = self.keyframes.iter().position(|k| k.time > time).unwrap();
+        let from = &self.keyframes[next_index - 1];
+        let to = &self.keyframes[next_index];
+        let t = (time - from.time) / (to.time - from.time);
+
+        from.decomposed.interpolate(&to.decomposed, t).to_matrix()
+    }
+
+    // Bounds of `bounding_box` transformed at any time during the motion.
+    pub(crate) fn bounds(&self, bounding_box: &BoundingBox) -> BoundingBox {
+        if let Some((bounded, bounds)) = self.bounds.get() {
+            if bounded == bounding_box {
+                return *bounds;
+            }
+        }
+
+        let mut points = vec![];
+        let mut longest_step: f64 = 0.;
+        for corner in bounding_box.points() {
+            let path: Vec<Tuple> = self.samples.iter().map(|t| *t * corner).collect();
+            longest_step = path
+                .windows(2)
+                .map(|step| (step[1] - step[0]).magnitude())
+                .fold(longest_step, f64::max);
+            points.extend(path);
+        }
+
+        // Between two samples a rotating corner bulges out of the line
+        // joining them, but never by more than the step.
+        let padding = Tuple::vector(longest_step, longest_step, longest_step);
+        let swept = BoundingBox::from_points(&points);
+        let bounds = BoundingBox::new(swept.min() - padding, swept.max() + padding);
+        let _ = self.bounds.set((*bounding_box, bounds));
+
+        bounds
+    }
+}
+
+fn sampled_transforms(keyframes: &[Keyframe]) -> Vec<Matrix4> {
+    let mut transforms = vec![keyframes[0].transform];
+
+    for window in keyframes.windows(2) {
+        let (from, to) = (&window[0], &window[1]);
+
+        for step in 1..=BOUNDING_STEPS {
+            let t = step as f64 / BOUNDING_STEPS as f64;
+
+            transforms.push(from.decomposed.interpolate(&to.decomposed, t).to_matrix());
+        }
+    }
+
+    transforms
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn a_motion_returns_its_keyframes_at_their_times() {
+        let start = Matrix4::translation(-1., 0., 0.);
+        let end = Matrix4::translation(1., 0., 0.) * Matrix4::rotation_y(1.);
+        let motion = Motion::linear(start, end);
+
+        assert_eq!(motion.transform_at(0.), start);
+        assert_eq!(motion.transform_at(1.), end);
+        assert_eq!(motion.transform_at(-5.), start);
+        assert_eq!(motion.transform_at(5.), end);
+    }
+
+    #[test]
+    fn interpolating_translation_and_scale() {
+        let motion = Motion::linear(
+            Matrix4::translation(0., 0., 0.),
+            Matrix4::translation(4., 2., 0.) * Matrix4::scaling(3., 3., 3.),
+        );
+
+        assert_eq!(
+            motion.transform_at(0.5),
+            Matrix4::translation(2., 1., 0.) * Matrix4::scaling(2., 2., 2.)
+        );
+    }
+
+    #[test]
+    fn interpolating_a_rotation_follows_the_arc() {
+        let motion = Motion::linear(Matrix4::identity(), Matrix4::rotation_y(PI / 2.));
+
+        assert_eq!(motion.transform_at(0.5), Matrix4::rotation_y(PI / 4.));
+    }
+
+    #[test]
+    fn interpolating_between_several_keyframes() {
+        let motion = Motion::keyframed(vec![
+            (2., Matrix4::translation(0., 10., 0.)),
+            (0., Matrix4::translation(0., 0., 0.)),
+            (1., Matrix4::translation(0., 2., 0.)),
+        ])
+        .unwrap();
+
+        assert_eq!(motion.transform_at(0.5), Matrix4::translation(0., 1., 0.));
+        assert_eq!(motion.transform_at(1.5), Matrix4::translation(0., 6., 0.));
+    }
+
+    #[test]
+    fn keyframes_need_finite_times() {
+        assert!(Motion::keyframed(vec![]).is_err());
+        assert!(Motion::keyframed(vec![(f64::NAN, Matrix4::identity())]).is_err());
+        assert!(Motion::keyframed(vec![
+            (0., Matrix4::identity()),
+            (f64::INFINITY, Matrix4::identity()),
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn bounds_contain_a_spinning_rod_throughout_the_motion() {
+        let rod = BoundingBox::new(Tuple::point(-4., -0.1, -0.1), Tuple::point(4., 0.1, 0.1));
+        let motion = Motion::linear(Matrix4::identity(), Matrix4::rotation_z(0.9 * PI));
+
+        let bounds = motion.bounds(&rod);
+
+        for step in 0..=100 {
+            let transform = motion.transform_at(step as f64 / 100.);
+            for corner in rod.points() {
+                let Tuple { x, y, z, .. } = transform * corner;
+                assert!(bounds.min().x <= x && x <= bounds.max().x);
+                assert!(bounds.min().y <= y && y <= bounds.max().y);
+                assert!(bounds.min().z <= z && z <= bounds.max().z);
+            }
+        }
+        assert_eq!(motion.bounds(&rod), bounds);
+    }
+}
@@ -0,0 +1,276 @@
+use crate::{math::tuple::Tuple, sampling::Rng};
+
+const DEFAULT_OCTAVES: usize = 4;
+
+// Edge midpoints of a cube, the gradient set from Perlin's "Improving
+// Noise" (2002).
+const GRADIENTS: [(f64, f64, f64); 12] = [
+    (1., 1., 0.),
+    (-1., 1., 0.),
+    (1., -1., 0.),
+    (-1., -1., 0.),
+    (1., 0., 1.),
+    (-1., 0., 1.),
+    (1., 0., -1.),
+    (-1., 0., -1.),
+    (0., 1., 1.),
+    (0., -1., 1.),
+    (0., 1., -1.),
+    (0., -1., -1.),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoiseKind {
+    Perlin,
+    Simplex,
+}
+
+// Seeded 3D gradient noise. Gradients are hashed from the lattice
+// coordinates and the seed, so there is no permutation table to build and
+// the same seed always gives the same field.
+//
+// `octaves`, `lacunarity` and `gain` shape the fractal sums: each octave
+// has `lacunarity` times the frequency and `gain` times the amplitude of the
+// previous one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Noise {
+    pub kind: NoiseKind,
+    pub seed: u64,
+    pub octaves: usize,
+    pub lacunarity: f64,
+    pub gain: f64,
+}
+
+impl Noise {
+    pub fn new(kind: NoiseKind, seed: u64) -> Self {
+        Self {
+            kind,
+            seed,
+            octaves: DEFAULT_OCTAVES,
+            lacunarity: 2.,
+            gain: 0.5,
+        }
+    }
+
+    pub fn perlin(seed: u64) -> Self {
+        Self::new(NoiseKind::Perlin, seed)
+    }
+
+    pub fn simplex(seed: u64) -> Self {
+        Self::new(NoiseKind::Simplex, seed)
+    }
+
+    // Roughly in [-1, 1], and 0 on every lattice point for Perlin noise.
+    pub fn value(&self, point: Tuple) -> f64 {
+        match self.kind {
+            NoiseKind::Perlin => perlin(self.seed, point.x, point.y, point.z),
+            NoiseKind::Simplex => simplex(self.seed, point.x, point.y, point.z),
+        }
+    }
+
+    // Fractal Brownian motion: octaves of noise summed and scaled back into
+    // roughly [-1, 1].
+    pub fn fbm(&self, point: Tuple) -> f64 {
+        self.fractal(point, |value| value)
+    }
+
+    // Like `fbm` but summing absolute values, which gives the creases used
+    // for marble veins and flames. In [0, 1].
+    pub fn turbulence(&self, point: Tuple) -> f64 {
+        self.fractal(point, f64::abs)
+    }
+
+    fn fractal(&self, point: Tuple, octave: impl Fn(f64) -> f64) -> f64 {
+        let mut total = 0.;
+        let mut amplitude = 1.;
+        let mut frequency = 1.;
+        let mut max = 0.;
+
+        for _ in 0..self.octaves.max(1) {
+            total += octave(self.value(point * frequency)) * amplitude;
+            max += amplitude;
+            amplitude *= self.gain;
+            frequency *= self.lacunarity;
+        }
+
+        total / max
+    }
+}
+
+fn gradient(seed: u64, x: i64, y: i64, z: i64) -> (f64, f64, f64) {
+    let hash = Rng::from_seeds(&[seed, x as u64, y as u64, z as u64]).next_u64();
+
+    GRADIENTS[(hash % GRADIENTS.len() as u64) as usize]
+}
+
+fn dot_gradient(seed: u64, cell: (i64, i64, i64), offset: (f64, f64, f64)) -> f64 {
+    let (gx, gy, gz) = gradient(seed, cell.0, cell.1, cell.2);
+
+    gx * offset.0 + gy * offset.1 + gz * offset.2
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6. - 15.) + 10.)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+fn perlin(seed: u64, x: f64, y: f64, z: f64) -> f64 {
+    let (x0, y0, z0) = (x.floor(), y.floor(), z.floor());
+    let (fx, fy, fz) = (x - x0, y - y0, z - z0);
+    let (ix, iy, iz) = (x0 as i64, y0 as i64, z0 as i64);
+
+    let corner = |dx: i64, dy: i64, dz: i64| {
+        dot_gradient(
+            seed,
+            (ix + dx, iy + dy, iz + dz),
+            (fx - dx as f64, fy - dy as f64, fz - dz as f64),
+        )
+    };
+    let (u, v, w) = (fade(fx), fade(fy), fade(fz));
+
+    lerp(
+        w,
+        lerp(
+            v,
+            lerp(u, corner(0, 0, 0), corner(1, 0, 0)),
+            lerp(u, corner(0, 1, 0), corner(1, 1, 0)),
+        ),
+        lerp(
+            v,
+            lerp(u, corner(0, 0, 1), corner(1, 0, 1)),
+            lerp(u, corner(0, 1, 1), corner(1, 1, 1)),
+        ),
+    )
+}
+
+// Stefan Gustavson's formulation of Perlin's simplex noise.
+fn simplex(seed: u64, x: f64, y: f64, z: f64) -> f64 {
+    const SKEW: f64 = 1. / 3.;
+    const UNSKEW: f64 = 1. / 6.;
+
+    // Find the simplex cell containing the point
+    let s = (x + y + z) * SKEW;
+    let (i, j, k) = ((x + s).floor(), (y + s).floor(), (z + s).floor());
+    let t = (i + j + k) * UNSKEW;
+    let x0 = (x - (i - t), y - (j - t), z - (k - t));
+
+    // Which of the six tetrahedra in the cell the point is in
+    let (first, second) = if x0.0 >= x0.1 {
+        if x0.1 >= x0.2 {
+            ((1, 0, 0), (1, 1, 0))
+        } else if x0.0 >= x0.2 {
+            ((1, 0, 0), (1, 0, 1))
+        } else {
+            ((0, 0, 1), (1, 0, 1))
+        }
+    } else if x0.1 < x0.2 {
+        ((0, 0, 1), (0, 1, 1))
+    } else if x0.0 < x0.2 {
+        ((0, 1, 0), (0, 1, 1))
+    } else {
+        ((0, 1, 0), (1, 1, 0))
+    };
+
+    let cell = (i as i64, j as i64, k as i64);
+    let contribution = |corner: (i64, i64, i64)| {
+        let unskew = (corner.0 + corner.1 + corner.2) as f64 * UNSKEW;
+        let offset = (
+            x0.0 - corner.0 as f64 + unskew,
+            x0.1 - corner.1 as f64 + unskew,
+            x0.2 - corner.2 as f64 + unskew,
+        );
+        let falloff = 0.6 - offset.0 * offset.0 - offset.1 * offset.1 - offset.2 * offset.2;
+
+        if falloff < 0. {
+            0.
+        } else {
+            let lattice = (cell.0 + corner.0, cell.1 + corner.1, cell.2 + corner.2);
+            falloff.powi(4) * dot_gradient(seed, lattice, offset)
+        }
+    };
+
+    32. * (contribution((0, 0, 0))
+        + contribution(first)
+        + contribution(second)
+        + contribution((1, 1, 1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_points() -> impl Iterator<Item = Tuple> {
+        (0..2000).map(|i| {
+            let i = i as f64;
+            Tuple::point(i * 0.137 - 40., i * 0.291 - 100., i * 0.053)
+        })
+    }
+
+    #[test]
+    fn perlin_noise_is_zero_on_the_lattice() {
+        let noise = Noise::perlin(3);
+
+        assert_eq!(noise.value(Tuple::point(0., 0., 0.)), 0.);
+        assert_eq!(noise.value(Tuple::point(4., -2., 7.)), 0.);
+    }
+
+    #[test]
+    fn noise_is_deterministic_for_a_seed() {
+        for kind in [NoiseKind::Perlin, NoiseKind::Simplex] {
+            let point = Tuple::point(1.3, -0.7, 2.45);
+
+            assert_eq!(
+                Noise::new(kind, 11).value(point),
+                Noise::new(kind, 11).value(point)
+            );
+            assert_ne!(
+                Noise::new(kind, 11).value(point),
+                Noise::new(kind, 12).value(point)
+            );
+        }
+    }
+
+    #[test]
+    fn noise_stays_in_range_and_varies() {
+        for kind in [NoiseKind::Perlin, NoiseKind::Simplex] {
+            let noise = Noise::new(kind, 5);
+            let values: Vec<f64> = sample_points().map(|p| noise.value(p)).collect();
+
+            assert!(values.iter().all(|v| v.abs() <= 1.05));
+            assert!(values.iter().any(|v| *v > 0.3));
+            assert!(values.iter().any(|v| *v < -0.3));
+        }
+    }
+
+    #[test]
+    fn noise_is_continuous() {
+        for kind in [NoiseKind::Perlin, NoiseKind::Simplex] {
+            let noise = Noise::new(kind, 9);
+            let step = Tuple::vector(1e-4, 1e-4, 1e-4);
+
+            for point in sample_points() {
+                assert!((noise.value(point) - noise.value(point + step)).abs() < 0.01);
+            }
+        }
+    }
+
+    #[test]
+    fn a_single_octave_of_fbm_is_plain_noise() {
+        let mut noise = Noise::simplex(2);
+        noise.octaves = 1;
+        let point = Tuple::point(0.3, 0.6, 0.9);
+
+        assert_eq!(noise.fbm(point), noise.value(point));
+        assert_eq!(noise.turbulence(point), noise.value(point).abs());
+    }
+
+    #[test]
+    fn turbulence_is_never_negative() {
+        let noise = Noise::perlin(1);
+
+        assert!(sample_points().all(|p| (0. ..=1.).contains(&noise.turbulence(p))));
+    }
+}
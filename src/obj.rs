@@ -1,15 +1,42 @@
 use std::{
-    collections::{hash_map::DefaultHasher, HashMap},
-    hash::BuildHasherDefault,
+    f64::consts::PI,
+    io::{Error, ErrorKind},
 };
 
 use crate::{
     math::tuple::Tuple,
-    shape::{triangle::Triangle, Object, Shape},
+    mesh::{Mesh, NormalWeighting},
+    shape::{self, triangle::Triangle, Object},
 };
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ObjSettings {
+    // Smooth faces without `vn` normals with generated ones, instead of
+    // shading them flat. Faces before any `s` line are smoothed together.
+    pub generate_normals: bool,
+    // Faces meeting at a sharper angle than this, in radians, keep a hard
+    // edge between them.
+    pub crease_angle: f64,
+    pub weighting: NormalWeighting,
+}
+
+impl Default for ObjSettings {
+    fn default() -> Self {
+        Self {
+            generate_normals: false,
+            crease_angle: PI / 3.,
+            weighting: NormalWeighting::default(),
+        }
+    }
+}
+
 pub struct WavefrontObj {
-    groups: HashMap<String, Vec<Triangle>, BuildHasherDefault<DefaultHasher>>,
+    // In the order the groups first appear in the file.
+    groups: Vec<(String, Vec<Triangle>)>,
+    // Faces left out because they have no area.
+    pub degenerate_faces: usize,
+    // Faces shaded flat because their `vn` normals were unusable.
+    pub flat_fallbacks: usize,
     #[cfg(test)]
     vertices: Vec<Tuple>,
     #[cfg(test)]
@@ -21,32 +48,58 @@ impl WavefrontObj {
         Object::group(
             self.groups
                 .into_iter()
-                .map(|(_, triangles)| {
-                    let triangles = triangles
-                        .into_iter()
-                        .map(|triangle| Object::new(Shape::Triangle(triangle)))
-                        .collect();
+                .map(|(name, triangles)| {
+                    let triangles = triangles.into_iter().map(Object::triangle).collect();
 
-                    Object::group(triangles)
+                    // Names that can't be used in a path are left out, and
+                    // the group is found by its index instead
+                    let mut group = Object::group(triangles);
+                    group.name = shape::check_name(&name).ok().map(|_| name);
+
+                    group
                 })
                 .collect(),
         )
     }
 
+    #[cfg(test)]
+    fn group(&self, name: &str) -> &[Triangle] {
+        self.groups
+            .iter()
+            .find(|(group, _)| group == name)
+            .map_or(&[], |(_, triangles)| triangles)
+    }
+
     pub fn from_file(file_path: &str) -> std::io::Result<Object> {
+        WavefrontObj::from_file_with(file_path, &ObjSettings::default())
+    }
+
+    // Only the shapes; `from_file_contents_with` also tells how many faces
+    // were left out or shaded flat.
+    pub fn from_file_with(file_path: &str, settings: &ObjSettings) -> std::io::Result<Object> {
         let file_contents = std::fs::read_to_string(file_path)?;
-        let obj = WavefrontObj::from_file_contents(&file_contents)?;
+        let obj = WavefrontObj::from_file_contents_with(&file_contents, settings)?;
         Ok(obj.to_group())
     }
 
     pub fn from_file_contents(file_contents: &str) -> std::io::Result<WavefrontObj> {
+        WavefrontObj::from_file_contents_with(file_contents, &ObjSettings::default())
+    }
+
+    pub fn from_file_contents_with(
+        file_contents: &str,
+        settings: &ObjSettings,
+    ) -> std::io::Result<WavefrontObj> {
         let mut vertices = vec![];
         let mut normals = vec![];
 
         let mut current_group = "default";
+        let mut smoothing_group = 1;
+        // Faces waiting for generated normals, with their group names
+        let mut unsmoothed: Vec<(&str, [usize; 3], u32)> = vec![];
 
-        let map_hasher = BuildHasherDefault::<DefaultHasher>::default();
-        let mut groups: HashMap<String, Vec<Triangle>, _> = HashMap::with_hasher(map_hasher);
+        let mut groups = vec![];
+        let mut tally = Tally::default();
 
         for line in file_contents.lines() {
             if let Some((node_type, rest)) = line.split_once(" ") {
@@ -68,6 +121,12 @@ impl WavefrontObj {
                         normals.push(Tuple::vector(x, y, z));
                     }
                     "f" => {
+                        // Faces with generated normals are added last, so
+                        // the group takes its place in the order here
+                        if !groups.iter().any(|(name, _)| name == current_group) {
+                            groups.push((current_group.to_owned(), vec![]));
+                        }
+
                         let rest = rest.split_ascii_whitespace();
                         let mut indices = rest.map(|attr| {
                             let mut it = attr.split('/').map(|i| i.parse::<usize>().ok());
@@ -82,37 +141,68 @@ impl WavefrontObj {
                         let (start_index, _, normal1) = indices.next().unwrap();
                         for window in indices.collect::<Vec<_>>().windows(2) {
                             if let [(index2, _, normal2), (index3, _, normal3)] = window {
-                                let entry = groups.entry(current_group.to_owned());
-                                let triangle = match (normal1, normal2, normal3) {
-                                    (Some(n1), Some(n2), Some(n3)) => Triangle::smooth(
-                                        vertices[start_index],
-                                        vertices[*index2],
-                                        vertices[*index3],
-                                        normals[n1],
-                                        normals[*n2],
-                                        normals[*n3],
-                                    ),
-                                    _ => Triangle::new(
-                                        vertices[start_index],
-                                        vertices[*index2],
-                                        vertices[*index3],
-                                    ),
+                                let face = [start_index, *index2, *index3];
+                                let face_normals = match (normal1, normal2, normal3) {
+                                    (Some(n1), Some(n2), Some(n3)) => {
+                                        Some([normals[n1], normals[*n2], normals[*n3]])
+                                    }
+                                    _ if settings.generate_normals => {
+                                        unsmoothed.push((current_group, face, smoothing_group));
+                                        continue;
+                                    }
+                                    _ => None,
                                 };
 
-                                entry.or_insert(vec![]).push(triangle);
+                                tally.count(add_triangle(
+                                    &mut groups,
+                                    current_group,
+                                    &vertices,
+                                    face,
+                                    face_normals,
+                                ));
                             }
                         }
                     }
                     "g" => {
                         current_group = rest;
                     }
+                    "s" => {
+                        // `s off` and `s 0` turn smoothing off
+                        smoothing_group = rest.trim().parse().unwrap_or(0);
+                    }
                     _ => {}
                 }
             }
         }
 
+        if !unsmoothed.is_empty() {
+            let indices: Vec<usize> = unsmoothed.iter().flat_map(|(_, face, _)| *face).collect();
+            let smoothing_groups: Vec<u32> =
+                unsmoothed.iter().map(|(_, _, group)| *group).collect();
+            let mesh = Mesh::new(&vertices, &indices)
+                .and_then(|mesh| mesh.with_smoothing_groups(&smoothing_groups))
+                .map_err(|message| Error::new(ErrorKind::InvalidData, message))?
+                .with_weighting(settings.weighting);
+
+            let generated = mesh.generate_normals(settings.crease_angle);
+            for ((group, face, _), face_normals) in unsmoothed.into_iter().zip(generated) {
+                tally.count(add_triangle(
+                    &mut groups,
+                    group,
+                    &vertices,
+                    face,
+                    Some(face_normals),
+                ));
+            }
+        }
+
+        // Groups whose faces were all left out
+        groups.retain(|(_, triangles)| !triangles.is_empty());
+
         Ok(WavefrontObj {
             groups,
+            degenerate_faces: tally.degenerate,
+            flat_fallbacks: tally.flat,
             #[cfg(test)]
             vertices,
             #[cfg(test)]
@@ -120,3 +210,170 @@ impl WavefrontObj {
         })
     }
 }
+
+// What happened to a face handed to `add_triangle`.
+enum Outcome {
+    Added,
+    Flat,
+    Degenerate,
+}
+
+// Faces that didn't make it in as they were given.
+#[derive(Default)]
+struct Tally {
+    flat: usize,
+    degenerate: usize,
+}
+
+impl Tally {
+    fn count(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Added => {}
+            Outcome::Flat => self.flat += 1,
+            Outcome::Degenerate => self.degenerate += 1,
+        }
+    }
+}
+
+// Falls back to flat shading when the normals are unusable, and leaves out
+// degenerate faces, which could never be hit.
+fn add_triangle(
+    groups: &mut Vec<(String, Vec<Triangle>)>,
+    group: &str,
+    vertices: &[Tuple],
+    [a, b, c]: [usize; 3],
+    normals: Option<[Tuple; 3]>,
+) -> Outcome {
+    let (p1, p2, p3) = (vertices[a], vertices[b], vertices[c]);
+    let Ok(flat) = Triangle::new(p1, p2, p3) else {
+        return Outcome::Degenerate;
+    };
+    let (triangle, outcome) = match normals {
+        Some([n1, n2, n3]) => match Triangle::smooth(p1, p2, p3, n1, n2, n3) {
+            Ok(smooth) => (smooth, Outcome::Added),
+            Err(_) => (flat, Outcome::Flat),
+        },
+        None => (flat, Outcome::Added),
+    };
+
+    // Faces usually come in runs of the same group, so look from the end
+    match groups.iter_mut().rev().find(|(name, _)| name == group) {
+        Some((_, triangles)) => triangles.push(triangle),
+        None => groups.push((group.to_owned(), vec![triangle])),
+    }
+
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shape::{LocalHit, Primitive};
+
+    // Two faces meeting at a right angle along the z axis, like a roof.
+    const ROOF: &str = "\
+v 0 0 0
+v 0 0 1
+v -1 -1 0
+v 1 -1 0
+f 1 2 3
+f 2 1 4
+";
+
+    fn normals_at_first_corner(obj: &str, settings: &ObjSettings) -> Vec<Tuple> {
+        let obj = WavefrontObj::from_file_contents_with(obj, settings).unwrap();
+
+        obj.group("default")
+            .iter()
+            .map(|triangle| {
+                triangle.local_normal_at(Tuple::point(0., 0., 0.), LocalHit::with_uv(0., 0., 0.))
+            })
+            .collect()
+    }
+
+    fn generating(crease_angle: f64) -> ObjSettings {
+        ObjSettings {
+            generate_normals: true,
+            crease_angle,
+            ..ObjSettings::default()
+        }
+    }
+
+    #[test]
+    fn faces_without_normals_are_flat_by_default() {
+        let normals = normals_at_first_corner(ROOF, &ObjSettings::default());
+
+        assert_eq!(normals[0], Tuple::vector(-1., 1., 0.).normalize());
+        assert_eq!(normals[1], Tuple::vector(1., 1., 0.).normalize());
+    }
+
+    #[test]
+    fn generated_normals_smooth_edges_below_the_crease_angle() {
+        let smooth = normals_at_first_corner(ROOF, &generating(PI * 0.75));
+        assert_eq!(smooth[0], Tuple::vector(0., 1., 0.));
+        assert_eq!(smooth[1], Tuple::vector(0., 1., 0.));
+
+        let creased = normals_at_first_corner(ROOF, &generating(PI / 3.));
+        assert_eq!(creased[0], Tuple::vector(-1., 1., 0.).normalize());
+    }
+
+    #[test]
+    fn smoothing_groups_are_respected() {
+        let apart = ROOF.replace("f 1 2 3\n", "s 1\nf 1 2 3\ns 2\n");
+        let normals = normals_at_first_corner(&apart, &generating(PI * 0.75));
+        assert_eq!(normals[0], Tuple::vector(-1., 1., 0.).normalize());
+
+        let off = format!("s off\n{}", ROOF);
+        let normals = normals_at_first_corner(&off, &generating(PI * 0.75));
+        assert_eq!(normals[1], Tuple::vector(1., 1., 0.).normalize());
+    }
+
+    #[test]
+    fn unusable_faces_and_normals_are_counted() {
+        let obj = "\
+v 0 0 0
+v 1 0 0
+v 0 1 0
+v 2 0 0
+vn 0 0 1
+vn 0 0 0
+f 1//1 2//1 3//2
+f 1 2 4
+f 1//1 2//1 3//1
+";
+        let obj = WavefrontObj::from_file_contents(obj).unwrap();
+
+        assert_eq!(obj.degenerate_faces, 1);
+        assert_eq!(obj.flat_fallbacks, 1);
+        assert_eq!(obj.group("default").len(), 2);
+    }
+
+    #[test]
+    fn groups_are_named_after_their_obj_group() {
+        let contents = ROOF.replace("f 2 1 4\n", "g Lid\nf 2 1 4\n");
+        let group = WavefrontObj::from_file_contents(&contents)
+            .unwrap()
+            .to_group();
+
+        let lid = group.find("Lid").unwrap();
+        assert_eq!(lid.children().len(), 1);
+    }
+
+    #[test]
+    fn groups_keep_file_order_and_unusable_names_are_dropped() {
+        let contents = ROOF
+            .replace("f 1 2 3\n", "g z\nf 1 2 3\n")
+            .replace("f 2 1 4\n", "g a/b\nf 2 1 4\ng #1\nf 1 2 3\ng y\nf 2 1 4\n");
+        let group = WavefrontObj::from_file_contents(&contents)
+            .unwrap()
+            .to_group();
+
+        let names: Vec<_> = group
+            .children()
+            .iter()
+            .map(|child| child.name.as_deref())
+            .collect();
+        assert_eq!(names, [Some("z"), None, None, Some("y")]);
+        assert_eq!(group.find("#3").unwrap().name.as_deref(), Some("y"));
+    }
+}
@@ -0,0 +1,25 @@
+// Settings for estimating how much of the sky above a point is hidden by
+// nearby geometry. Only objects closer than `max_distance` count, so open
+// floors stay bright while corners and contact areas darken.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AmbientOcclusion {
+    pub samples: usize,
+    pub max_distance: f64,
+}
+
+impl AmbientOcclusion {
+    pub fn new(samples: usize, max_distance: f64) -> Self {
+        Self {
+            samples,
+            max_distance,
+        }
+    }
+}
+
+// A few rays reaching one unit out, enough for the AOV pass of a world that
+// doesn't set its own.
+impl Default for AmbientOcclusion {
+    fn default() -> Self {
+        Self::new(16, 1.)
+    }
+}
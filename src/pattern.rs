@@ -1,17 +1,82 @@
-use crate::{color::Color, math::matrix4::Matrix4, math::tuple::Tuple, shape::SimpleObject};
+use std::{f64::consts::PI, sync::Arc};
 
-#[derive(Clone, Copy, Debug)]
+use crate::{
+    color::Color, math::matrix4::Matrix4, math::tuple::Tuple, noise::Noise, shape::SimpleObject,
+};
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct Pattern {
     pub transform: Matrix4,
     pattern_type: PatternType,
+    perturbation: Option<Perturbation>,
+}
+
+// What fills one of the slots of a pattern: a plain color, or another
+// pattern evaluated in its own space (the parent's pattern space moved by
+// the nested pattern's transform).
+#[derive(Clone, Debug, PartialEq)]
+pub enum Paint {
+    Solid(Color),
+    Pattern(Arc<NestedPattern>),
+}
+
+// A pattern in a slot of another one, fixed once it is nested.
+#[derive(Debug, PartialEq)]
+pub struct NestedPattern {
+    pattern: Pattern,
+    inverse_transform: Matrix4,
+}
+
+impl Paint {
+    fn color_at(&self, point: Tuple) -> Color {
+        match self {
+            Paint::Solid(color) => *color,
+            Paint::Pattern(nested) => nested.pattern.pattern_at(nested.inverse_transform * point),
+        }
+    }
+}
+
+impl From<Color> for Paint {
+    fn from(color: Color) -> Self {
+        Paint::Solid(color)
+    }
 }
 
-#[derive(Clone, Copy, Debug)]
+impl From<Pattern> for Paint {
+    fn from(pattern: Pattern) -> Self {
+        Paint::Pattern(Arc::new(NestedPattern {
+            inverse_transform: pattern.transform.inverse().unwrap(),
+            pattern,
+        }))
+    }
+}
+
+impl PartialEq<Color> for Paint {
+    fn eq(&self, color: &Color) -> bool {
+        matches!(self, Paint::Solid(solid) if solid == color)
+    }
+}
+
+// Moves each sample point by up to `scale` along every axis before the
+// pattern is evaluated, so crisp patterns get wobbly edges.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Perturbation {
+    noise: Noise,
+    scale: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
 enum PatternType {
     Striped(StripePattern),
     Gradient(GradientPattern),
+    RadialGradient(RadialGradientPattern),
     Ring(RingPattern),
     Checkered(CheckeredPattern),
+    Marble(MarblePattern),
+    Wood(WoodPattern),
+    Clouds(CloudsPattern),
+    Blend(BlendPattern),
+    Mask(MaskPattern),
     #[cfg(test)]
     TestPattern,
 }
@@ -21,6 +86,7 @@ impl Pattern {
         Self {
             transform: Matrix4::identity(),
             pattern_type,
+            perturbation: None,
         }
     }
 
@@ -28,111 +94,194 @@ impl Pattern {
         &mut self.transform
     }
 
-    pub fn striped(a: Color, b: Color) -> Self {
+    pub fn striped(a: impl Into<Paint>, b: impl Into<Paint>) -> Self {
         Self::new(PatternType::Striped(StripePattern::new(a, b)))
     }
 
     #[allow(dead_code)]
-    pub fn gradient(a: Color, b: Color) -> Self {
+    pub fn gradient(a: impl Into<Paint>, b: impl Into<Paint>) -> Self {
         Self::new(PatternType::Gradient(GradientPattern::new(a, b)))
     }
 
+    // Fades from `a` to `b` moving away from the y axis, repeating every
+    // unit.
+    pub fn radial_gradient(a: impl Into<Paint>, b: impl Into<Paint>) -> Self {
+        Self::new(PatternType::RadialGradient(RadialGradientPattern::new(
+            a, b,
+        )))
+    }
+
     #[allow(dead_code)]
-    pub fn ring(a: Color, b: Color) -> Self {
+    pub fn ring(a: impl Into<Paint>, b: impl Into<Paint>) -> Self {
         Self::new(PatternType::Ring(RingPattern::new(a, b)))
     }
 
-    pub fn checkered(a: Color, b: Color) -> Self {
+    pub fn checkered(a: impl Into<Paint>, b: impl Into<Paint>) -> Self {
         Self::new(PatternType::Checkered(CheckeredPattern::new(a, b)))
     }
 
+    // Veins following the x axis, distorted by turbulence.
+    pub fn marble(a: impl Into<Paint>, b: impl Into<Paint>, noise: Noise) -> Self {
+        Self::new(PatternType::Marble(MarblePattern::new(a, b, noise)))
+    }
+
+    // Rings around the y axis, warped by noise.
+    pub fn wood(a: impl Into<Paint>, b: impl Into<Paint>, noise: Noise) -> Self {
+        Self::new(PatternType::Wood(WoodPattern::new(a, b, noise)))
+    }
+
+    // Soft blend between `a` and `b` driven by fractal noise.
+    pub fn clouds(a: impl Into<Paint>, b: impl Into<Paint>, noise: Noise) -> Self {
+        Self::new(PatternType::Clouds(CloudsPattern::new(a, b, noise)))
+    }
+
+    // Weighted average of two paints; a `weight` of 0 gives only `a`.
+    pub fn blend(a: impl Into<Paint>, b: impl Into<Paint>, weight: f64) -> Self {
+        Self::new(PatternType::Blend(BlendPattern {
+            a: a.into(),
+            b: b.into(),
+            weight,
+        }))
+    }
+
+    // Shows `a` where `mask` is black and `b` where it is white, mixing the
+    // two in between. The mask's brightness is the mean of its channels.
+    pub fn mask(mask: impl Into<Paint>, a: impl Into<Paint>, b: impl Into<Paint>) -> Self {
+        Self::new(PatternType::Mask(MaskPattern {
+            mask: mask.into(),
+            a: a.into(),
+            b: b.into(),
+        }))
+    }
+
+    pub fn perturbed(self, noise: Noise, scale: f64) -> Self {
+        Self {
+            perturbation: Some(Perturbation { noise, scale }),
+            ..self
+        }
+    }
+
     fn pattern_at(&self, point: Tuple) -> Color {
-        match self.pattern_type {
+        let point = match self.perturbation {
+            Some(perturbation) => perturbation.apply(point),
+            None => point,
+        };
+
+        match &self.pattern_type {
             PatternType::Striped(pattern_type) => pattern_type.pattern_at(point),
             PatternType::Gradient(pattern_type) => pattern_type.pattern_at(point),
+            PatternType::RadialGradient(pattern_type) => pattern_type.pattern_at(point),
             PatternType::Ring(pattern_type) => pattern_type.pattern_at(point),
             PatternType::Checkered(pattern_type) => pattern_type.pattern_at(point),
+            PatternType::Marble(pattern_type) => pattern_type.pattern_at(point),
+            PatternType::Wood(pattern_type) => pattern_type.pattern_at(point),
+            PatternType::Clouds(pattern_type) => pattern_type.pattern_at(point),
+            PatternType::Blend(pattern_type) => pattern_type.pattern_at(point),
+            PatternType::Mask(pattern_type) => pattern_type.pattern_at(point),
             #[cfg(test)]
             PatternType::TestPattern => tests::TestPattern::pattern_at(point),
         }
     }
 
-    pub(crate) fn pattern_at_object(self, object: SimpleObject, world_point: Tuple) -> Color {
+    pub(crate) fn pattern_at_object(&self, object: SimpleObject, world_point: Tuple) -> Color {
         let object_point = object.transform.inverse().unwrap() * world_point;
         let pattern_point = self.transform.inverse().unwrap() * object_point;
 
         self.pattern_at(pattern_point)
     }
 }
-#[derive(Clone, Copy, Debug)]
+
+impl Perturbation {
+    // The three axes read the noise at far apart points so they move
+    // independently.
+    fn apply(&self, point: Tuple) -> Tuple {
+        let offset = |shift: f64| self.noise.fbm(point + Tuple::vector(shift, shift, shift));
+
+        point + Tuple::vector(offset(0.), offset(31.4), offset(-57.1)) * self.scale
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 struct StripePattern {
-    a: Color,
-    b: Color,
+    a: Paint,
+    b: Paint,
 }
 
 impl StripePattern {
-    pub fn new(a: Color, b: Color) -> Self {
-        Self { a, b }
+    pub fn new(a: impl Into<Paint>, b: impl Into<Paint>) -> Self {
+        Self {
+            a: a.into(),
+            b: b.into(),
+        }
     }
 
     pub fn pattern_at(&self, point: Tuple) -> Color {
         if point.x.floor() as i32 % 2 == 0 {
-            self.a
+            self.a.color_at(point)
         } else {
-            self.b
+            self.b.color_at(point)
         }
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 struct GradientPattern {
-    a: Color,
-    b: Color,
+    a: Paint,
+    b: Paint,
 }
 
 impl GradientPattern {
-    pub fn new(a: Color, b: Color) -> Self {
-        Self { a, b }
+    pub fn new(a: impl Into<Paint>, b: impl Into<Paint>) -> Self {
+        Self {
+            a: a.into(),
+            b: b.into(),
+        }
     }
 
     pub fn pattern_at(&self, point: Tuple) -> Color {
         let t = point.x - point.x.floor();
 
-        self.a + (self.b - self.a) * t
+        mix(&self.a, &self.b, point, t)
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Clone, Debug, PartialEq)]
 struct RingPattern {
-    a: Color,
-    b: Color,
+    a: Paint,
+    b: Paint,
 }
 
 impl RingPattern {
-    pub fn new(a: Color, b: Color) -> Self {
-        Self { a, b }
+    pub fn new(a: impl Into<Paint>, b: impl Into<Paint>) -> Self {
+        Self {
+            a: a.into(),
+            b: b.into(),
+        }
     }
 
     pub fn pattern_at(&self, point: Tuple) -> Color {
         let p = (point.x.powi(2) + point.z.powi(2)).floor() as i32 % 2 == 0;
 
         if p {
-            self.a
+            self.a.color_at(point)
         } else {
-            self.b
+            self.b.color_at(point)
         }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Clone, Debug, PartialEq)]
 struct CheckeredPattern {
-    a: Color,
-    b: Color,
+    a: Paint,
+    b: Paint,
 }
 
 impl CheckeredPattern {
-    pub fn new(a: Color, b: Color) -> Self {
-        Self { a, b }
+    pub fn new(a: impl Into<Paint>, b: impl Into<Paint>) -> Self {
+        Self {
+            a: a.into(),
+            b: b.into(),
+        }
     }
 
     pub fn pattern_at(&self, point: Tuple) -> Color {
@@ -140,16 +289,160 @@ impl CheckeredPattern {
         let predicate = sum_floors as i32 % 2 == 0;
 
         if predicate {
-            self.a
+            self.a.color_at(point)
         } else {
-            self.b
+            self.b.color_at(point)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct MarblePattern {
+    a: Paint,
+    b: Paint,
+    noise: Noise,
+    // How far the turbulence bends the veins.
+    strength: f64,
+}
+
+impl MarblePattern {
+    pub fn new(a: impl Into<Paint>, b: impl Into<Paint>, noise: Noise) -> Self {
+        Self {
+            a: a.into(),
+            b: b.into(),
+            noise,
+            strength: 5.,
+        }
+    }
+
+    pub fn pattern_at(&self, point: Tuple) -> Color {
+        let phase = point.x * PI + self.noise.turbulence(point) * self.strength;
+        let t = 0.5 + 0.5 * phase.sin();
+
+        mix(&self.a, &self.b, point, t)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct WoodPattern {
+    a: Paint,
+    b: Paint,
+    noise: Noise,
+    // Rings per unit of distance from the y axis.
+    rings: f64,
+    strength: f64,
+}
+
+impl WoodPattern {
+    pub fn new(a: impl Into<Paint>, b: impl Into<Paint>, noise: Noise) -> Self {
+        Self {
+            a: a.into(),
+            b: b.into(),
+            noise,
+            rings: 4.,
+            strength: 0.1,
+        }
+    }
+
+    pub fn pattern_at(&self, point: Tuple) -> Color {
+        let distance = (point.x.powi(2) + point.z.powi(2)).sqrt();
+        let grain = distance * self.rings + self.noise.fbm(point) * self.strength * self.rings;
+        let t = grain - grain.floor();
+
+        mix(&self.a, &self.b, point, t)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct CloudsPattern {
+    a: Paint,
+    b: Paint,
+    noise: Noise,
+}
+
+impl CloudsPattern {
+    pub fn new(a: impl Into<Paint>, b: impl Into<Paint>, noise: Noise) -> Self {
+        Self {
+            a: a.into(),
+            b: b.into(),
+            noise,
+        }
+    }
+
+    pub fn pattern_at(&self, point: Tuple) -> Color {
+        let t = (0.5 + 0.5 * self.noise.fbm(point)).clamp(0., 1.);
+
+        mix(&self.a, &self.b, point, t)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct RadialGradientPattern {
+    a: Paint,
+    b: Paint,
+}
+
+impl RadialGradientPattern {
+    pub fn new(a: impl Into<Paint>, b: impl Into<Paint>) -> Self {
+        Self {
+            a: a.into(),
+            b: b.into(),
         }
     }
+
+    pub fn pattern_at(&self, point: Tuple) -> Color {
+        let distance = (point.x.powi(2) + point.z.powi(2)).sqrt();
+
+        mix(&self.a, &self.b, point, distance - distance.floor())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct BlendPattern {
+    a: Paint,
+    b: Paint,
+    weight: f64,
+}
+
+impl BlendPattern {
+    pub fn pattern_at(&self, point: Tuple) -> Color {
+        mix(&self.a, &self.b, point, self.weight)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct MaskPattern {
+    mask: Paint,
+    a: Paint,
+    b: Paint,
+}
+
+impl MaskPattern {
+    pub fn pattern_at(&self, point: Tuple) -> Color {
+        let Color { red, green, blue } = self.mask.color_at(point);
+
+        mix(&self.a, &self.b, point, (red + green + blue) / 3.)
+    }
+}
+
+// Linear interpolation between two paints. The ends skip evaluating the
+// paint that does not contribute.
+fn mix(a: &Paint, b: &Paint, point: Tuple, t: f64) -> Color {
+    if t <= 0. {
+        a.color_at(point)
+    } else if t >= 1. {
+        b.color_at(point)
+    } else {
+        let a = a.color_at(point);
+
+        a + (b.color_at(point) - a) * t
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::noise::Noise;
     use crate::shape::{Object, SimpleObject};
 
     impl Pattern {
@@ -331,7 +624,7 @@ mod tests {
         assert_eq!(pattern.pattern_at(Tuple::point(0., 0., 0.)), Color::white());
         assert_eq!(pattern.pattern_at(Tuple::point(1., 0., 0.)), Color::black());
         assert_eq!(pattern.pattern_at(Tuple::point(0., 0., 1.)), Color::black());
-        // Quake 3 level hack? 
+        // Quake 3 level hack?
         assert_eq!(
             pattern.pattern_at(Tuple::point(0.708, 0., 0.708)),
             Color::black()
@@ -379,4 +672,152 @@ mod tests {
             Color::black()
         );
     }
+
+    #[test]
+    fn noise_patterns_stay_between_their_colors() {
+        let noise = Noise::perlin(4);
+        let patterns = [
+            Pattern::marble(Color::white(), Color::black(), noise),
+            Pattern::wood(Color::white(), Color::black(), noise),
+            Pattern::clouds(Color::white(), Color::black(), noise),
+        ];
+
+        for pattern in patterns {
+            let shades: Vec<f64> = (0..200)
+                .map(|i| {
+                    let i = i as f64;
+                    pattern
+                        .pattern_at(Tuple::point(i * 0.37, i * 0.11, i * 0.23))
+                        .red
+                })
+                .collect();
+
+            assert!(shades.iter().all(|shade| (0. ..=1.).contains(shade)));
+            assert!(shades.iter().any(|shade| *shade < 0.4));
+            assert!(shades.iter().any(|shade| *shade > 0.6));
+        }
+    }
+
+    #[test]
+    fn wood_rings_are_centred_on_the_y_axis() {
+        let mut noise = Noise::perlin(4);
+        noise.octaves = 1;
+        let pattern = Pattern::wood(Color::white(), Color::black(), noise);
+
+        // Perlin noise vanishes on the lattice, so these sit exactly on a
+        // ring boundary.
+        assert_eq!(pattern.pattern_at(Tuple::point(0., 0., 0.)), Color::white());
+        assert_eq!(pattern.pattern_at(Tuple::point(1., 0., 0.)), Color::white());
+        assert_eq!(pattern.pattern_at(Tuple::point(0., 3., 2.)), Color::white());
+    }
+
+    #[test]
+    fn perturbing_with_no_scale_leaves_a_pattern_unchanged() {
+        let pattern = Pattern::striped(Color::white(), Color::black());
+        let perturbed = pattern.clone().perturbed(Noise::simplex(1), 0.);
+
+        for i in 0..50 {
+            let point = Tuple::point(i as f64 * 0.13 - 3., 0.5, 0.2);
+            assert_eq!(perturbed.pattern_at(point), pattern.pattern_at(point));
+        }
+    }
+
+    #[test]
+    fn perturbing_a_pattern_moves_its_edges() {
+        let pattern = Pattern::striped(Color::white(), Color::black());
+        let perturbed = pattern.clone().perturbed(Noise::simplex(1), 0.5);
+
+        let changed = (0..200)
+            .map(|i| Tuple::point(i as f64 * 0.05 - 5., 0.3, 0.7))
+            .filter(|point| perturbed.pattern_at(*point) != pattern.pattern_at(*point))
+            .count();
+
+        assert!(changed > 0);
+        assert!(changed < 100);
+    }
+
+    #[test]
+    fn a_checkerboard_of_stripes() {
+        let mut stripes = Pattern::striped(Color::white(), Color::black());
+        *stripes.transform_mut() = Matrix4::scaling(0.5, 1., 1.);
+        let pattern = Pattern::checkered(stripes, Color::new(0., 0., 1.));
+
+        assert_eq!(
+            pattern.pattern_at(Tuple::point(0.2, 0., 0.)),
+            Color::white()
+        );
+        assert_eq!(
+            pattern.pattern_at(Tuple::point(0.7, 0., 0.)),
+            Color::black()
+        );
+        assert_eq!(
+            pattern.pattern_at(Tuple::point(1.2, 0., 0.)),
+            Color::new(0., 0., 1.)
+        );
+    }
+
+    #[test]
+    fn nested_patterns_are_evaluated_in_their_own_space() {
+        let mut inner = Pattern::test();
+        *inner.transform_mut() = Matrix4::translation(1., 0., 0.);
+        let pattern = Pattern::striped(inner, Color::black());
+
+        assert_eq!(
+            pattern.pattern_at(Tuple::point(0.5, 0.25, 0.)),
+            Color::new(-0.5, 0.25, 0.)
+        );
+    }
+
+    #[test]
+    fn a_blend_averages_two_patterns() {
+        let pattern = Pattern::blend(
+            Pattern::striped(Color::white(), Color::black()),
+            Color::new(0., 0., 1.),
+            0.25,
+        );
+
+        assert_eq!(
+            pattern.pattern_at(Tuple::point(0., 0., 0.)),
+            Color::new(0.75, 0.75, 1.)
+        );
+        assert_eq!(
+            pattern.pattern_at(Tuple::point(1., 0., 0.)),
+            Color::new(0., 0., 0.25)
+        );
+    }
+
+    #[test]
+    fn a_mask_chooses_between_two_patterns() {
+        let red = Color::new(1., 0., 0.);
+        let pattern = Pattern::mask(
+            Pattern::striped(Color::black(), Color::white()),
+            Pattern::checkered(red, Color::black()),
+            Color::new(0., 1., 0.),
+        );
+
+        assert_eq!(pattern.pattern_at(Tuple::point(0.5, 0., 0.)), red);
+        assert_eq!(
+            pattern.pattern_at(Tuple::point(0.5, 1.5, 0.)),
+            Color::black()
+        );
+        assert_eq!(
+            pattern.pattern_at(Tuple::point(1.5, 0., 0.)),
+            Color::new(0., 1., 0.)
+        );
+    }
+
+    #[test]
+    fn a_radial_gradient_fades_away_from_the_y_axis() {
+        let pattern = Pattern::radial_gradient(Color::white(), Color::black());
+
+        assert_eq!(pattern.pattern_at(Tuple::point(0., 0., 0.)), Color::white());
+        assert_eq!(
+            pattern.pattern_at(Tuple::point(0.3, 5., 0.4)),
+            Color::new(0.5, 0.5, 0.5)
+        );
+        assert_eq!(
+            pattern.pattern_at(Tuple::point(0., 0., -0.25)),
+            Color::new(0.75, 0.75, 0.75)
+        );
+    }
 }
@@ -0,0 +1,540 @@
+use std::sync::Arc;
+
+use crate::{canvas::Canvas, color::Color};
+
+// At an amount of 1 the blue channel would shrink to a point, and past it
+// the image would flip over.
+const MAX_ABERRATION: f64 = 0.5;
+
+// Square convolution kernel with an odd number of rows, applied with the
+// image edges extended outwards.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Kernel {
+    size: usize,
+    weights: Vec<f64>,
+}
+
+impl Kernel {
+    // `weights` are given row by row.
+    pub fn new(size: usize, weights: Vec<f64>) -> Result<Self, String> {
+        if size % 2 != 1 || weights.len() != size * size {
+            return Err(format!(
+                "A kernel needs an odd size and size * size weights, got size {} and {} weights",
+                size,
+                weights.len()
+            ));
+        }
+
+        Ok(Self { size, weights })
+    }
+
+    pub fn box_blur(radius: usize) -> Self {
+        let size = 2 * radius + 1;
+
+        Self {
+            size,
+            weights: vec![1. / (size * size) as f64; size * size],
+        }
+    }
+
+    // Extends to three standard deviations on each side. A sigma of zero
+    // leaves the image as it is.
+    pub fn gaussian(sigma: f64) -> Self {
+        let radius = (3. * sigma).ceil().max(1.) as i32;
+        let size = (2 * radius + 1) as usize;
+
+        let mut weights = Vec::with_capacity(size * size);
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                weights.push(gaussian((dx * dx + dy * dy) as f64, sigma));
+            }
+        }
+        let total: f64 = weights.iter().sum();
+
+        Self {
+            size,
+            weights: weights.into_iter().map(|weight| weight / total).collect(),
+        }
+    }
+
+    pub fn sharpen() -> Self {
+        Self {
+            size: 3,
+            weights: vec![0., -1., 0., -1., 5., -1., 0., -1., 0.],
+        }
+    }
+
+    fn apply(&self, canvas: &Canvas) -> Canvas {
+        let radius = (self.size / 2) as i32;
+
+        map_pixels(canvas, |x, y| {
+            let mut total = Color::black();
+            for (index, weight) in self.weights.iter().enumerate() {
+                let dx = (index % self.size) as i32 - radius;
+                let dy = (index / self.size) as i32 - radius;
+
+                total = total + clamped_pixel(canvas, x + dx, y + dy) * *weight;
+            }
+
+            total
+        })
+    }
+}
+
+// Buffers a joint bilateral filter follows instead of the image's own
+// colors, usually the normal and albedo passes of the same render. Edges
+// in either stop the smoothing, noisy lighting doesn't.
+#[derive(Clone, Debug)]
+pub struct Guide {
+    pub normal: Arc<Canvas>,
+    pub albedo: Arc<Canvas>,
+    pub normal_sigma: f64,
+    pub albedo_sigma: f64,
+}
+
+impl Guide {
+    pub fn new(normal: Canvas, albedo: Canvas) -> Self {
+        Self {
+            normal: Arc::new(normal),
+            albedo: Arc::new(albedo),
+            normal_sigma: 0.1,
+            albedo_sigma: 0.1,
+        }
+    }
+
+    fn weight(&self, (x, y): (i32, i32), (nx, ny): (i32, i32)) -> f64 {
+        let normal = distance_squared(self.normal.pixel_at(x, y), self.normal.pixel_at(nx, ny));
+        let albedo = distance_squared(self.albedo.pixel_at(x, y), self.albedo.pixel_at(nx, ny));
+
+        gaussian(normal, self.normal_sigma) * gaussian(albedo, self.albedo_sigma)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum PostEffect {
+    Convolve(Kernel),
+    // Adds a blurred copy of everything brighter than `threshold`, so
+    // highlights glow into their surroundings.
+    Bloom {
+        threshold: f64,
+        sigma: f64,
+        intensity: f64,
+    },
+    // Darkens towards the corners, by `strength` at the corners themselves.
+    Vignette {
+        strength: f64,
+    },
+    // Scales the red channel up and the blue channel down around the image
+    // centre by `amount`, like a lens that focuses colors differently. Kept
+    // within ±0.5 when applied.
+    ChromaticAberration {
+        amount: f64,
+    },
+    // Edge-aware smoothing: neighbours within `spatial_sigma` pixels are
+    // averaged, weighted down the more their color differs by more than
+    // `color_sigma`, or their guide buffers differ when there is a guide.
+    Bilateral {
+        spatial_sigma: f64,
+        color_sigma: f64,
+        guide: Option<Guide>,
+    },
+}
+
+impl PostEffect {
+    pub fn box_blur(radius: usize) -> Self {
+        PostEffect::Convolve(Kernel::box_blur(radius))
+    }
+
+    pub fn gaussian_blur(sigma: f64) -> Self {
+        PostEffect::Convolve(Kernel::gaussian(sigma))
+    }
+
+    pub fn sharpen() -> Self {
+        PostEffect::Convolve(Kernel::sharpen())
+    }
+
+    pub fn bloom(threshold: f64, sigma: f64, intensity: f64) -> Self {
+        PostEffect::Bloom {
+            threshold,
+            sigma,
+            intensity,
+        }
+    }
+
+    pub fn vignette(strength: f64) -> Self {
+        PostEffect::Vignette { strength }
+    }
+
+    pub fn chromatic_aberration(amount: f64) -> Self {
+        PostEffect::ChromaticAberration { amount }
+    }
+
+    pub fn bilateral(spatial_sigma: f64, color_sigma: f64) -> Self {
+        PostEffect::Bilateral {
+            spatial_sigma,
+            color_sigma,
+            guide: None,
+        }
+    }
+
+    pub fn joint_bilateral(spatial_sigma: f64, guide: Guide) -> Self {
+        PostEffect::Bilateral {
+            spatial_sigma,
+            color_sigma: f64::INFINITY,
+            guide: Some(guide),
+        }
+    }
+
+    pub fn apply(&self, canvas: &Canvas) -> Canvas {
+        match self {
+            PostEffect::Convolve(kernel) => kernel.apply(canvas),
+            PostEffect::Bloom {
+                threshold,
+                sigma,
+                intensity,
+            } => {
+                let highlights = map_pixels(canvas, |x, y| {
+                    let color = canvas.pixel_at(x, y);
+
+                    Color::new(
+                        (color.red - threshold).max(0.),
+                        (color.green - threshold).max(0.),
+                        (color.blue - threshold).max(0.),
+                    )
+                });
+                let glow = Kernel::gaussian(*sigma).apply(&highlights);
+
+                map_pixels(canvas, |x, y| {
+                    canvas.pixel_at(x, y) + glow.pixel_at(x, y) * *intensity
+                })
+            }
+            PostEffect::Vignette { strength } => {
+                let (cx, cy) = centre(canvas);
+                let corner = cx * cx + cy * cy;
+
+                map_pixels(canvas, |x, y| {
+                    let dx = x as f64 + 0.5 - cx;
+                    let dy = y as f64 + 0.5 - cy;
+                    let falloff = (1. - strength * (dx * dx + dy * dy) / corner).max(0.);
+
+                    canvas.pixel_at(x, y) * falloff
+                })
+            }
+            PostEffect::ChromaticAberration { amount } => {
+                let amount = amount.clamp(-MAX_ABERRATION, MAX_ABERRATION);
+                let (cx, cy) = centre(canvas);
+
+                map_pixels(canvas, |x, y| {
+                    let dx = x as f64 + 0.5 - cx;
+                    let dy = y as f64 + 0.5 - cy;
+                    let scaled = |scale: f64| {
+                        bilinear_pixel(canvas, cx + dx * scale - 0.5, cy + dy * scale - 0.5)
+                    };
+
+                    Color::new(
+                        scaled(1. / (1. + amount)).red,
+                        canvas.pixel_at(x, y).green,
+                        scaled(1. / (1. - amount)).blue,
+                    )
+                })
+            }
+            PostEffect::Bilateral {
+                spatial_sigma,
+                color_sigma,
+                guide,
+            } => {
+                let radius = (2. * spatial_sigma).ceil().max(1.) as i32;
+
+                map_pixels(canvas, |x, y| {
+                    let centre = canvas.pixel_at(x, y);
+                    let mut total = Color::black();
+                    let mut total_weight = 0.;
+
+                    for ny in (y - radius).max(0)..=(y + radius).min(canvas.height() as i32 - 1) {
+                        for nx in (x - radius).max(0)..=(x + radius).min(canvas.width() as i32 - 1)
+                        {
+                            let color = canvas.pixel_at(nx, ny);
+                            let spatial = ((nx - x).pow(2) + (ny - y).pow(2)) as f64;
+                            let weight = gaussian(spatial, *spatial_sigma)
+                                * gaussian(distance_squared(centre, color), *color_sigma)
+                                * guide
+                                    .as_ref()
+                                    .map_or(1., |guide| guide.weight((x, y), (nx, ny)));
+
+                            total = total + color * weight;
+                            total_weight += weight;
+                        }
+                    }
+
+                    total * (1. / total_weight)
+                })
+            }
+        }
+    }
+}
+
+// Effects run one after another on the output of the previous one.
+#[derive(Clone, Debug, Default)]
+pub struct PostProcess {
+    pub effects: Vec<PostEffect>,
+}
+
+impl PostProcess {
+    pub fn new() -> Self {
+        Self { effects: vec![] }
+    }
+
+    pub fn then(mut self, effect: PostEffect) -> Self {
+        self.effects.push(effect);
+        self
+    }
+
+    pub fn apply(&self, canvas: &Canvas) -> Canvas {
+        self.effects
+            .iter()
+            .fold(canvas.clone(), |canvas, effect| effect.apply(&canvas))
+    }
+}
+
+// Weight of a squared distance under a Gaussian with the given standard
+// deviation, 1 at distance 0. Without any spread only the point itself has
+// weight.
+fn gaussian(distance_squared: f64, sigma: f64) -> f64 {
+    if sigma <= 0. {
+        return if distance_squared == 0. { 1. } else { 0. };
+    }
+
+    (-distance_squared / (2. * sigma * sigma)).exp()
+}
+
+fn distance_squared(a: Color, b: Color) -> f64 {
+    (a.red - b.red).powi(2) + (a.green - b.green).powi(2) + (a.blue - b.blue).powi(2)
+}
+
+fn centre(canvas: &Canvas) -> (f64, f64) {
+    (canvas.width() as f64 / 2., canvas.height() as f64 / 2.)
+}
+
+fn map_pixels(canvas: &Canvas, f: impl Fn(i32, i32) -> Color) -> Canvas {
+    let mut result = Canvas::new(canvas.width(), canvas.height());
+    for y in 0..canvas.height() as i32 {
+        for x in 0..canvas.width() as i32 {
+            result.write_pixel(x, y, f(x, y));
+        }
+    }
+
+    result
+}
+
+// The nearest pixel inside the canvas.
+fn clamped_pixel(canvas: &Canvas, x: i32, y: i32) -> Color {
+    canvas.pixel_at(
+        x.clamp(0, canvas.width() as i32 - 1),
+        y.clamp(0, canvas.height() as i32 - 1),
+    )
+}
+
+// `x` and `y` are measured between pixel centres.
+fn bilinear_pixel(canvas: &Canvas, x: f64, y: f64) -> Color {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let (x0, y0) = (x0 as i32, y0 as i32);
+
+    let top = clamped_pixel(canvas, x0, y0) * (1. - fx) + clamped_pixel(canvas, x0 + 1, y0) * fx;
+    let bottom =
+        clamped_pixel(canvas, x0, y0 + 1) * (1. - fx) + clamped_pixel(canvas, x0 + 1, y0 + 1) * fx;
+
+    top * (1. - fy) + bottom * fy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::misc::approx_equal;
+
+    fn gray(value: f64) -> Color {
+        Color::new(value, value, value)
+    }
+
+    fn filled(width: usize, height: usize, color: Color) -> Canvas {
+        map_pixels(&Canvas::new(width, height), |_, _| color)
+    }
+
+    // A 5x5 black canvas with one bright pixel in the middle.
+    fn single_dot(value: f64) -> Canvas {
+        let mut canvas = Canvas::new(5, 5);
+        canvas.write_pixel(2, 2, gray(value));
+
+        canvas
+    }
+
+    fn total(canvas: &Canvas) -> f64 {
+        let mut total = 0.;
+        for y in 0..canvas.height() as i32 {
+            for x in 0..canvas.width() as i32 {
+                total += canvas.pixel_at(x, y).red;
+            }
+        }
+
+        total
+    }
+
+    #[test]
+    fn kernels_need_an_odd_size_and_matching_weights() {
+        assert!(Kernel::new(3, vec![0.; 9]).is_ok());
+        assert!(Kernel::new(2, vec![0.; 4]).is_err());
+        assert!(Kernel::new(3, vec![0.; 8]).is_err());
+    }
+
+    #[test]
+    fn a_box_blur_spreads_a_pixel_evenly() {
+        let blurred = PostEffect::box_blur(1).apply(&single_dot(9.));
+
+        for y in 1..=3 {
+            for x in 1..=3 {
+                assert_eq!(blurred.pixel_at(x, y), gray(1.));
+            }
+        }
+        assert_eq!(blurred.pixel_at(0, 0), Color::black());
+    }
+
+    #[test]
+    fn a_gaussian_blur_keeps_the_energy_and_peaks_in_the_middle() {
+        let blurred = PostEffect::gaussian_blur(0.5).apply(&single_dot(1.));
+
+        assert!(approx_equal(total(&blurred), 1.));
+        assert!(blurred.pixel_at(2, 2).red > blurred.pixel_at(1, 2).red);
+        assert!(blurred.pixel_at(1, 2).red > blurred.pixel_at(1, 1).red);
+    }
+
+    #[test]
+    fn sharpening_leaves_flat_areas_alone_and_boosts_details() {
+        let flat = filled(4, 4, gray(0.3));
+        assert_eq!(PostEffect::sharpen().apply(&flat).pixel_at(1, 2), gray(0.3));
+
+        let sharpened = PostEffect::sharpen().apply(&single_dot(1.));
+        assert_eq!(sharpened.pixel_at(2, 2), gray(5.));
+        assert_eq!(sharpened.pixel_at(2, 1), gray(-1.));
+    }
+
+    #[test]
+    fn bloom_only_spreads_highlights() {
+        let dim = single_dot(0.8);
+        assert_eq!(
+            PostEffect::bloom(1., 1., 1.).apply(&dim).pixel_at(1, 2),
+            Color::black()
+        );
+
+        let bloomed = PostEffect::bloom(1., 1., 1.).apply(&single_dot(3.));
+        assert!(bloomed.pixel_at(1, 2).red > 0.);
+        assert!(bloomed.pixel_at(2, 2).red > 3.);
+    }
+
+    #[test]
+    fn a_vignette_darkens_the_corners() {
+        let canvas = filled(4, 4, Color::white());
+
+        let vignetted = PostEffect::vignette(0.5).apply(&canvas);
+
+        assert!(vignetted.pixel_at(1, 1).red > 0.9);
+        assert!(vignetted.pixel_at(0, 0).red < vignetted.pixel_at(1, 1).red);
+        assert!(vignetted.pixel_at(0, 0).red > 0.5);
+    }
+
+    #[test]
+    fn chromatic_aberration_splits_colors_away_from_the_centre() {
+        // A white column in the middle of a 9 pixel wide image
+        let mut canvas = Canvas::new(9, 1);
+        canvas.write_pixel(4, 0, Color::white());
+        canvas.write_pixel(7, 0, Color::white());
+
+        let split = PostEffect::chromatic_aberration(0.2).apply(&canvas);
+
+        assert_eq!(split.pixel_at(4, 0), Color::white());
+        // Red is magnified, so the outer column's red moves outwards
+        assert!(split.pixel_at(7, 0).red < 1.);
+        assert!(split.pixel_at(8, 0).red > 0.);
+        assert_eq!(split.pixel_at(7, 0).green, 1.);
+    }
+
+    #[test]
+    fn too_much_aberration_is_clamped() {
+        let mut canvas = Canvas::new(9, 1);
+        canvas.write_pixel(7, 0, Color::white());
+
+        let split = PostEffect::ChromaticAberration { amount: 1. }.apply(&canvas);
+        let limit = PostEffect::chromatic_aberration(MAX_ABERRATION).apply(&canvas);
+
+        for x in 0..9 {
+            assert!(!split.pixel_at(x, 0).blue.is_nan());
+            assert_eq!(split.pixel_at(x, 0), limit.pixel_at(x, 0));
+        }
+    }
+
+    #[test]
+    fn filters_without_spread_leave_the_image_alone() {
+        let dot = single_dot(1.);
+
+        for effect in [
+            PostEffect::gaussian_blur(0.),
+            PostEffect::bilateral(0., 0.5),
+            PostEffect::bilateral(1., 0.),
+        ] {
+            let filtered = effect.apply(&dot);
+            for y in 0..5 {
+                for x in 0..5 {
+                    assert_eq!(filtered.pixel_at(x, y), dot.pixel_at(x, y));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn a_bilateral_filter_smooths_noise_but_keeps_edges() {
+        // Left half dark, right half bright, with a little noise
+        let canvas = map_pixels(&Canvas::new(6, 6), |x, y| {
+            let noise = if (x + y) % 2 == 0 { 0.02 } else { -0.02 };
+
+            gray(if x < 3 { 0.2 } else { 0.8 } + noise)
+        });
+
+        let smoothed = PostEffect::bilateral(1., 0.1).apply(&canvas);
+
+        assert!((smoothed.pixel_at(1, 2).red - 0.2).abs() < 0.015);
+        assert!((smoothed.pixel_at(2, 2).red - 0.2).abs() < 0.05);
+        assert!((smoothed.pixel_at(3, 2).red - 0.8).abs() < 0.05);
+    }
+
+    #[test]
+    fn a_joint_bilateral_filter_follows_the_guide_edges() {
+        // Noise the color filter can't tell from an edge, and a guide that
+        // only changes across the middle
+        let canvas = map_pixels(&Canvas::new(6, 1), |x, _| {
+            gray(if x % 2 == 0 { 0. } else { 1. })
+        });
+        let albedo = map_pixels(&Canvas::new(6, 1), |x, _| gray(if x < 3 { 0. } else { 1. }));
+        let normal = filled(6, 1, Color::new(0.5, 0.5, 1.));
+
+        let smoothed = PostEffect::joint_bilateral(10., Guide::new(normal, albedo)).apply(&canvas);
+
+        // Each side averages its own three pixels, nearer ones a little more
+        assert!((smoothed.pixel_at(0, 0).red - 1. / 3.).abs() < 0.01);
+        assert!((smoothed.pixel_at(5, 0).red - 2. / 3.).abs() < 0.01);
+    }
+
+    #[test]
+    fn post_processing_runs_the_effects_in_order() {
+        let canvas = single_dot(9.);
+        let pipeline = PostProcess::new()
+            .then(PostEffect::box_blur(1))
+            .then(PostEffect::vignette(1.));
+
+        let expected = PostEffect::vignette(1.).apply(&PostEffect::box_blur(1).apply(&canvas));
+
+        assert_eq!(
+            pipeline.apply(&canvas).pixel_at(1, 2),
+            expected.pixel_at(1, 2)
+        );
+        assert_eq!(PostProcess::new().apply(&canvas).pixel_at(2, 2), gray(9.));
+    }
+}
@@ -0,0 +1,235 @@
+use std::{
+    io::Write,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+const BAR_WIDTH: usize = 30;
+
+// How far a render has come. `done` and `total` count whatever the render
+// works through: rows for `Camera::render_with`, passes for progressive
+// renders.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Progress {
+    pub done: usize,
+    pub total: usize,
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    pub fn fraction(&self) -> f64 {
+        if self.total == 0 {
+            1.
+        } else {
+            self.done as f64 / self.total as f64
+        }
+    }
+
+    // Time left if the rest goes as fast as what is done, unknown until
+    // something is done.
+    pub fn eta(&self) -> Option<Duration> {
+        if self.done == 0 {
+            return None;
+        }
+
+        let remaining = self.total.saturating_sub(self.done) as f64 / self.done as f64;
+
+        Some(self.elapsed.mul_f64(remaining))
+    }
+}
+
+// Told about a render as it goes. Renders check `is_cancelled` between units
+// of work and stop early when it returns true.
+pub trait ProgressObserver {
+    fn update(&mut self, progress: Progress);
+
+    fn finish(&mut self, _progress: Progress) {}
+
+    fn is_cancelled(&self) -> bool {
+        false
+    }
+
+    fn with_cancel(self, token: CancelToken) -> Cancellable<Self>
+    where
+        Self: Sized,
+    {
+        Cancellable {
+            observer: self,
+            token,
+        }
+    }
+}
+
+impl<F: FnMut(Progress)> ProgressObserver for F {
+    fn update(&mut self, progress: Progress) {
+        self(progress)
+    }
+}
+
+// Reports nothing.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Silent;
+
+impl ProgressObserver for Silent {
+    fn update(&mut self, _progress: Progress) {}
+}
+
+// A progress bar redrawn in place on stderr, so it stays out of piped
+// output.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TerminalProgress;
+
+impl ProgressObserver for TerminalProgress {
+    fn update(&mut self, progress: Progress) {
+        let filled = (progress.fraction() * BAR_WIDTH as f64).round() as usize;
+        let eta = match progress.eta() {
+            Some(eta) => format!("{:.1}s", eta.as_secs_f64()),
+            None => "?".to_owned(),
+        };
+
+        // Nothing sensible to do if the terminal is gone
+        let mut stderr = std::io::stderr();
+        let _ = write!(
+            stderr,
+            "\r[{}{}] {:3.0}% {}/{} {:.1}s elapsed, ETA {}",
+            "#".repeat(filled.min(BAR_WIDTH)),
+            " ".repeat(BAR_WIDTH - filled.min(BAR_WIDTH)),
+            100. * progress.fraction(),
+            progress.done,
+            progress.total,
+            progress.elapsed.as_secs_f64(),
+            eta
+        );
+        let _ = stderr.flush();
+    }
+
+    fn finish(&mut self, _progress: Progress) {
+        eprintln!();
+    }
+}
+
+// One JSON object per update, e.g.
+// `{"done":3,"total":10,"elapsed":1.500,"eta":3.500}`, with a null ETA
+// until it is known.
+#[derive(Debug)]
+pub struct JsonLines<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> JsonLines<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> ProgressObserver for JsonLines<W> {
+    fn update(&mut self, progress: Progress) {
+        let eta = match progress.eta() {
+            Some(eta) => format!("{:.3}", eta.as_secs_f64()),
+            None => "null".to_owned(),
+        };
+
+        // Reporting must not fail the render, a closed pipe just stops it
+        let _ = writeln!(
+            self.writer,
+            "{{\"done\":{},\"total\":{},\"elapsed\":{:.3},\"eta\":{}}}",
+            progress.done,
+            progress.total,
+            progress.elapsed.as_secs_f64(),
+            eta
+        );
+        let _ = self.writer.flush();
+    }
+}
+
+// Shared flag that stops a render from another thread or from inside an
+// observer.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// An observer that also stops the render once its token is cancelled.
+#[derive(Debug)]
+pub struct Cancellable<O> {
+    observer: O,
+    token: CancelToken,
+}
+
+impl<O: ProgressObserver> ProgressObserver for Cancellable<O> {
+    fn update(&mut self, progress: Progress) {
+        self.observer.update(progress)
+    }
+
+    fn finish(&mut self, progress: Progress) {
+        self.observer.finish(progress)
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled() || self.observer.is_cancelled()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn progress(done: usize, total: usize, seconds: u64) -> Progress {
+        Progress {
+            done,
+            total,
+            elapsed: Duration::from_secs(seconds),
+        }
+    }
+
+    #[test]
+    fn the_eta_assumes_a_steady_pace() {
+        assert_eq!(progress(0, 10, 3).eta(), None);
+        assert_eq!(progress(2, 10, 3).eta(), Some(Duration::from_secs(12)));
+        assert_eq!(progress(10, 10, 3).eta(), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn json_lines_are_written_one_per_update() {
+        let mut observer = JsonLines::new(vec![]);
+
+        observer.update(progress(0, 4, 0));
+        observer.update(progress(1, 4, 2));
+
+        assert_eq!(
+            String::from_utf8(observer.into_inner()).unwrap(),
+            "{\"done\":0,\"total\":4,\"elapsed\":0.000,\"eta\":null}\n\
+             {\"done\":1,\"total\":4,\"elapsed\":2.000,\"eta\":6.000}\n"
+        );
+    }
+
+    #[test]
+    fn cancelling_the_token_cancels_the_observer() {
+        let token = CancelToken::new();
+        let observer = Silent.with_cancel(token.clone());
+        assert!(!observer.is_cancelled());
+
+        token.cancel();
+
+        assert!(observer.is_cancelled());
+    }
+}
@@ -0,0 +1,409 @@
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    camera::Camera,
+    canvas::Canvas,
+    color::Color,
+    progress::{Progress, ProgressObserver, TerminalProgress},
+    world::World,
+};
+
+// Running sums of the samples taken for every pixel, so the image can be
+// looked at after any number of passes.
+#[derive(Clone, Debug)]
+pub struct Accumulator {
+    width: usize,
+    height: usize,
+    passes: usize,
+    sums: Vec<Color>,
+    // Sums of the squared brightness (mean of the channels) of the samples.
+    squares: Vec<f64>,
+}
+
+impl Accumulator {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            passes: 0,
+            sums: vec![Color::black(); width * height],
+            squares: vec![0.; width * height],
+        }
+    }
+
+    pub fn passes(&self) -> usize {
+        self.passes
+    }
+
+    // Adds one sample for every pixel.
+    pub fn add_pass(&mut self, pass: &Canvas) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let color = pass.pixel_at(x as i32, y as i32);
+                let index = x + y * self.width;
+
+                self.sums[index] = self.sums[index] + color;
+                self.squares[index] += brightness(color).powi(2);
+            }
+        }
+        self.passes += 1;
+    }
+
+    // Average of the passes so far.
+    pub fn image(&self) -> Canvas {
+        let mut canvas = Canvas::new(self.width, self.height);
+        let scale = 1. / self.passes.max(1) as f64;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                canvas.write_pixel(x as i32, y as i32, self.sums[x + y * self.width] * scale);
+            }
+        }
+
+        canvas
+    }
+
+    // Estimated variance of a pixel's average brightness, which shrinks as
+    // passes are added. Infinite until there are two passes.
+    pub fn variance(&self, x: usize, y: usize) -> f64 {
+        if self.passes < 2 {
+            return f64::INFINITY;
+        }
+
+        let n = self.passes as f64;
+        let index = x + y * self.width;
+        let sum = brightness(self.sums[index]);
+
+        ((self.squares[index] - sum * sum / n) / (n * (n - 1.))).max(0.)
+    }
+
+    // The noisiest pixel's variance.
+    pub fn max_variance(&self) -> f64 {
+        (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .map(|(x, y)| self.variance(x, y))
+            .fold(0., f64::max)
+    }
+}
+
+fn brightness(color: Color) -> f64 {
+    (color.red + color.green + color.blue) / 3.
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProgressiveSettings {
+    // Each pass adds one sample per pixel.
+    pub max_passes: usize,
+    // Passes always rendered before checking `variance_threshold`.
+    pub min_passes: usize,
+    // Stop once every pixel's variance is below this.
+    pub variance_threshold: Option<f64>,
+    // Stop after the pass that runs past this much time.
+    pub time_budget: Option<Duration>,
+    // Write the image so far to `snapshot_path` every `snapshot_interval`
+    // passes, overwriting the previous snapshot.
+    pub snapshot_path: Option<String>,
+    pub snapshot_interval: usize,
+    pub snapshot_format: SnapshotFormat,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SnapshotFormat {
+    // Tone mapped 8-bit sRGB, for looking at.
+    Ppm,
+    // The linear colors as they are, for finishing the image elsewhere.
+    Pfm,
+}
+
+impl Default for ProgressiveSettings {
+    fn default() -> Self {
+        Self {
+            max_passes: 64,
+            min_passes: 4,
+            variance_threshold: None,
+            time_budget: None,
+            snapshot_path: None,
+            snapshot_interval: 8,
+            snapshot_format: SnapshotFormat::Ppm,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    MaxPasses,
+    Converged,
+    TimeBudget,
+    Cancelled,
+}
+
+#[derive(Clone, Debug)]
+pub struct ProgressiveRender {
+    pub image: Canvas,
+    pub passes: usize,
+    pub stop_reason: StopReason,
+}
+
+// Renders with a progress bar on stderr.
+pub fn render_progressive(
+    camera: Camera,
+    world: &World,
+    settings: &ProgressiveSettings,
+) -> std::io::Result<ProgressiveRender> {
+    render_progressive_with(camera, world, settings, &mut TerminalProgress)
+}
+
+// Renders one sample per pixel at a time until a stopping condition is met
+// or `observer` cancels, reporting after every pass. Pass `i` uses the
+// camera's `i`-th sample out of `max_passes`, so stopping early gives the
+// same image as a shorter run.
+pub fn render_progressive_with(
+    camera: Camera,
+    world: &World,
+    settings: &ProgressiveSettings,
+    observer: &mut dyn ProgressObserver,
+) -> std::io::Result<ProgressiveRender> {
+    let started = Instant::now();
+    let max_passes = settings.max_passes.max(1);
+    let mut accumulator = Accumulator::new(camera.hsize as usize, camera.vsize as usize);
+    let progress = |done: usize| Progress {
+        done,
+        total: max_passes,
+        elapsed: started.elapsed(),
+    };
+
+    observer.update(progress(0));
+    let stop_reason = loop {
+        if observer.is_cancelled() {
+            break StopReason::Cancelled;
+        }
+
+        let pass = accumulator.passes();
+        let mut canvas = Canvas::new(camera.hsize as usize, camera.vsize as usize);
+        for y in 0..camera.vsize {
+            for x in 0..camera.hsize {
+                canvas.write_pixel(x, y, camera.sample_color(world, x, y, pass, max_passes));
+            }
+        }
+        accumulator.add_pass(&canvas);
+
+        let passes = accumulator.passes();
+        observer.update(progress(passes));
+
+        if let Some(path) = &settings.snapshot_path {
+            if passes.is_multiple_of(settings.snapshot_interval.max(1)) {
+                write_snapshot(path, settings.snapshot_format, &accumulator.image())?;
+            }
+        }
+
+        if passes >= max_passes {
+            break StopReason::MaxPasses;
+        }
+        if let Some(threshold) = settings.variance_threshold {
+            if passes >= settings.min_passes.max(2) && accumulator.max_variance() < threshold {
+                break StopReason::Converged;
+            }
+        }
+        if let Some(budget) = settings.time_budget {
+            if started.elapsed() >= budget {
+                break StopReason::TimeBudget;
+            }
+        }
+    };
+
+    observer.finish(progress(accumulator.passes()));
+
+    let image = accumulator.image();
+    if let Some(path) = &settings.snapshot_path {
+        write_snapshot(path, settings.snapshot_format, &image)?;
+    }
+
+    Ok(ProgressiveRender {
+        image,
+        passes: accumulator.passes(),
+        stop_reason,
+    })
+}
+
+fn write_snapshot(path: &str, format: SnapshotFormat, image: &Canvas) -> std::io::Result<()> {
+    if let Some(parent) = Path::new(path).parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    match format {
+        SnapshotFormat::Ppm => std::fs::write(path, image.to_ppm()),
+        SnapshotFormat::Pfm => std::fs::write(path, image.to_pfm()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        light::Light,
+        material::Material,
+        math::transformations::view_transform,
+        math::tuple::Tuple,
+        progress::{CancelToken, Silent},
+        shape::Object,
+    };
+
+    fn gray(value: f64) -> Color {
+        Color::new(value, value, value)
+    }
+
+    fn filled(color: Color) -> Canvas {
+        let mut canvas = Canvas::new(2, 1);
+        canvas.write_pixel(0, 0, color);
+        canvas.write_pixel(1, 0, color);
+
+        canvas
+    }
+
+    // A small view of a sphere's edge, where jittered samples disagree.
+    fn sphere_edge() -> (Camera, World) {
+        let mut world = World::new();
+        world.add_light(Light::point_light(
+            Tuple::point(-10., 10., -10.),
+            Color::white(),
+        ));
+        let mut sphere = Object::sphere();
+        sphere.set_material(Material::new());
+        world.add_object(sphere);
+
+        let mut camera = Camera::new(4, 4, 0.5);
+        camera.transform = view_transform(
+            Tuple::point(1., 0., -5.),
+            Tuple::point(1., 0., 0.),
+            Tuple::vector(0., 1., 0.),
+        );
+
+        (camera, world)
+    }
+
+    #[test]
+    fn accumulating_passes_averages_them() {
+        let mut accumulator = Accumulator::new(2, 1);
+
+        accumulator.add_pass(&filled(gray(0.2)));
+        accumulator.add_pass(&filled(gray(0.6)));
+
+        assert_eq!(accumulator.passes(), 2);
+        assert_eq!(accumulator.image().pixel_at(1, 0), gray(0.4));
+    }
+
+    #[test]
+    fn variance_shrinks_with_more_passes() {
+        let mut accumulator = Accumulator::new(2, 1);
+        accumulator.add_pass(&filled(gray(0.)));
+        assert_eq!(accumulator.variance(0, 0), f64::INFINITY);
+
+        accumulator.add_pass(&filled(gray(1.)));
+        let two_passes = accumulator.max_variance();
+        accumulator.add_pass(&filled(gray(0.)));
+        accumulator.add_pass(&filled(gray(1.)));
+
+        // Sample variance 1/2 over 2 samples, then 1/3 over 4
+        assert!((two_passes - 0.25).abs() < 1e-9);
+        assert!((accumulator.max_variance() - 1. / 12.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn a_flat_image_converges_right_away() {
+        let (camera, mut world) = sphere_edge();
+        world.objects.clear();
+        let settings = ProgressiveSettings {
+            variance_threshold: Some(1e-6),
+            ..ProgressiveSettings::default()
+        };
+
+        let render = render_progressive_with(camera, &world, &settings, &mut Silent).unwrap();
+
+        assert_eq!(render.stop_reason, StopReason::Converged);
+        assert_eq!(render.passes, settings.min_passes);
+    }
+
+    #[test]
+    fn stopping_early_matches_a_shorter_run() {
+        let (camera, world) = sphere_edge();
+        let settings = ProgressiveSettings {
+            max_passes: 8,
+            time_budget: Some(Duration::ZERO),
+            ..ProgressiveSettings::default()
+        };
+
+        let render = render_progressive_with(camera, &world, &settings, &mut Silent).unwrap();
+
+        assert_eq!(render.stop_reason, StopReason::TimeBudget);
+        assert_eq!(render.passes, 1);
+        assert_eq!(
+            render.image.pixel_at(1, 2),
+            camera.sample_color(&world, 1, 2, 0, 8)
+        );
+    }
+
+    #[test]
+    fn rendering_every_pass_matches_supersampling() {
+        let (mut camera, world) = sphere_edge();
+        let settings = ProgressiveSettings {
+            max_passes: 4,
+            ..ProgressiveSettings::default()
+        };
+
+        let render = render_progressive_with(camera, &world, &settings, &mut Silent).unwrap();
+        camera.samples_per_pixel = 4;
+
+        assert_eq!(render.stop_reason, StopReason::MaxPasses);
+        for (x, y) in [(0, 0), (1, 2), (3, 3)] {
+            assert_eq!(
+                render.image.pixel_at(x, y),
+                camera.pixel_color(&world, x, y)
+            );
+        }
+    }
+
+    #[test]
+    fn snapshots_can_keep_the_full_range() {
+        let (camera, world) = sphere_edge();
+        let directory =
+            std::env::temp_dir().join(format!("ray_tracer_snapshot_{}", std::process::id()));
+        let path = directory.join("snapshot.pfm");
+        let settings = ProgressiveSettings {
+            max_passes: 2,
+            snapshot_path: Some(path.to_string_lossy().into_owned()),
+            snapshot_format: SnapshotFormat::Pfm,
+            ..ProgressiveSettings::default()
+        };
+
+        let render = render_progressive_with(camera, &world, &settings, &mut Silent).unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), render.image.to_pfm());
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn a_cancelled_render_stops_after_the_current_pass() {
+        let (camera, world) = sphere_edge();
+        let token = CancelToken::new();
+        let cancel = token.clone();
+        let mut observer = (move |progress: Progress| {
+            if progress.done == 2 {
+                cancel.cancel()
+            }
+        })
+        .with_cancel(token);
+
+        let render = render_progressive_with(
+            camera,
+            &world,
+            &ProgressiveSettings::default(),
+            &mut observer,
+        )
+        .unwrap();
+
+        assert_eq!(render.stop_reason, StopReason::Cancelled);
+        assert_eq!(render.passes, 2);
+    }
+}
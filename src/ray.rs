@@ -5,11 +5,32 @@ use crate::math::tuple::Tuple;
 pub struct Ray {
     pub origin: Tuple,
     pub direction: Tuple,
+    // Moment inside the camera shutter interval the ray was sent at, used to
+    // place moving objects.
+    pub time: f64,
+    // Set when rendering spectrally, in nanometres.
+    pub wavelength: Option<f64>,
 }
 
 impl Ray {
     pub fn new(origin: Tuple, direction: Tuple) -> Self {
-        Self { origin, direction }
+        Self {
+            origin,
+            direction,
+            time: 0.,
+            wavelength: None,
+        }
+    }
+
+    pub fn at_time(self, time: f64) -> Self {
+        Self { time, ..self }
+    }
+
+    pub fn with_wavelength(self, wavelength: f64) -> Self {
+        Self {
+            wavelength: Some(wavelength),
+            ..self
+        }
     }
 
     pub fn position(self, t: f64) -> Tuple {
@@ -20,6 +41,7 @@ impl Ray {
         Self {
             origin: matrix * self.origin,
             direction: matrix * self.direction,
+            ..self
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,77 @@
+use std::f64::consts::PI;
+
+use crate::math::tuple::Tuple;
+
+// Small deterministic random number generator (SplitMix64).
+//
+// Renders seed it from things like pixel coordinates and sample indices
+// rather than sharing one stream, so the result does not depend on the order
+// in which pixels are computed.
+#[derive(Clone, Copy, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn from_seeds(seeds: &[u64]) -> Self {
+        let state = seeds
+            .iter()
+            .fold(0x853c_49e6_748f_ea9b, |state, seed| mix(state ^ seed));
+
+        Self::new(state)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+        mix(self.state)
+    }
+
+    // Uniformly distributed in [0, 1).
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+// Direction picked uniformly within `half_angle` radians of `axis`.
+pub fn cone_direction(rng: &mut Rng, axis: Tuple, half_angle: f64) -> Tuple {
+    let cos_theta = 1. - rng.next_f64() * (1. - half_angle.min(PI).cos());
+
+    around_axis(rng, axis, cos_theta)
+}
+
+// Direction in the hemisphere around `normal`, more likely near the normal
+// in proportion to the cosine of the angle to it.
+pub fn cosine_hemisphere_direction(rng: &mut Rng, normal: Tuple) -> Tuple {
+    let cos_theta = rng.next_f64().sqrt();
+
+    around_axis(rng, normal, cos_theta)
+}
+
+// Direction at the given angle from `axis`, at a random angle around it.
+fn around_axis(rng: &mut Rng, axis: Tuple, cos_theta: f64) -> Tuple {
+    let axis = axis.normalize();
+    let helper = if axis.x.abs() < 0.9 {
+        Tuple::vector(1., 0., 0.)
+    } else {
+        Tuple::vector(0., 1., 0.)
+    };
+    let u = axis.cross(helper).normalize();
+    let w = axis.cross(u);
+
+    let sin_theta = (1. - cos_theta * cos_theta).sqrt();
+    let phi = 2. * PI * rng.next_f64();
+
+    u * (sin_theta * phi.cos()) + w * (sin_theta * phi.sin()) + axis * cos_theta
+}
+
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+
+    z ^ (z >> 31)
+}
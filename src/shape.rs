@@ -1,10 +1,13 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use crate::bump::SurfaceFrame;
 use crate::color::Color;
 use crate::intersection::Intersection;
-use crate::intersection::TorUVT;
 use crate::material::Material;
 use crate::math::matrix4::Matrix4;
 use crate::math::tuple::Tuple;
-use crate::misc::EPSILON;
+use crate::motion::Motion;
 use crate::ray::Ray;
 pub mod cone;
 pub mod csg;
@@ -24,7 +27,11 @@ use self::csg::Csg;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Object {
+    // Used to find the object by path, see `find`.
+    pub name: Option<String>,
     pub transform: Matrix4,
+    // Overrides `transform` for objects that move while the shutter is open.
+    pub motion: Option<Motion>,
     pub shape: ShapeOrGroup,
 }
 
@@ -36,36 +43,180 @@ impl Object {
                 shape: Shape::Csg(csg),
                 ..
             } => csg.includes(object),
-            ShapeOrGroup::Shape { .. } => {
-                let o = SimpleObject::from_object(self).unwrap();
+            ShapeOrGroup::Shape { shape, .. } => std::ptr::eq(shape, object.shape),
+        }
+    }
 
-                o == object
-            }
+    // Materials of every shape in the object, including CSG operands.
+    pub(crate) fn materials(&self) -> Vec<&Material> {
+        match &self.shape {
+            ShapeOrGroup::Group(group) => group.iter().flat_map(|o| o.materials()).collect(),
+            ShapeOrGroup::Shape {
+                shape: Shape::Csg(csg),
+                ..
+            } => [csg.left.materials(), csg.right.materials()].concat(),
+            ShapeOrGroup::Shape { material, .. } => vec![material],
         }
     }
 
     pub fn bounding_box(&self) -> BoundingBox {
         let inner_bb = match &self.shape {
-            ShapeOrGroup::Shape { shape, .. } => shape.bounding_box(),
-            ShapeOrGroup::Group(ref group) => group
+            ShapeOrGroup::Shape { shape, .. } => shape.primitive_bounding_box(),
+            ShapeOrGroup::Group(ref group) => match group
                 .iter()
                 .map(|object| object.bounding_box())
                 .reduce(|box1, box2| BoundingBox::union(&box1, &box2))
-                .unwrap(),
+            {
+                Some(bounding_box) => bounding_box,
+                // Transforming would turn its infinities into NaNs
+                None => return BoundingBox::empty(),
+            },
         };
 
-        let new_points = inner_bb.points().map(|point| self.transform * point);
+        match &self.motion {
+            Some(motion) => motion.bounds(&inner_bb),
+            None => {
+                let points = inner_bb.points().map(|point| self.transform * point);
 
-        BoundingBox::from_points(&new_points)
+                BoundingBox::from_points(&points)
+            }
+        }
+    }
+
+    pub fn transform_at(&self, time: f64) -> Matrix4 {
+        match &self.motion {
+            Some(motion) => motion.transform_at(time),
+            None => self.transform,
+        }
     }
 
     pub fn group(objects: Vec<Object>) -> Self {
         Object {
+            name: None,
             transform: Matrix4::identity(),
+            motion: None,
             shape: ShapeOrGroup::Group(objects),
         }
     }
 
+    // See `check_name` for the names paths can find.
+    pub fn named(mut self, name: &str) -> Result<Self, String> {
+        check_name(name)?;
+        self.name = Some(name.to_owned());
+
+        Ok(self)
+    }
+
+    // Members of a group, or the operands of a CSG shape.
+    pub fn children(&self) -> Vec<&Object> {
+        match &self.shape {
+            ShapeOrGroup::Group(group) => group.iter().collect(),
+            ShapeOrGroup::Shape {
+                shape: Shape::Csg(csg),
+                ..
+            } => vec![&csg.left, &csg.right],
+            ShapeOrGroup::Shape { .. } => vec![],
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Object> {
+        match &mut self.shape {
+            ShapeOrGroup::Group(group) => group.iter_mut().collect(),
+            ShapeOrGroup::Shape {
+                shape: Shape::Csg(csg),
+                ..
+            } => vec![&mut csg.left, &mut csg.right],
+            ShapeOrGroup::Shape { .. } => vec![],
+        }
+    }
+
+    // A descendant by the names of the objects leading down to it, like
+    // "teapot/lid". A '#' and an index picks a child by position instead,
+    // like "teapot/#0".
+    pub fn find(&self, path: &str) -> Option<&Object> {
+        path.split('/').try_fold(self, |object, segment| {
+            let children = object.children();
+            let index = child_index(&children, segment)?;
+
+            Some(children[index])
+        })
+    }
+
+    pub fn find_mut(&mut self, path: &str) -> Option<&mut Object> {
+        path.split('/').try_fold(self, |object, segment| {
+            let index = child_index(&object.children(), segment)?;
+
+            object.children_mut().into_iter().nth(index)
+        })
+    }
+
+    // The group or CSG shape holding the descendant at `path`, which is this
+    // object for its own children.
+    pub fn parent(&self, path: &str) -> Option<&Object> {
+        self.find(path)?;
+
+        match path.rsplit_once('/') {
+            Some((parent, _)) => self.find(parent),
+            None => Some(self),
+        }
+    }
+
+    // Every object below this one, parents before their children, with
+    // paths relative to this object and transforms into its parent's space.
+    pub fn descendants(&self) -> Vec<Node<'_>> {
+        let mut nodes = vec![];
+        let children = self.children();
+        for (index, child) in children.iter().enumerate() {
+            child.collect_nodes(
+                node_path(None, &children, index),
+                self.transform_at(0.),
+                &mut nodes,
+            );
+        }
+
+        nodes
+    }
+
+    pub(crate) fn collect_nodes<'a>(
+        &'a self,
+        path: String,
+        parent_transform: Matrix4,
+        nodes: &mut Vec<Node<'a>>,
+    ) {
+        let transform = parent_transform * self.transform_at(0.);
+        nodes.push(Node {
+            path: path.clone(),
+            object: self,
+            transform,
+        });
+
+        let children = self.children();
+        for (index, child) in children.iter().enumerate() {
+            child.collect_nodes(node_path(Some(&path), &children, index), transform, nodes);
+        }
+    }
+
+    // Takes a descendant out of its group. Operands of a CSG shape can only
+    // be replaced.
+    pub fn remove(&mut self, path: &str) -> Option<Object> {
+        let (parent, name) = match path.rsplit_once('/') {
+            Some((parent_path, name)) => (self.find_mut(parent_path)?, name),
+            None => (self, path),
+        };
+        let index = child_index(&parent.children(), name)?;
+
+        match &mut parent.shape {
+            ShapeOrGroup::Group(group) => Some(group.remove(index)),
+            ShapeOrGroup::Shape { .. } => None,
+        }
+    }
+
+    // Puts `object` in place of a descendant, returning the descendant.
+    pub fn replace(&mut self, path: &str, object: Object) -> Option<Object> {
+        self.find_mut(path)
+            .map(|old| std::mem::replace(old, object))
+    }
+
     pub fn set_material(&mut self, material: Material) {
         match self.shape {
             ShapeOrGroup::Shape {
@@ -76,7 +227,21 @@ impl Object {
             }
             ShapeOrGroup::Group(ref mut group) => {
                 for object in group.iter_mut() {
-                    object.set_material(material);
+                    object.set_material(material.clone());
+                }
+            }
+        }
+    }
+
+    // Changes the material of this object, or of every object in the group.
+    pub fn update_material(&mut self, update: &dyn Fn(&mut Material)) {
+        match self.shape {
+            ShapeOrGroup::Shape {
+                ref mut material, ..
+            } => update(material),
+            ShapeOrGroup::Group(ref mut group) => {
+                for object in group.iter_mut() {
+                    object.update_material(update);
                 }
             }
         }
@@ -87,15 +252,16 @@ impl Object {
         let intersects_box = bb.intersect(ray);
 
         if intersects_box {
-            let local_ray = ray.transform(self.transform.inverse().unwrap());
+            let transform = self.transform_at(ray.time);
+            let local_ray = ray.transform(transform.inverse().unwrap());
 
-            self.local_intersect(local_ray)
+            self.local_intersect(transform, local_ray)
         } else {
             vec![]
         }
     }
 
-    fn local_intersect<'a>(&'a self, local_ray: Ray) -> Vec<Intersection<'a>> {
+    fn local_intersect<'a>(&'a self, transform: Matrix4, local_ray: Ray) -> Vec<Intersection<'a>> {
         match self.shape {
             ShapeOrGroup::Shape {
                 shape: Shape::Csg(ref csg),
@@ -104,7 +270,7 @@ impl Object {
                 .local_intersect(local_ray)
                 .into_iter()
                 .map(|mut i| {
-                    i.object.transform = self.transform * i.object.transform;
+                    i.object.transform = transform * i.object.transform;
                     i
                 })
                 .collect(),
@@ -112,7 +278,7 @@ impl Object {
                 .iter()
                 .flat_map(|object| object.intersect(local_ray))
                 .map(|mut i| {
-                    i.object.transform = self.transform * i.object.transform;
+                    i.object.transform = transform * i.object.transform;
                     i
                 })
                 .collect(),
@@ -123,12 +289,12 @@ impl Object {
             } => shape
                 .local_intersect(local_ray)
                 .into_iter()
-                .map(|t| {
+                .map(|hit| {
                     Intersection::new(
-                        &t,
+                        hit,
                         SimpleObject {
-                            material: *material,
-                            transform: self.transform,
+                            material,
+                            transform,
                             shape: &shape,
                         },
                     )
@@ -139,7 +305,9 @@ impl Object {
 
     pub fn new(shape: Shape) -> Self {
         Self {
+            name: None,
             transform: Matrix4::identity(),
+            motion: None,
             shape: ShapeOrGroup::Shape {
                 material: Material::new(),
                 shape,
@@ -167,6 +335,14 @@ impl Object {
         Self::new(Shape::Cone(Cone::new()))
     }
 
+    pub fn triangle(triangle: Triangle) -> Self {
+        Self::new(Shape::Triangle(triangle))
+    }
+
+    pub fn custom(primitive: impl Primitive + 'static) -> Self {
+        Self::new(Shape::custom(primitive))
+    }
+
     pub fn union(left: Object, right: Object) -> Self {
         Self::new(Shape::Csg(Csg::union(left, right)))
     }
@@ -180,6 +356,80 @@ impl Object {
     }
 }
 
+// An object in a tree of groups, with where it is in the tree and in space.
+#[derive(Clone, Debug)]
+pub struct Node<'a> {
+    // Names from the root down, joined by '/'. Unnamed objects, and objects
+    // named like an earlier sibling, go by '#' and their index, so the path
+    // always finds the object again.
+    pub path: String,
+    pub object: &'a Object,
+    // From the object's space to the root's, as the shutter opens.
+    pub transform: Matrix4,
+}
+
+// The path to the shape among `objects` and their descendants that `hit`
+// is on, and the object for it. Only the branch holding it is walked.
+pub(crate) fn hit_path<'a>(
+    objects: Vec<&'a Object>,
+    hit: SimpleObject,
+) -> Option<(String, &'a Object)> {
+    let mut siblings = objects;
+    let mut parent: Option<String> = None;
+
+    loop {
+        // Groups and CSG shapes include the shapes below them
+        let index = siblings.iter().position(|object| object.includes(hit))?;
+        let path = node_path(parent.as_deref(), &siblings, index);
+        let object = siblings[index];
+
+        let children = object.children();
+        if children.is_empty() {
+            return Some((path, object));
+        }
+        siblings = children;
+        parent = Some(path);
+    }
+}
+
+// Names can't contain '/', which separates the segments of a path, or start
+// with '#', which marks an index.
+pub(crate) fn check_name(name: &str) -> Result<(), String> {
+    if name.contains('/') || name.starts_with('#') {
+        return Err(format!(
+            "Object name {:?} contains '/' or starts with '#'",
+            name
+        ));
+    }
+
+    Ok(())
+}
+
+// "#<index>" picks a child by position, anything else is the name of the
+// first child by that name.
+pub(crate) fn child_index(children: &[&Object], segment: &str) -> Option<usize> {
+    match segment.strip_prefix('#') {
+        Some(index) => index.parse().ok().filter(|&index| index < children.len()),
+        None => children
+            .iter()
+            .position(|child| child.name.as_deref() == Some(segment)),
+    }
+}
+
+// The path of the `index`-th of `siblings`, see `Node::path`.
+pub(crate) fn node_path(parent: Option<&str>, siblings: &[&Object], index: usize) -> String {
+    let name = siblings[index].name.as_deref();
+    let segment = match name {
+        Some(name) if child_index(siblings, name) == Some(index) => name.to_owned(),
+        _ => format!("#{}", index),
+    };
+
+    match parent {
+        Some(parent) => format!("{}/{}", parent, segment),
+        None => segment,
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum ShapeOrGroup {
     Shape { material: Material, shape: Shape },
@@ -188,18 +438,38 @@ pub enum ShapeOrGroup {
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct SimpleObject<'a> {
-    pub material: Material,
+    pub material: &'a Material,
     pub transform: Matrix4,
     pub shape: &'a Shape,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct BoundingBox {
     min: Tuple,
     max: Tuple,
 }
 
 impl BoundingBox {
+    pub fn new(min: Tuple, max: Tuple) -> Self {
+        Self { min, max }
+    }
+
+    // Contains nothing, and adds nothing to a union.
+    fn empty() -> Self {
+        Self::new(
+            Tuple::point(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            Tuple::point(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        )
+    }
+
+    pub fn min(&self) -> Tuple {
+        self.min
+    }
+
+    pub fn max(&self) -> Tuple {
+        self.max
+    }
+
     #[allow(dead_code)]
     pub fn to_object(&self) -> Object {
         let Tuple {
@@ -238,7 +508,7 @@ impl BoundingBox {
         }
     }
 
-    fn points(&self) -> [Tuple; 8] {
+    pub(crate) fn points(&self) -> [Tuple; 8] {
         let Tuple {
             x: x_min,
             y: y_min,
@@ -280,6 +550,48 @@ impl BoundingBox {
     }
 }
 
+// A shape in its own object space, where `Object` places it with its
+// transform and material. Implement this to render shapes the crate doesn't
+// have, and add them with `Object::custom`.
+pub trait Primitive: Debug + Send + Sync {
+    // Every place the ray crosses the surface, in any order.
+    fn local_intersect(&self, local_ray: Ray) -> Vec<LocalHit>;
+
+    // Outward normal at a point found by `local_intersect`, which is also
+    // given the hit it returned there. It needn't be normalized.
+    fn local_normal_at(&self, local_point: Tuple, hit: LocalHit) -> Tuple;
+
+    // Must contain the whole surface, rays missing it are never tested.
+    fn bounding_box(&self) -> BoundingBox;
+
+    // UV coordinates and their directions, for bump maps. Shapes without
+    // them are only bumped by solid noise.
+    fn local_surface_frame(&self, _local_point: Tuple, _hit: LocalHit) -> Option<SurfaceFrame> {
+        None
+    }
+}
+
+// Distance along the ray to a hit, with the surface's (u, v) there for
+// primitives that work them out while intersecting.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LocalHit {
+    pub t: f64,
+    pub uv: Option<(f64, f64)>,
+}
+
+impl LocalHit {
+    pub fn new(t: f64) -> Self {
+        Self { t, uv: None }
+    }
+
+    pub fn with_uv(t: f64, u: f64, v: f64) -> Self {
+        Self {
+            t,
+            uv: Some((u, v)),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Shape {
     Sphere,
@@ -289,45 +601,34 @@ pub enum Shape {
     Cone(Cone),
     Triangle(Triangle),
     Csg(Csg),
+    Custom(Custom),
 }
 
-impl Shape {
-    fn bounding_box(&self) -> BoundingBox {
-        match self {
-            Shape::Sphere => BoundingBox {
-                min: Tuple::point(-(1. + EPSILON), -(1. + EPSILON), -(1. + EPSILON)),
-                max: Tuple::point(1. + EPSILON, 1. + EPSILON, 1. + EPSILON),
-            },
+// A primitive implemented outside the crate. Clones share it, and it is only
+// equal to its clones.
+#[derive(Clone, Debug)]
+pub struct Custom(Arc<dyn Primitive>);
 
-            Shape::Cube => BoundingBox {
-                min: Tuple::point(-1., -1., -1.),
-                max: Tuple::point(1., 1., 1.),
-            },
-            Shape::Plane => BoundingBox {
-                min: Tuple::point(f64::NEG_INFINITY, 0., f64::NEG_INFINITY),
-                max: Tuple::point(f64::INFINITY, 0., f64::INFINITY),
-            },
-            Shape::Cylinder(Cylinder {
-                minimum: min_y,
-                maximum: max_y,
-                ..
-            }) => BoundingBox {
-                min: Tuple::point(-1., *min_y, -1.),
-                max: Tuple::point(1., *max_y, 1.),
-            },
-            Shape::Cone(Cone {
-                minimum: min_y,
-                maximum: max_y,
-                ..
-            }) => {
-                let max_x = f64::max(min_y.abs(), max_y.abs());
-                let max_z = max_x;
+impl PartialEq for Custom {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
 
-                BoundingBox {
-                    min: Tuple::point(-max_x, *min_y, -max_z),
-                    max: Tuple::point(max_x, *max_y, max_z),
-                }
-            }
+impl Shape {
+    pub fn custom(primitive: impl Primitive + 'static) -> Self {
+        Shape::Custom(Custom(Arc::new(primitive)))
+    }
+
+    // Built-in shapes are matched rather than boxed so their calls stay
+    // static.
+    fn primitive_bounding_box(&self) -> BoundingBox {
+        match self {
+            Shape::Sphere => Sphere {}.bounding_box(),
+            Shape::Plane => Plane {}.bounding_box(),
+            Shape::Cube => Cube.bounding_box(),
+            Shape::Cylinder(cylinder) => cylinder.bounding_box(),
+            Shape::Cone(cone) => cone.bounding_box(),
             Shape::Triangle(triangle) => triangle.bounding_box(),
             Shape::Csg(csg) => {
                 let left = csg.left.bounding_box();
@@ -335,65 +636,66 @@ impl Shape {
 
                 left.union(&right)
             }
+            Shape::Custom(Custom(primitive)) => primitive.bounding_box(),
         }
     }
 
     pub(crate) fn local_normal_at(&self, intersection: Intersection, local_point: Tuple) -> Tuple {
+        let hit = intersection.local_hit();
+
         match self {
-            Shape::Sphere => Sphere::local_normal_at(local_point),
-            Shape::Plane => Plane::local_normal_at(local_point),
-            Shape::Cube => Cube::local_normal_at(local_point),
-            Shape::Cylinder(cylinder) => cylinder.local_normal_at(local_point),
-            Shape::Cone(cone) => cone.local_normal_at(local_point),
-            Shape::Triangle(triangle) => {
-                let uvt = intersection.uvt().unwrap();
-
-                triangle.local_normal_at(&uvt)
-            }
+            Shape::Sphere => Sphere {}.local_normal_at(local_point, hit),
+            Shape::Plane => Plane {}.local_normal_at(local_point, hit),
+            Shape::Cube => Cube.local_normal_at(local_point, hit),
+            Shape::Cylinder(cylinder) => cylinder.local_normal_at(local_point, hit),
+            Shape::Cone(cone) => cone.local_normal_at(local_point, hit),
+            Shape::Triangle(triangle) => triangle.local_normal_at(local_point, hit),
             Shape::Csg(_) => unreachable!(),
+            Shape::Custom(Custom(primitive)) => primitive.local_normal_at(local_point, hit),
         }
     }
 
-    fn local_intersect(&self, local_ray: Ray) -> Vec<TorUVT> {
+    // UV coordinates and their directions, for shapes that have them.
+    pub(crate) fn local_surface_frame(
+        &self,
+        intersection: Intersection,
+        local_point: Tuple,
+    ) -> Option<SurfaceFrame> {
+        let hit = intersection.local_hit();
+
         match self {
-            Shape::Sphere => Sphere::local_intersect(local_ray)
-                .into_iter()
-                .map(|t| TorUVT::JustT { t })
-                .collect(),
-            Shape::Plane => Plane::local_intersect(local_ray)
-                .into_iter()
-                .map(|t| TorUVT::JustT { t })
-                .collect(),
-            Shape::Cube => Cube::local_intersect(local_ray)
-                .into_iter()
-                .map(|t| TorUVT::JustT { t })
-                .collect(),
-            Shape::Cylinder(cylinder) => cylinder
-                .local_intersect(local_ray)
-                .into_iter()
-                .map(|t| TorUVT::JustT { t })
-                .collect(),
-            Shape::Cone(cone) => cone
-                .local_intersect(local_ray)
-                .into_iter()
-                .map(|t| TorUVT::JustT { t })
-                .collect(),
-            Shape::Triangle(triangle) => triangle
-                .local_intersect(local_ray)
-                .into_iter()
-                .map(|uvt| TorUVT::UVT { uvt })
-                .collect(),
+            Shape::Sphere => Sphere {}.local_surface_frame(local_point, hit),
+            Shape::Plane => Plane {}.local_surface_frame(local_point, hit),
+            Shape::Cube => Cube.local_surface_frame(local_point, hit),
+            Shape::Cylinder(cylinder) => cylinder.local_surface_frame(local_point, hit),
+            Shape::Cone(cone) => cone.local_surface_frame(local_point, hit),
+            Shape::Triangle(triangle) => triangle.local_surface_frame(local_point, hit),
+            Shape::Csg(_) => None,
+            Shape::Custom(Custom(primitive)) => primitive.local_surface_frame(local_point, hit),
+        }
+    }
+
+    fn local_intersect(&self, local_ray: Ray) -> Vec<LocalHit> {
+        match self {
+            Shape::Sphere => Sphere {}.local_intersect(local_ray),
+            Shape::Plane => Plane {}.local_intersect(local_ray),
+            Shape::Cube => Cube.local_intersect(local_ray),
+            Shape::Cylinder(cylinder) => cylinder.local_intersect(local_ray),
+            Shape::Cone(cone) => cone.local_intersect(local_ray),
+            Shape::Triangle(triangle) => triangle.local_intersect(local_ray),
             Shape::Csg(_) => unreachable!(),
+            Shape::Custom(Custom(primitive)) => primitive.local_intersect(local_ray),
         }
     }
 }
 
 impl<'a> SimpleObject<'a> {
+    #[cfg(test)]
     pub(crate) fn from_object(object: &'a Object) -> Option<Self> {
         match &object.shape {
             ShapeOrGroup::Shape { material, shape } => Some(Self {
                 transform: object.transform,
-                material: *material,
+                material,
                 shape: shape,
             }),
             ShapeOrGroup::Group(_) => None,
@@ -404,7 +706,7 @@ impl<'a> SimpleObject<'a> {
         self.transform
     }
 
-    pub fn material(&self) -> Material {
+    pub fn material(&self) -> &'a Material {
         self.material
     }
 
@@ -413,10 +715,40 @@ impl<'a> SimpleObject<'a> {
         let local_point = inverse_transform * world_point;
         let local_normal = self.shape.local_normal_at(intersection, local_point);
 
-        let mut world_normal = inverse_transform.transpose() * local_normal;
-        world_normal.w = 0.;
+        to_world_normal(inverse_transform, local_normal)
+    }
+
+    // Texture coordinates at a point, for shapes that have them.
+    pub fn uv_at(&self, intersection: Intersection, world_point: Tuple) -> Option<(f64, f64)> {
+        let local_point = self.transform().inverse().unwrap() * world_point;
+
+        self.shape
+            .local_surface_frame(intersection, local_point)
+            .map(|frame| frame.uv)
+    }
+
+    // Normal used for shading: `normal_at` tilted by the material's bump.
+    pub fn shading_normal_at(&self, intersection: Intersection, world_point: Tuple) -> Tuple {
+        let Some(bump) = &self.material.bump else {
+            return self.normal_at(intersection, world_point);
+        };
+
+        let inverse_transform = self.transform().inverse().unwrap();
+        let local_point = inverse_transform * world_point;
+        let local_normal = self.shape.local_normal_at(intersection, local_point);
+        let frame = self.shape.local_surface_frame(intersection, local_point);
 
-        world_normal.normalize()
+        to_world_normal(
+            inverse_transform,
+            bump.perturb(local_point, local_normal, frame),
+        )
     }
 }
 
+fn to_world_normal(inverse_transform: Matrix4, local_normal: Tuple) -> Tuple {
+    let mut world_normal = inverse_transform.transpose() * local_normal;
+    world_normal.w = 0.;
+
+    world_normal.normalize()
+}
+
@@ -2,6 +2,8 @@ use std::f64::{INFINITY, NEG_INFINITY};
 
 use crate::{math::tuple::Tuple, misc::EPSILON, ray::Ray};
 
+use super::{BoundingBox, LocalHit, Primitive};
+
 #[derive(Clone, Copy, Debug)]
 pub struct Cone {
     pub minimum: f64,
@@ -18,7 +20,27 @@ impl Cone {
         }
     }
 
-    pub fn local_intersect(&self, ray: Ray) -> Vec<f64> {
+    fn intersect_caps(&self, ray: Ray) -> Vec<LocalHit> {
+        let mut xs = Vec::with_capacity(2);
+
+        if !self.closed || ray.direction.y.abs() < EPSILON {
+            return xs;
+        }
+
+        let t_min = (self.minimum - ray.origin.y) / ray.direction.y;
+        let t_max = (self.maximum - ray.origin.y) / ray.direction.y;
+
+        [t_min, t_max]
+            .into_iter()
+            .filter(|t| check_cap(ray, *t))
+            .for_each(|t| xs.push(LocalHit::new(t)));
+
+        xs
+    }
+}
+
+impl Primitive for Cone {
+    fn local_intersect(&self, ray: Ray) -> Vec<LocalHit> {
         let a = ray.direction.x.powi(2) - ray.direction.y.powi(2) + ray.direction.z.powi(2);
         let b = 2. * ray.origin.x * ray.direction.x - 2. * ray.origin.y * ray.direction.y
             + 2. * ray.origin.z * ray.direction.z;
@@ -31,7 +53,7 @@ impl Cone {
         let mut xs = Vec::with_capacity(4);
 
         if a.abs() < EPSILON {
-            xs.push(-c / (2. * b));
+            xs.push(LocalHit::new(-c / (2. * b)));
         }
 
         let disc = b.powi(2) - 4. * a * c;
@@ -46,11 +68,11 @@ impl Cone {
             let y1 = ray.origin.y + t1 * ray.direction.y;
 
             if self.minimum < y0 && y0 < self.maximum {
-                xs.push(t0);
+                xs.push(LocalHit::new(t0));
             }
 
             if self.minimum < y1 && y1 < self.maximum {
-                xs.push(t1);
+                xs.push(LocalHit::new(t1));
             }
 
             xs.append(&mut self.intersect_caps(ray));
@@ -59,7 +81,7 @@ impl Cone {
         xs
     }
 
-    pub fn local_normal_at(&self, local_point: Tuple) -> Tuple {
+    fn local_normal_at(&self, local_point: Tuple, _hit: LocalHit) -> Tuple {
         let dist = local_point.x.powi(2) + local_point.z.powi(2);
         let y_2 = local_point.y.powi(2);
         let y = if local_point.y > 0. {
@@ -77,22 +99,14 @@ impl Cone {
         }
     }
 
-    fn intersect_caps(&self, ray: Ray) -> Vec<f64> {
-        let mut xs = Vec::with_capacity(2);
-
-        if !self.closed || ray.direction.y.abs() < EPSILON {
-            return xs;
-        }
+    fn bounding_box(&self) -> BoundingBox {
+        let max_x = f64::max(self.minimum.abs(), self.maximum.abs());
+        let max_z = max_x;
 
-        let t_min = (self.minimum - ray.origin.y) / ray.direction.y;
-        let t_max = (self.maximum - ray.origin.y) / ray.direction.y;
-
-        [t_min, t_max]
-            .into_iter()
-            .filter(|t| check_cap(ray, *t))
-            .for_each(|t| xs.push(t));
-
-        xs
+        BoundingBox::new(
+            Tuple::point(-max_x, self.minimum, -max_z),
+            Tuple::point(max_x, self.maximum, max_z),
+        )
     }
 }
 
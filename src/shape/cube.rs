@@ -2,18 +2,24 @@ use std::f64::INFINITY;
 
 use crate::{math::tuple::Tuple, misc::EPSILON, ray::Ray};
 
+use super::{BoundingBox, LocalHit, Primitive};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Cube;
 
-impl Cube {
-    pub fn local_intersect(local_ray: Ray) -> Vec<f64> {
+impl Primitive for Cube {
+    fn local_intersect(&self, local_ray: Ray) -> Vec<LocalHit> {
         local_intersect(
             Tuple::point(-1., -1., -1.),
             Tuple::point(1., 1., 1.),
             local_ray,
         )
+        .into_iter()
+        .map(LocalHit::new)
+        .collect()
     }
 
-    pub fn local_normal_at(local_point: Tuple) -> Tuple {
+    fn local_normal_at(&self, local_point: Tuple, _hit: LocalHit) -> Tuple {
         let max_abs = [local_point.x, local_point.y, local_point.z]
             .iter()
             .copied()
@@ -29,6 +35,10 @@ impl Cube {
             Tuple::vector(0., 0., local_point.z)
         }
     }
+
+    fn bounding_box(&self) -> BoundingBox {
+        BoundingBox::new(Tuple::point(-1., -1., -1.), Tuple::point(1., 1., 1.))
+    }
 }
 
 pub fn local_intersect(min: Tuple, max: Tuple, local_ray: Ray) -> Vec<f64> {
@@ -2,6 +2,8 @@ use std::f64::{INFINITY, NEG_INFINITY};
 
 use crate::{math::tuple::Tuple, misc::EPSILON, ray::Ray};
 
+use super::{BoundingBox, LocalHit, Primitive};
+
 #[derive(Clone, Copy, Debug)]
 pub struct Cylinder {
     pub minimum: f64,
@@ -18,7 +20,27 @@ impl Cylinder {
         }
     }
 
-    pub fn local_intersect(&self, ray: Ray) -> Vec<f64> {
+    fn intersect_caps(&self, ray: Ray) -> Vec<LocalHit> {
+        let mut xs = Vec::with_capacity(2);
+
+        if !self.closed || ray.direction.y.abs() < EPSILON {
+            return xs;
+        }
+
+        let t_min = (self.minimum - ray.origin.y) / ray.direction.y;
+        let t_max = (self.maximum - ray.origin.y) / ray.direction.y;
+
+        [t_min, t_max]
+            .into_iter()
+            .filter(|t| check_cap(ray, *t))
+            .for_each(|t| xs.push(LocalHit::new(t)));
+
+        xs
+    }
+}
+
+impl Primitive for Cylinder {
+    fn local_intersect(&self, ray: Ray) -> Vec<LocalHit> {
         let a = ray.direction.x.powi(2) + ray.direction.z.powi(2);
 
         if a.abs() < EPSILON {
@@ -41,11 +63,11 @@ impl Cylinder {
             let mut xs = Vec::with_capacity(2);
 
             if self.minimum < y0 && y0 < self.maximum {
-                xs.push(t0);
+                xs.push(LocalHit::new(t0));
             }
 
             if self.minimum < y1 && y1 < self.maximum {
-                xs.push(t1);
+                xs.push(LocalHit::new(t1));
             }
 
             xs.append(&mut self.intersect_caps(ray));
@@ -53,7 +75,7 @@ impl Cylinder {
         }
     }
 
-    pub fn local_normal_at(&self, local_point: Tuple) -> Tuple {
+    fn local_normal_at(&self, local_point: Tuple, _hit: LocalHit) -> Tuple {
         let dist = local_point.x.powi(2) + local_point.z.powi(2);
 
         if dist < 1. && local_point.y >= self.maximum - EPSILON {
@@ -65,22 +87,11 @@ impl Cylinder {
         }
     }
 
-    fn intersect_caps(&self, ray: Ray) -> Vec<f64> {
-        let mut xs = Vec::with_capacity(2);
-
-        if !self.closed || ray.direction.y.abs() < EPSILON {
-            return xs;
-        }
-
-        let t_min = (self.minimum - ray.origin.y) / ray.direction.y;
-        let t_max = (self.maximum - ray.origin.y) / ray.direction.y;
-
-        [t_min, t_max]
-            .into_iter()
-            .filter(|t| check_cap(ray, *t))
-            .for_each(|t| xs.push(t));
-
-        xs
+    fn bounding_box(&self) -> BoundingBox {
+        BoundingBox::new(
+            Tuple::point(-1., self.minimum, -1.),
+            Tuple::point(1., self.maximum, 1.),
+        )
     }
 }
 
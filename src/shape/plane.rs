@@ -1,23 +1,41 @@
+use crate::bump::SurfaceFrame;
 use crate::math::tuple::Tuple;
 use crate::misc::EPSILON;
 use crate::ray::Ray;
 
+use super::{BoundingBox, LocalHit, Primitive};
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Plane {}
 
-impl Plane {
-    pub fn local_intersect(local_ray: Ray) -> Vec<f64> {
+impl Primitive for Plane {
+    fn local_intersect(&self, local_ray: Ray) -> Vec<LocalHit> {
         if local_ray.direction.y.abs() < EPSILON {
             vec![]
         } else {
             let t = -local_ray.origin.y / local_ray.direction.y;
 
-            vec![t]
+            vec![LocalHit::new(t)]
         }
     }
 
-    pub fn local_normal_at(_: Tuple) -> Tuple {
+    fn local_normal_at(&self, _: Tuple, _hit: LocalHit) -> Tuple {
         Tuple::vector(0., 1., 0.)
     }
-}
 
+    fn bounding_box(&self) -> BoundingBox {
+        BoundingBox::new(
+            Tuple::point(f64::NEG_INFINITY, 0., f64::NEG_INFINITY),
+            Tuple::point(f64::INFINITY, 0., f64::INFINITY),
+        )
+    }
+
+    // Planar mapping repeating every unit along x and z.
+    fn local_surface_frame(&self, local_point: Tuple, _hit: LocalHit) -> Option<SurfaceFrame> {
+        Some(SurfaceFrame {
+            uv: (local_point.x.rem_euclid(1.), local_point.z.rem_euclid(1.)),
+            dpdu: Tuple::vector(1., 0., 0.),
+            dpdv: Tuple::vector(0., 0., 1.),
+        })
+    }
+}
@@ -1,11 +1,17 @@
+use std::f64::consts::PI;
+
+use crate::bump::SurfaceFrame;
 use crate::math::tuple::Tuple;
+use crate::misc::EPSILON;
 use crate::ray::Ray;
 
+use super::{BoundingBox, LocalHit, Primitive};
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Sphere {}
 
-impl Sphere {
-    pub fn local_intersect(local_ray: Ray) -> Vec<f64> {
+impl Primitive for Sphere {
+    fn local_intersect(&self, local_ray: Ray) -> Vec<LocalHit> {
         let sphere_to_ray = local_ray.origin - Tuple::point(0., 0., 0.);
         let a = local_ray.direction.magnitude_squared();
         let b = 2. * local_ray.direction.dot(sphere_to_ray);
@@ -19,12 +25,33 @@ impl Sphere {
             let t1 = (-b - discriminant.sqrt()) / (2. * a);
             let t2 = (-b + discriminant.sqrt()) / (2. * a);
 
-            vec![t1, t2]
+            vec![LocalHit::new(t1), LocalHit::new(t2)]
         }
     }
 
-    pub fn local_normal_at(local_point: Tuple) -> Tuple {
+    fn local_normal_at(&self, local_point: Tuple, _hit: LocalHit) -> Tuple {
         // Warning: do not remove
         local_point - Tuple::point(0., 0., 0.)
     }
-}
\ No newline at end of file
+
+    fn bounding_box(&self) -> BoundingBox {
+        BoundingBox::new(
+            Tuple::point(-(1. + EPSILON), -(1. + EPSILON), -(1. + EPSILON)),
+            Tuple::point(1. + EPSILON, 1. + EPSILON, 1. + EPSILON),
+        )
+    }
+
+    // Spherical mapping: u goes once around the y axis, v from the south
+    // pole to the north pole.
+    fn local_surface_frame(&self, local_point: Tuple, _hit: LocalHit) -> Option<SurfaceFrame> {
+        let Tuple { x, y, z, .. } = local_point;
+        let theta = x.atan2(z);
+        let phi = (y / (x * x + y * y + z * z).sqrt()).clamp(-1., 1.).acos();
+
+        Some(SurfaceFrame {
+            uv: (1. - (theta / (2. * PI) + 0.5), 1. - phi / PI),
+            dpdu: Tuple::vector(-z, 0., x),
+            dpdv: Tuple::vector(-y * x, x * x + z * z, -y * z),
+        })
+    }
+}
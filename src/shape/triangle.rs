@@ -1,4 +1,6 @@
-use crate::{math::tuple::Tuple, misc::EPSILON, ray::Ray, shape::BoundingBox};
+use crate::{bump::SurfaceFrame, math::tuple::Tuple, misc::EPSILON, ray::Ray};
+
+use super::{BoundingBox, LocalHit, Primitive};
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Triangle {
@@ -15,23 +17,46 @@ pub(crate) enum TriangleKind {
 }
 
 impl Triangle {
-    pub(crate) fn new(p1: Tuple, p2: Tuple, p3: Tuple) -> Self {
-        Self {
+    // Fails for triangles that can't be hit: ones with zero area or
+    // non-finite corners.
+    pub fn new(p1: Tuple, p2: Tuple, p3: Tuple) -> Result<Self, String> {
+        let triangle = Self {
             p1,
             p2,
             p3,
             kind: TriangleKind::Flat,
+        };
+
+        if [p1, p2, p3].iter().any(|p| !is_finite(*p)) {
+            return Err("Triangle has a non-finite corner".to_owned());
         }
+        if !is_finite(triangle.normal()) {
+            return Err("Triangle has zero area".to_owned());
+        }
+
+        Ok(triangle)
     }
 
-    #[allow(dead_code)]
-    pub(crate) fn smooth(p1: Tuple, p2: Tuple, p3: Tuple, n1: Tuple, n2: Tuple, n3: Tuple) -> Self {
-        Self {
-            p1,
-            p2,
-            p3,
-            kind: TriangleKind::Smooth { n1, n2, n3 },
+    // Shaded with the normals at its corners blended across it. The normals
+    // needn't be normalized, but must be finite and non-zero.
+    pub fn smooth(
+        p1: Tuple,
+        p2: Tuple,
+        p3: Tuple,
+        n1: Tuple,
+        n2: Tuple,
+        n3: Tuple,
+    ) -> Result<Self, String> {
+        let flat = Self::new(p1, p2, p3)?;
+
+        if [n1, n2, n3].iter().any(|n| !is_finite(n.normalize())) {
+            return Err("Triangle has a zero or non-finite normal".to_owned());
         }
+
+        Ok(Self {
+            kind: TriangleKind::Smooth { n1, n2, n3 },
+            ..flat
+        })
     }
 
     fn edge1(&self) -> Tuple {
@@ -45,19 +70,30 @@ impl Triangle {
     fn normal(&self) -> Tuple {
         self.edge2().cross(self.edge1()).normalize()
     }
+}
 
-    pub(crate) fn local_normal_at(&self, uvt: &UVT) -> Tuple {
-        let UVT { u, v, .. } = uvt;
-
-        match self.kind {
-            TriangleKind::Flat => self.normal(),
-            TriangleKind::Smooth { n1, n2, n3 } => {
-                (n2 * *u + n3 * *v + n1 * (1. - *u - *v)).normalize()
+impl Primitive for Triangle {
+    fn local_normal_at(&self, _local_point: Tuple, hit: LocalHit) -> Tuple {
+        // Without barycentric coordinates there is nothing to blend with,
+        // so smooth triangles fall back to the face normal
+        match (self.kind, hit.uv) {
+            (TriangleKind::Smooth { n1, n2, n3 }, Some((u, v))) => {
+                (n2 * u + n3 * v + n1 * (1. - u - v)).normalize()
             }
+            _ => self.normal(),
         }
     }
 
-    pub(crate) fn local_intersect(&self, local_ray: Ray) -> Vec<UVT> {
+    // Barycentric coordinates double as UV coordinates.
+    fn local_surface_frame(&self, _local_point: Tuple, hit: LocalHit) -> Option<SurfaceFrame> {
+        Some(SurfaceFrame {
+            uv: hit.uv?,
+            dpdu: self.edge1(),
+            dpdv: self.edge2(),
+        })
+    }
+
+    fn local_intersect(&self, local_ray: Ray) -> Vec<LocalHit> {
         let dir_cross_edge2 = local_ray.direction.cross(self.edge2());
         let det = self.edge1().dot(dir_cross_edge2);
 
@@ -79,17 +115,14 @@ impl Triangle {
         }
 
         let t = f * self.edge2().dot(origin_cross_e1);
-        vec![UVT { u, v, t }]
+        vec![LocalHit::with_uv(t, u, v)]
     }
 
-    pub(crate) fn bounding_box(&self) -> BoundingBox {
+    fn bounding_box(&self) -> BoundingBox {
         BoundingBox::from_points(&[self.p1, self.p2, self.p3])
     }
 }
 
-#[derive(Clone, Copy)]
-pub(crate) struct UVT {
-    pub(crate) t: f64,
-    pub(crate) u: f64,
-    pub(crate) v: f64,
-}
\ No newline at end of file
+fn is_finite(tuple: Tuple) -> bool {
+    tuple.x.is_finite() && tuple.y.is_finite() && tuple.z.is_finite()
+}
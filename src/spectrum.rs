@@ -0,0 +1,231 @@
+use crate::{color::Color, sampling::Rng};
+
+// Visible range, in nanometres.
+pub const MIN_WAVELENGTH: f64 = 380.;
+pub const MAX_WAVELENGTH: f64 = 780.;
+
+// CIE 1931 colour matching functions, using the multi-lobe Gaussian fit from
+// Wyman, Sloan and Shirley, "Simple Analytic Approximations to the CIE XYZ
+// Color Matching Functions" (2013).
+pub fn cie_xyz(wavelength: f64) -> (f64, f64, f64) {
+    let x = 1.056 * lobe(wavelength, 599.8, 37.9, 31.0)
+        + 0.362 * lobe(wavelength, 442.0, 16.0, 26.7)
+        - 0.065 * lobe(wavelength, 501.1, 20.4, 26.2);
+    let y =
+        0.821 * lobe(wavelength, 568.8, 46.9, 40.5) + 0.286 * lobe(wavelength, 530.9, 16.3, 31.1);
+    let z =
+        1.217 * lobe(wavelength, 437.0, 11.8, 36.0) + 0.681 * lobe(wavelength, 459.0, 26.0, 13.8);
+
+    (x, y, z)
+}
+
+// Gaussian with a different width on each side of its peak.
+fn lobe(wavelength: f64, peak: f64, width_below: f64, width_above: f64) -> f64 {
+    let width = if wavelength < peak {
+        width_below
+    } else {
+        width_above
+    };
+    let t = (wavelength - peak) / width;
+
+    (-0.5 * t * t).exp()
+}
+
+// How much a single wavelength contributes to each linear sRGB channel.
+// Negative responses outside the sRGB gamut are clipped.
+pub fn rgb_weights(wavelength: f64) -> Color {
+    let (x, y, z) = cie_xyz(wavelength);
+
+    Color::new(
+        (3.2406 * x - 1.5372 * y - 0.4986 * z).max(0.),
+        (-0.9689 * x + 1.8758 * y + 0.0415 * z).max(0.),
+        (0.0557 * x - 0.2040 * y + 1.0570 * z).max(0.),
+    )
+}
+
+// `count` wavelengths covering the visible range, one in each of `count`
+// equal slices.
+pub fn sample_wavelengths(count: usize, rng: &mut Rng) -> Vec<f64> {
+    let slice = (MAX_WAVELENGTH - MIN_WAVELENGTH) / count as f64;
+
+    (0..count)
+        .map(|i| MIN_WAVELENGTH + (i as f64 + rng.next_f64()) * slice)
+        .collect()
+}
+
+// Turns colors traced at single wavelengths back into one RGB color. Each
+// channel is the average of the samples weighted by how much their
+// wavelength shows up in that channel, so a scene without dispersion comes
+// out exactly as it would in RGB mode.
+pub fn combine(samples: &[(f64, Color)]) -> Color {
+    let (total, weights) = samples.iter().fold(
+        (Color::black(), Color::black()),
+        |(total, weights), (wavelength, color)| {
+            let weight = rgb_weights(*wavelength);
+
+            (total + *color * weight, weights + weight)
+        },
+    );
+
+    let average = samples
+        .iter()
+        .fold(Color::black(), |sum, (_, color)| sum + *color)
+        * (1. / samples.len().max(1) as f64);
+    let channel = |total: f64, weight: f64, fallback: f64| {
+        if weight > 0. {
+            total / weight
+        } else {
+            fallback
+        }
+    };
+
+    Color::new(
+        channel(total.red, weights.red, average.red),
+        channel(total.green, weights.green, average.green),
+        channel(total.blue, weights.blue, average.blue),
+    )
+}
+
+// Wavelength dependent refractive index. Wavelengths are given in nanometres
+// but the coefficients use micrometres, as in most glass catalogues.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Dispersion {
+    // n = a + b / λ²
+    Cauchy { a: f64, b: f64 },
+    // n² = 1 + Σ bᵢ λ² / (λ² - cᵢ)
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    // Schott N-BK7 crown glass.
+    pub fn crown_glass() -> Self {
+        Dispersion::Sellmeier {
+            b: [1.039_612_12, 0.231_792_344, 1.010_469_45],
+            c: [0.006_000_698_67, 0.020_017_914_4, 103.560_653],
+        }
+    }
+
+    // Schott SF11 dense flint glass, which spreads colors much more.
+    pub fn flint_glass() -> Self {
+        Dispersion::Sellmeier {
+            b: [1.737_596_95, 0.313_747_346, 1.898_781_01],
+            c: [0.013_188_707, 0.062_306_814_2, 155.236_29],
+        }
+    }
+
+    pub fn diamond() -> Self {
+        Dispersion::Sellmeier {
+            b: [0.3306, 4.3356, 0.],
+            c: [0.175 * 0.175, 0.106 * 0.106, 0.],
+        }
+    }
+
+    pub fn refractive_index(&self, wavelength: f64) -> f64 {
+        let micrometres = wavelength / 1000.;
+        let l2 = micrometres * micrometres;
+
+        match *self {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => {
+                let sum: f64 = b.iter().zip(c.iter()).map(|(b, c)| b * l2 / (l2 - c)).sum();
+
+                (1. + sum).sqrt()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::misc::approx_equal;
+
+    #[test]
+    fn the_luminance_curve_peaks_in_the_green() {
+        let (_, y_green, _) = cie_xyz(555.);
+        let (_, y_blue, _) = cie_xyz(450.);
+        let (_, y_red, _) = cie_xyz(650.);
+
+        assert!((y_green - 1.).abs() < 0.02);
+        assert!(y_green > y_blue && y_green > y_red);
+    }
+
+    #[test]
+    fn wavelengths_map_to_their_expected_channels() {
+        let red = rgb_weights(640.);
+        let green = rgb_weights(530.);
+        let blue = rgb_weights(450.);
+
+        assert!(red.red > red.green && red.red > red.blue);
+        assert!(green.green > green.red && green.green > green.blue);
+        assert!(blue.blue > blue.red && blue.blue > blue.green);
+    }
+
+    #[test]
+    fn sampled_wavelengths_cover_the_visible_range() {
+        let mut rng = Rng::new(7);
+        let wavelengths = sample_wavelengths(4, &mut rng);
+
+        for (i, wavelength) in wavelengths.iter().enumerate() {
+            let slice_start = MIN_WAVELENGTH + i as f64 * 100.;
+            assert!(slice_start <= *wavelength && *wavelength < slice_start + 100.);
+        }
+    }
+
+    #[test]
+    fn combining_identical_samples_gives_back_the_color() {
+        let color = Color::new(0.2, 0.5, 0.9);
+        let mut rng = Rng::new(1);
+        let samples: Vec<_> = sample_wavelengths(8, &mut rng)
+            .into_iter()
+            .map(|wavelength| (wavelength, color))
+            .collect();
+
+        assert_eq!(combine(&samples), color);
+    }
+
+    #[test]
+    fn light_only_at_long_wavelengths_looks_red() {
+        let mut rng = Rng::new(1);
+        let samples: Vec<_> = sample_wavelengths(16, &mut rng)
+            .into_iter()
+            .map(|wavelength| {
+                let color = if wavelength > 600. {
+                    Color::white()
+                } else {
+                    Color::black()
+                };
+                (wavelength, color)
+            })
+            .collect();
+
+        let color = combine(&samples);
+
+        assert!(color.red > 0.5);
+        assert!(color.blue < 0.1);
+    }
+
+    #[test]
+    fn refractive_indices_from_sellmeier_coefficients() {
+        // Catalogue values at the helium d line
+        let d_line = 587.56;
+
+        assert!(approx_equal(
+            (Dispersion::crown_glass().refractive_index(d_line) * 1e4).round() / 1e4,
+            1.5168
+        ));
+        assert!(approx_equal(
+            (Dispersion::flint_glass().refractive_index(d_line) * 1e4).round() / 1e4,
+            1.7847
+        ));
+        assert!((Dispersion::diamond().refractive_index(d_line) - 2.417).abs() < 0.01);
+    }
+
+    #[test]
+    fn refractive_indices_from_cauchy_coefficients() {
+        let dispersion = Dispersion::Cauchy { a: 1.5, b: 0.004 };
+
+        assert!(approx_equal(dispersion.refractive_index(400.), 1.525));
+        assert!(dispersion.refractive_index(450.) > dispersion.refractive_index(650.));
+    }
+}
@@ -0,0 +1,342 @@
+use std::{
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use crate::{
+    camera::{Camera, Cancelled},
+    canvas::{Canvas, Region},
+    color::Color,
+    progress::{Progress, ProgressObserver, Silent},
+    world::World,
+};
+
+const TILE_MAGIC: &str = "TILE";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TileSettings {
+    pub tile_size: usize,
+    // Part of the image to render, all of it when `None`.
+    pub region: Option<Region>,
+    // Finished tiles are saved here, and tiles already there are loaded
+    // instead of rendered, so an interrupted render picks up where it
+    // stopped. Only the image size is checked, so empty the directory when
+    // the scene or camera changes.
+    pub checkpoint_dir: Option<PathBuf>,
+}
+
+impl Default for TileSettings {
+    fn default() -> Self {
+        Self {
+            tile_size: 32,
+            region: None,
+            checkpoint_dir: None,
+        }
+    }
+}
+
+// Renders the region tile by tile, telling `observer` after each tile. The
+// canvas is the size of the region, and every pixel is exactly what a full
+// render gives. Cancelling returns an `Interrupted` error, leaving the
+// finished tiles in the checkpoint directory.
+pub fn render_tiled(
+    camera: Camera,
+    world: &World,
+    settings: &TileSettings,
+    observer: &mut dyn ProgressObserver,
+) -> std::io::Result<Canvas> {
+    let (width, height) = (camera.hsize as usize, camera.vsize as usize);
+    let region = settings
+        .region
+        .unwrap_or(camera.frame())
+        .clipped(width, height);
+    let tiles = region.tiles(settings.tile_size);
+    let mut canvas = Canvas::new(region.width, region.height);
+
+    if let Some(directory) = &settings.checkpoint_dir {
+        std::fs::create_dir_all(directory)?;
+    }
+
+    let started = Instant::now();
+    let progress = |done: usize| Progress {
+        done,
+        total: tiles.len(),
+        elapsed: started.elapsed(),
+    };
+
+    observer.update(progress(0));
+    for (done, tile) in tiles.iter().enumerate() {
+        if observer.is_cancelled() {
+            return Err(Error::new(ErrorKind::Interrupted, "Render cancelled"));
+        }
+
+        let directory = settings.checkpoint_dir.as_deref();
+        let saved = directory.and_then(|directory| load_tile(directory, (width, height), *tile));
+
+        let rendered = match saved {
+            Some(canvas) => canvas,
+            None => {
+                // Silent never cancels, cancellation is checked between tiles
+                let rendered = camera
+                    .render_region_with(world, *tile, &mut Silent)
+                    .unwrap_or_else(|Cancelled(partial)| partial);
+                if let Some(directory) = directory {
+                    save_tile(directory, (width, height), *tile, &rendered)?;
+                }
+
+                rendered
+            }
+        };
+
+        canvas.paste(&rendered, tile.x - region.x, tile.y - region.y);
+        observer.update(progress(done + 1));
+    }
+    observer.finish(progress(tiles.len()));
+
+    Ok(canvas)
+}
+
+// A checkpointed tile, if there is a readable one for this image.
+pub(crate) fn load_tile(directory: &Path, size: (usize, usize), tile: Region) -> Option<Canvas> {
+    let bytes = std::fs::read(tile_path(directory, tile)).ok()?;
+
+    read_tile(&bytes, size, tile).ok()
+}
+
+pub(crate) fn save_tile(
+    directory: &Path,
+    size: (usize, usize),
+    tile: Region,
+    canvas: &Canvas,
+) -> std::io::Result<()> {
+    write_atomically(&tile_path(directory, tile), &write_tile(canvas, size, tile))
+}
+
+fn tile_path(directory: &Path, tile: Region) -> PathBuf {
+    directory.join(format!(
+        "tile_{}_{}_{}x{}.tile",
+        tile.x, tile.y, tile.width, tile.height
+    ))
+}
+
+// Written next to the final file and renamed over it, so a render killed
+// mid-write never leaves half a tile behind.
+fn write_atomically(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let partial = path.with_extension("partial");
+    std::fs::write(&partial, bytes)?;
+
+    std::fs::rename(partial, path)
+}
+
+// A short text header with the image size and the tile's place in it,
+// followed by the colors as little endian f64s so they load back exactly.
+pub(crate) fn write_tile(canvas: &Canvas, size: (usize, usize), tile: Region) -> Vec<u8> {
+    let mut bytes = tile_header(size, tile).into_bytes();
+
+    for y in 0..tile.height as i32 {
+        for x in 0..tile.width as i32 {
+            let color = canvas.pixel_at(x, y);
+            for value in [color.red, color.green, color.blue] {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+
+    bytes
+}
+
+// Bytes `write_tile` produces for a tile.
+pub(crate) fn tile_length(size: (usize, usize), tile: Region) -> usize {
+    tile_header(size, tile).len() + tile.width * tile.height * 3 * 8
+}
+
+fn tile_header((width, height): (usize, usize), tile: Region) -> String {
+    format!(
+        "{}\n{} {}\n{} {} {} {}\n",
+        TILE_MAGIC, width, height, tile.x, tile.y, tile.width, tile.height
+    )
+}
+
+pub(crate) fn read_tile(
+    bytes: &[u8],
+    size: (usize, usize),
+    tile: Region,
+) -> Result<Canvas, String> {
+    let header = tile_header(size, tile);
+    let header_length = header.len();
+    if bytes.get(..header_length) != Some(header.as_bytes()) {
+        return Err("Tile was saved for a different image".to_owned());
+    }
+
+    let data = &bytes[header_length..];
+    if data.len() != tile.width * tile.height * 3 * 8 {
+        return Err(format!("Tile has {} bytes of pixel data", data.len()));
+    }
+
+    let values: Vec<f64> = data
+        .chunks(8)
+        .map(|value| f64::from_le_bytes(value.try_into().unwrap()))
+        .collect();
+    let mut canvas = Canvas::new(tile.width, tile.height);
+    for (index, rgb) in values.chunks(3).enumerate() {
+        canvas.write_pixel(
+            (index % tile.width) as i32,
+            (index / tile.width) as i32,
+            Color::new(rgb[0], rgb[1], rgb[2]),
+        );
+    }
+
+    Ok(canvas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        light::Light, material::Material, math::transformations::view_transform,
+        math::tuple::Tuple, progress::CancelToken, shape::Object,
+    };
+
+    fn scene() -> (Camera, World) {
+        let mut world = World::new();
+        world.add_light(Light::point_light(
+            Tuple::point(-10., 10., -10.),
+            Color::white(),
+        ));
+        let mut sphere = Object::sphere();
+        sphere.set_material(Material::new());
+        world.add_object(sphere);
+
+        let mut camera = Camera::new(7, 5, 1.);
+        camera.transform = view_transform(
+            Tuple::point(0., 0., -5.),
+            Tuple::point(0., 0., 0.),
+            Tuple::vector(0., 1., 0.),
+        );
+
+        (camera, world)
+    }
+
+    fn same_pixels(a: &Canvas, b: &Canvas) -> bool {
+        let bits = |c: Color| (c.red.to_bits(), c.green.to_bits(), c.blue.to_bits());
+
+        a.width() == b.width()
+            && a.height() == b.height()
+            && (0..a.height() as i32).all(|y| {
+                (0..a.width() as i32).all(|x| bits(a.pixel_at(x, y)) == bits(b.pixel_at(x, y)))
+            })
+    }
+
+    fn checkpoint_dir(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("ray_tracer_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+
+        directory
+    }
+
+    #[test]
+    fn regions_split_into_tiles_that_cover_them() {
+        let tiles = Region::new(1, 2, 5, 3).tiles(2);
+
+        assert_eq!(tiles.len(), 6);
+        assert_eq!(tiles[0], Region::new(1, 2, 2, 2));
+        assert_eq!(tiles[2], Region::new(5, 2, 1, 2));
+        assert_eq!(tiles[5], Region::new(5, 4, 1, 1));
+    }
+
+    #[test]
+    fn regions_are_clipped_to_the_image() {
+        assert_eq!(
+            Region::new(5, 3, 10, 10).clipped(7, 5),
+            Region::new(5, 3, 2, 2)
+        );
+        assert_eq!(Region::new(9, 9, 1, 1).clipped(7, 5).width, 0);
+    }
+
+    #[test]
+    fn a_region_renders_like_the_same_part_of_the_full_image() {
+        let (camera, world) = scene();
+        let full = camera.render_with(&world, &mut Silent).unwrap();
+        let region = Region::new(2, 1, 3, 3);
+
+        let cropped = camera
+            .render_region_with(&world, region, &mut Silent)
+            .unwrap();
+
+        assert!(same_pixels(&cropped, &full.crop(region)));
+
+        let mut placed = Canvas::new(7, 5);
+        placed.paste(&cropped, region.x, region.y);
+        assert_eq!(placed.pixel_at(3, 2), full.pixel_at(3, 2));
+        assert_eq!(placed.pixel_at(0, 0), Color::black());
+    }
+
+    #[test]
+    fn tiles_merge_into_exactly_the_full_image() {
+        let (camera, world) = scene();
+        let settings = TileSettings {
+            tile_size: 3,
+            ..TileSettings::default()
+        };
+
+        let tiled = render_tiled(camera, &world, &settings, &mut Silent).unwrap();
+
+        let full = camera.render_with(&world, &mut Silent).unwrap();
+        assert!(same_pixels(&tiled, &full));
+    }
+
+    #[test]
+    fn an_interrupted_render_resumes_from_its_checkpoints() {
+        let (camera, world) = scene();
+        let directory = checkpoint_dir("resume");
+        let settings = TileSettings {
+            tile_size: 3,
+            checkpoint_dir: Some(directory.clone()),
+            ..TileSettings::default()
+        };
+
+        let token = CancelToken::new();
+        let cancel = token.clone();
+        let mut observer = (move |progress: Progress| {
+            if progress.done == 2 {
+                cancel.cancel()
+            }
+        })
+        .with_cancel(token);
+        let error = render_tiled(camera, &world, &settings, &mut observer).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Interrupted);
+        assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 2);
+
+        // Saved tiles are loaded, so they keep the sphere even though it has
+        // gone from the world
+        let mut emptied = World::new();
+        emptied.add_light(world.lights()[0]);
+        let resumed = render_tiled(camera, &emptied, &settings, &mut Silent).unwrap();
+        let full = camera.render_with(&world, &mut Silent).unwrap();
+
+        assert!(same_pixels(
+            &resumed.crop(Region::new(0, 0, 6, 3)),
+            &full.crop(Region::new(0, 0, 6, 3))
+        ));
+        assert_eq!(resumed.pixel_at(6, 2), Color::black());
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn tiles_saved_for_another_image_are_not_loaded() {
+        let tile = Region::new(0, 0, 1, 1);
+        let mut canvas = Canvas::new(1, 1);
+        canvas.write_pixel(0, 0, Color::new(0.1, 0.2, 0.3));
+        let bytes = write_tile(&canvas, (7, 5), tile);
+
+        assert_eq!(
+            read_tile(&bytes, (7, 5), tile).unwrap().pixel_at(0, 0),
+            Color::new(0.1, 0.2, 0.3)
+        );
+        assert!(read_tile(&bytes, (8, 5), tile).is_err());
+        assert!(read_tile(&bytes[..bytes.len() - 1], (7, 5), tile).is_err());
+    }
+}
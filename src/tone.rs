@@ -0,0 +1,196 @@
+use crate::{canvas::Canvas, color::Color};
+
+// Compresses linear radiance into [0, 1] for display.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneOperator {
+    // Cuts off everything above 1.
+    Clamp,
+    // `c / (1 + c)` per channel: never blows out but flattens highlights.
+    Reinhard,
+    // Krzysztof Narkowicz's fit of the ACES filmic curve, with a toe in the
+    // shadows and a soft shoulder.
+    Aces,
+}
+
+// How display values are stored in 8-bit files.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    // Values are written as they are, for data like normals and depth.
+    Linear,
+    // The sRGB transfer curve.
+    Srgb,
+    // A plain power curve with the given display gamma, e.g. 2.2.
+    Gamma(f64),
+}
+
+// Turns the linear colors of a render into what gets written to 8-bit
+// images. `exposure` is in stops, so each +1 doubles the brightness before
+// the operator is applied.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ToneMapping {
+    pub exposure: f64,
+    pub operator: ToneOperator,
+    pub encoding: Encoding,
+}
+
+impl ToneMapping {
+    // Clamped and sRGB encoded.
+    pub fn new() -> Self {
+        Self {
+            exposure: 0.,
+            operator: ToneOperator::Clamp,
+            encoding: Encoding::Srgb,
+        }
+    }
+
+    // Clamped and written as they are.
+    pub fn linear() -> Self {
+        Self {
+            encoding: Encoding::Linear,
+            ..Self::new()
+        }
+    }
+
+    pub fn apply(&self, color: Color) -> Color {
+        let scale = self.exposure.exp2();
+
+        per_channel(color, |value| {
+            let mapped = match self.operator {
+                ToneOperator::Clamp => value * scale,
+                ToneOperator::Reinhard => reinhard(value * scale),
+                ToneOperator::Aces => aces(value * scale),
+            }
+            .clamp(0., 1.);
+
+            match self.encoding {
+                Encoding::Linear => mapped,
+                Encoding::Srgb => srgb(mapped),
+                Encoding::Gamma(gamma) => mapped.powf(1. / gamma),
+            }
+        })
+    }
+
+    pub fn apply_to(&self, canvas: &Canvas) -> Canvas {
+        let mut mapped = Canvas::new(canvas.width(), canvas.height());
+        for y in 0..canvas.height() as i32 {
+            for x in 0..canvas.width() as i32 {
+                mapped.write_pixel(x, y, self.apply(canvas.pixel_at(x, y)));
+            }
+        }
+
+        mapped
+    }
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn per_channel(color: Color, f: impl Fn(f64) -> f64) -> Color {
+    Color::new(f(color.red), f(color.green), f(color.blue))
+}
+
+fn reinhard(value: f64) -> f64 {
+    let value = value.max(0.);
+
+    value / (1. + value)
+}
+
+fn aces(value: f64) -> f64 {
+    let value = value.max(0.);
+
+    (value * (2.51 * value + 0.03)) / (value * (2.43 * value + 0.59) + 0.14)
+}
+
+fn srgb(value: f64) -> f64 {
+    if value <= 0.0031308 {
+        12.92 * value
+    } else {
+        1.055 * value.powf(1. / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::misc::approx_equal;
+
+    fn gray(value: f64) -> Color {
+        Color::new(value, value, value)
+    }
+
+    #[test]
+    fn linear_clamping_keeps_values_in_range() {
+        let tone = ToneMapping::linear();
+
+        assert_eq!(tone.apply(gray(0.25)), gray(0.25));
+        assert_eq!(tone.apply(Color::new(-1., 3., 1.)), Color::new(0., 1., 1.));
+    }
+
+    #[test]
+    fn srgb_brightens_midtones_and_keeps_the_ends() {
+        let tone = ToneMapping::new();
+
+        assert_eq!(tone.apply(Color::black()), Color::black());
+        assert_eq!(tone.apply(Color::white()), Color::white());
+        assert!(approx_equal(tone.apply(gray(0.5)).red, 0.735356983));
+        assert!(approx_equal(tone.apply(gray(0.002)).red, 0.02584));
+    }
+
+    #[test]
+    fn gamma_is_a_power_curve() {
+        let mut tone = ToneMapping::linear();
+        tone.encoding = Encoding::Gamma(2.);
+
+        assert_eq!(tone.apply(gray(0.25)), gray(0.5));
+    }
+
+    #[test]
+    fn each_stop_of_exposure_doubles_the_brightness() {
+        let mut tone = ToneMapping::linear();
+        tone.exposure = 1.;
+
+        assert_eq!(tone.apply(gray(0.2)), gray(0.4));
+        tone.exposure = -2.;
+        assert_eq!(tone.apply(gray(0.2)), gray(0.05));
+    }
+
+    #[test]
+    fn reinhard_and_aces_keep_highlights_apart() {
+        for operator in [ToneOperator::Reinhard, ToneOperator::Aces] {
+            let mut tone = ToneMapping::linear();
+            tone.operator = operator;
+            let bright = tone.apply(gray(2.)).red;
+            let brighter = tone.apply(gray(4.)).red;
+
+            assert!(bright < brighter && brighter < 1.);
+            assert_eq!(tone.apply(Color::black()), Color::black());
+        }
+    }
+
+    #[test]
+    fn reinhard_maps_one_to_a_half() {
+        let mut tone = ToneMapping::linear();
+        tone.operator = ToneOperator::Reinhard;
+
+        assert_eq!(tone.apply(Color::white()), gray(0.5));
+    }
+
+    #[test]
+    fn ppm_output_is_tone_mapped_and_pfm_output_is_not() {
+        let mut canvas = Canvas::new(1, 2);
+        canvas.write_pixel(0, 0, gray(0.5));
+        canvas.write_pixel(0, 1, gray(4.));
+
+        assert_eq!(canvas.to_ppm(), "P3\n1 2\n255\n188 188 188\n255 255 255\n");
+
+        let pfm = canvas.to_pfm();
+        let header = b"PF\n1 2\n-1.0\n";
+        assert_eq!(&pfm[..header.len()], header);
+        // Bottom row first
+        assert_eq!(pfm[header.len()..header.len() + 4], 4f32.to_le_bytes());
+        assert_eq!(pfm.len(), header.len() + 2 * 3 * 4);
+    }
+}
@@ -1,16 +1,85 @@
+use std::f64::consts::FRAC_PI_2;
+
+use crate::aov::{Aov, AovSample};
+use crate::camera::Camera;
 use crate::color::Color;
 use crate::intersection::{ComputedIntersection, Intersection};
 use crate::light::Light;
-use crate::material;
+use crate::material::{self, Material};
+use crate::math::matrix4::Matrix4;
 use crate::math::tuple::Tuple;
+use crate::medium::{Fog, Medium};
+use crate::occlusion::AmbientOcclusion;
 use crate::ray::Ray;
-use crate::shape::Object;
+use crate::sampling::{self, Rng};
+use crate::shape::{self, Node, Object, SimpleObject};
 
 const DEFAULT_ALLOWED_DEPTH: i32 = 8;
+const DEFAULT_GLOSSY_SAMPLES: usize = 8;
+// Media are marched at most this many mean free paths deep, where less than
+// 0.005% of the light is left.
+const MAX_OPTICAL_DEPTH: f64 = 10.;
+
+// The color at a surface split by where the light comes from, before
+// anything between the surface and the viewer is applied.
+struct Shading {
+    albedo: Color,
+    direct: Color,
+    reflection: Color,
+    refraction: Color,
+    // Average share of each light that reaches the surface.
+    shadow: Color,
+    // Share of the sky left open, when the world uses ambient occlusion.
+    occlusion: Option<f64>,
+}
+
+// What lies between the ray origin and a surface: the surface's color is
+// multiplied by `transmittance` and `in_scattered` light is added on top.
+#[derive(Clone, Copy)]
+struct Attenuation {
+    transmittance: Color,
+    in_scattered: Color,
+}
+
+impl Attenuation {
+    fn clear() -> Self {
+        Self {
+            transmittance: Color::white(),
+            in_scattered: Color::black(),
+        }
+    }
+
+    fn apply(self, color: Color) -> Color {
+        color * self.transmittance + self.in_scattered
+    }
+}
+
+// The nearest surface under a pixel, see `World::pick`.
+#[derive(Clone, Debug)]
+pub struct Pick<'a> {
+    // Finds the object again with `World::find`, see `Node::path`.
+    pub path: String,
+    pub object: &'a Object,
+    // Distance along the camera ray.
+    pub t: f64,
+    pub point: Tuple,
+    // Facing the camera.
+    pub normal: Tuple,
+    pub uv: Option<(f64, f64)>,
+}
 
 pub struct World {
     pub objects: Vec<Object>,
     lights: Vec<Light>,
+    pub fog: Option<Fog>,
+    // Rays averaged for rough reflection and refraction at surfaces seen
+    // directly by the camera. Deeper bounces use a single jittered ray so
+    // the cost doesn't multiply with every bounce.
+    pub glossy_samples: usize,
+    // Changes the noise pattern of rough surfaces between renders.
+    pub seed: u64,
+    // Darkens the ambient term where nearby geometry hides the sky.
+    pub ambient_occlusion: Option<AmbientOcclusion>,
 }
 
 impl World {
@@ -18,11 +87,24 @@ impl World {
         Self {
             objects: vec![],
             lights: vec![],
+            fog: None,
+            glossy_samples: DEFAULT_GLOSSY_SAMPLES,
+            seed: 0,
+            ambient_occlusion: None,
         }
     }
 
-    pub fn add_light(&mut self, light: Light) {
-        self.lights.push(light)
+    pub fn add_light(&mut self, light: Light) -> usize {
+        self.lights.push(light);
+        self.lights.len() - 1
+    }
+
+    pub fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    pub fn lights_mut(&mut self) -> &mut [Light] {
+        &mut self.lights
     }
 
     pub fn add_object(&mut self, object: Object) -> usize {
@@ -30,22 +112,309 @@ impl World {
         self.objects.len() - 1
     }
 
+    // An object by path, like "teapot/lid", see `Object::find`.
+    pub fn find(&self, path: &str) -> Option<&Object> {
+        let (index, rest) = self.top_level(path)?;
+
+        match rest {
+            Some(rest) => self.objects[index].find(rest),
+            None => Some(&self.objects[index]),
+        }
+    }
+
+    pub fn find_mut(&mut self, path: &str) -> Option<&mut Object> {
+        let (index, rest) = self.top_level(path)?;
+
+        match rest {
+            Some(rest) => self.objects[index].find_mut(rest),
+            None => Some(&mut self.objects[index]),
+        }
+    }
+
+    // Every object in the world, parents before their children, with
+    // transforms into world space.
+    pub fn nodes(&self) -> Vec<Node<'_>> {
+        let mut nodes = vec![];
+        let objects: Vec<&Object> = self.objects.iter().collect();
+        for (index, object) in objects.iter().enumerate() {
+            let path = shape::node_path(None, &objects, index);
+            object.collect_nodes(path, Matrix4::identity(), &mut nodes);
+        }
+
+        nodes
+    }
+
+    // The group or CSG shape holding the object at `path`. Top-level
+    // objects have none.
+    pub fn parent(&self, path: &str) -> Option<&Object> {
+        let (index, rest) = self.top_level(path)?;
+
+        self.objects[index].parent(rest?)
+    }
+
+    // Takes an object out of the world or out of its group. Indices of the
+    // objects after it go down by one.
+    pub fn remove(&mut self, path: &str) -> Option<Object> {
+        let (index, rest) = self.top_level(path)?;
+
+        match rest {
+            Some(rest) => self.objects[index].remove(rest),
+            None => Some(self.objects.remove(index)),
+        }
+    }
+
+    // Puts `object` in place of the one at `path`, returning that one.
+    pub fn replace(&mut self, path: &str, object: Object) -> Option<Object> {
+        self.find_mut(path)
+            .map(|old| std::mem::replace(old, object))
+    }
+
+    // The index of the top-level object a path starts at, and the rest of
+    // the path.
+    fn top_level<'p>(&self, path: &'p str) -> Option<(usize, Option<&'p str>)> {
+        let (first, rest) = match path.split_once('/') {
+            Some((first, rest)) => (first, Some(rest)),
+            None => (path, None),
+        };
+        let objects: Vec<&Object> = self.objects.iter().collect();
+
+        Some((shape::child_index(&objects, first)?, rest))
+    }
+
     pub fn color_at(&self, ray: Ray) -> Color {
         self.color_at_with_depth(ray, DEFAULT_ALLOWED_DEPTH)
     }
 
+    // Rough surfaces hit by `ray` average `World::glossy_samples` rays.
     pub fn color_at_with_depth(&self, ray: Ray, remaining_depth: i32) -> Color {
+        self.trace(ray, remaining_depth, self.glossy_samples)
+    }
+
+    // Rough surfaces hit by `ray` average `glossy_samples` rays, the
+    // bounces after them a single ray each.
+    fn trace(&self, ray: Ray, remaining_depth: i32, glossy_samples: usize) -> Color {
         let intersections = self.intersect(ray);
 
         let hit = Intersection::hit(&intersections);
 
         if let Some(i) = hit {
-            self.shade_hit(i.prepare_computations(ray, &intersections), remaining_depth)
+            let comps = i.prepare_computations(ray, &intersections);
+            let color = self.shade_hit(comps, remaining_depth, glossy_samples);
+
+            self.towards_origin(ray, i.t, comps).apply(color)
         } else {
-            Color::black()
+            self.through_fog(ray, f64::INFINITY).in_scattered
+        }
+    }
+
+    // Color along `ray` with what the AOV passes record about the surface it
+    // hits first. Occlusion is only estimated when `aovs` asks for it.
+    pub fn trace_aovs(&self, ray: Ray, aovs: &[Aov]) -> AovSample<'_> {
+        let intersections = self.intersect(ray);
+
+        let Some(i) = Intersection::hit(&intersections) else {
+            return AovSample {
+                color: self.through_fog(ray, f64::INFINITY).in_scattered,
+                ..AovSample::miss()
+            };
+        };
+
+        let comps = i.prepare_computations(ray, &intersections);
+        let shading = self.shade_parts(comps, DEFAULT_ALLOWED_DEPTH, self.glossy_samples);
+        let color = shading.direct + shading.reflection + shading.refraction;
+        // The light scattered towards the camera on the way counts as direct
+        // so that the parts still add up to the color
+        let attenuation = self.towards_origin(ray, i.t, comps);
+
+        AovSample {
+            color: attenuation.apply(color),
+            depth: Some(i.t),
+            normal: Some(comps.normal_vector),
+            albedo: shading.albedo,
+            object: self.objects.iter().position(|o| o.includes(i.object)),
+            material: Some(i.object.material()),
+            direct: attenuation.apply(shading.direct),
+            reflection: shading.reflection * attenuation.transmittance,
+            refraction: shading.refraction * attenuation.transmittance,
+            shadow: shading.shadow,
+            occlusion: aovs.contains(&Aov::AmbientOcclusion).then(|| {
+                shading.occlusion.unwrap_or_else(|| {
+                    self.unoccluded_fraction(&comps, AmbientOcclusion::default())
+                })
+            }),
+        }
+    }
+
+    // What the camera sees through the centre of pixel (x, y) as the
+    // shutter opens, if anything.
+    pub fn pick(&self, camera: &Camera, x: i32, y: i32) -> Option<Pick<'_>> {
+        let ray = camera.ray_for_pixel(x, y)?.at_time(camera.shutter_open);
+        let intersections = self.intersect(ray);
+        let i = Intersection::hit(&intersections)?;
+
+        let point = ray.position(i.t);
+        let normal = i.object.normal_at(*i, point);
+        let normal = if normal.dot(ray.direction) > 0. {
+            -normal
+        } else {
+            normal
+        };
+
+        let (path, object) = shape::hit_path(self.objects.iter().collect(), i.object)?;
+
+        Some(Pick {
+            path,
+            object,
+            t: i.t,
+            point,
+            normal,
+            uv: i.object.uv_at(*i, point),
+        })
+    }
+
+    // The distinct materials in the world, in the order objects were added.
+    // Every shape holds its own copy of its material, so equal copies (on
+    // each triangle of a mesh, say) are listed once.
+    pub fn materials(&self) -> Vec<&Material> {
+        let mut materials: Vec<&Material> = vec![];
+        for material in self.objects.iter().flat_map(|object| object.materials()) {
+            if !materials.contains(&material) {
+                materials.push(material);
+            }
+        }
+
+        materials
+    }
+
+    // What lies between the ray origin and a surface `distance` along the
+    // ray.
+    fn towards_origin(&self, ray: Ray, distance: f64, comps: ComputedIntersection) -> Attenuation {
+        match comps.container.map(|object| object.material()) {
+            Some(material) => {
+                let attenuation = match material.medium {
+                    Some(medium) => self.through_medium(ray, distance, medium),
+                    None => self.through_fog(ray, distance),
+                };
+
+                Attenuation {
+                    transmittance: attenuation.transmittance * material.absorption_over(distance),
+                    ..attenuation
+                }
+            }
+            None => self.through_fog(ray, distance),
         }
     }
 
+    fn through_fog(&self, ray: Ray, distance: f64) -> Attenuation {
+        match self.fog {
+            Some(fog) => self.through_medium(ray, distance.min(fog.max_distance), fog.medium),
+            None => Attenuation::clear(),
+        }
+    }
+
+    // Attenuation of the color seen at `distance` along the ray, with the
+    // light scattered towards the ray origin on the way (single scattering).
+    fn through_medium(&self, ray: Ray, distance: f64, medium: Medium) -> Attenuation {
+        let transmittance = medium.transmittance(distance);
+        let transmittance = Color::new(transmittance, transmittance, transmittance);
+        if medium.scattering <= 0. {
+            return Attenuation {
+                transmittance,
+                in_scattered: Color::black(),
+            };
+        }
+
+        // Light scattered further away than this is too faint to matter, and
+        // rays that hit nothing have no end to march to
+        let marched = distance.min(MAX_OPTICAL_DEPTH / medium.extinction());
+        let steps = medium.steps.max(1);
+        let step_size = marched / steps as f64;
+        let direction = ray.direction.normalize();
+
+        // Jitter the samples to trade banding for noise, seeded by the ray so
+        // renders stay reproducible
+        let mut rng = Rng::from_seeds(&[
+            ray.origin.x.to_bits(),
+            ray.origin.y.to_bits(),
+            ray.origin.z.to_bits(),
+            direction.x.to_bits(),
+            direction.y.to_bits(),
+            direction.z.to_bits(),
+        ]);
+        let jitter = rng.next_f64();
+
+        let in_scattered = (0..steps)
+            .map(|step| {
+                let s = (step as f64 + jitter) * step_size;
+                let point = ray.position(s);
+
+                let from_lights = self
+                    .lights
+                    .iter()
+                    .map(|light| {
+                        let transmittance =
+                            self.light_transmittance(point, *light, ray.time, ray.wavelength);
+                        let cos_theta = direction.dot((light.position - point).normalize());
+
+                        light.intensity * transmittance * medium.phase(cos_theta)
+                    })
+                    .fold(Color::black(), |c1, c2| c1 + c2);
+
+                from_lights
+                    * medium.color
+                    * (medium.scattering * medium.transmittance(s) * step_size)
+            })
+            .fold(Color::black(), |c1, c2| c1 + c2);
+
+        Attenuation {
+            transmittance,
+            in_scattered,
+        }
+    }
+
+    // How much of the light reaches `point` through the objects and media
+    // in between.
+    fn light_transmittance(
+        &self,
+        point: Tuple,
+        light: Light,
+        time: f64,
+        wavelength: Option<f64>,
+    ) -> Color {
+        let (ray, distance) = ray_to_light(point, light, time, wavelength);
+        let intersections = self.intersect(ray);
+
+        let shadow = self.attenuation_along(ray, distance, &intersections);
+        if shadow == Color::black() {
+            return shadow;
+        }
+
+        let mut transmittance = self
+            .fog
+            .map(|fog| fog.medium.transmittance(distance.min(fog.max_distance)))
+            .unwrap_or(1.);
+
+        // Walk the bounded media between the point and the light, entering
+        // and leaving them in order of distance
+        let mut inside: Vec<(SimpleObject, f64)> = vec![];
+        for i in &intersections {
+            let Some(medium) = i.object.material.medium else {
+                continue;
+            };
+            let t = i.t.clamp(0., distance);
+
+            match inside.iter().position(|(object, _)| *object == i.object) {
+                Some(index) => {
+                    let (_, entered) = inside.remove(index);
+                    transmittance *= medium.transmittance(t - entered);
+                }
+                None => inside.push((i.object, t)),
+            }
+        }
+
+        shadow * transmittance
+    }
+
     fn intersect(&self, ray: Ray) -> Vec<Intersection> {
         let mut intersections: Vec<Intersection> = self
             .objects
@@ -58,61 +427,209 @@ impl World {
         intersections
     }
 
-    fn shade_hit(&self, comps: ComputedIntersection, remaining_depth: i32) -> Color {
-        let surface_color = self
+    fn shade_hit(
+        &self,
+        comps: ComputedIntersection,
+        remaining_depth: i32,
+        glossy_samples: usize,
+    ) -> Color {
+        let shading = self.shade_parts(comps, remaining_depth, glossy_samples);
+
+        shading.direct + shading.reflection + shading.refraction
+    }
+
+    fn shade_parts(
+        &self,
+        comps: ComputedIntersection,
+        remaining_depth: i32,
+        glossy_samples: usize,
+    ) -> Shading {
+        let mut material = comps.object.material().at(comps.object, comps.over_point);
+        let occlusion = self
+            .ambient_occlusion
+            .map(|settings| self.unoccluded_fraction(&comps, settings));
+        if let Some(open) = occlusion {
+            material.to_mut().ambient *= open;
+        }
+
+        let shadows: Vec<Color> = self
             .lights
             .iter()
             .map(|light| {
+                self.shadow_attenuation(comps.over_point, *light, comps.time, comps.wavelength)
+            })
+            .collect();
+
+        let surface_color = self
+            .lights
+            .iter()
+            .zip(&shadows)
+            .map(|(light, shadow)| {
                 material::lighting(
-                    comps.object.material(),
+                    &material,
                     comps.object,
                     *light,
-
                     comps.over_point,
                     comps.eye_vector,
                     comps.normal_vector,
-                    self.is_shadowed(comps.over_point, *light),
+                    *shadow,
                 )
             })
             .fold(Color::black(), |c1, c2| c1 + c2);
 
-        let reflected_color = self.reflected_color(comps, remaining_depth);
-        let refracted_color = self.refracted_color(comps, remaining_depth);
+        let reflected_color =
+            self.reflected_color(comps, &material, remaining_depth, glossy_samples);
+        let refracted_color =
+            self.refracted_color(comps, &material, remaining_depth, glossy_samples);
 
-        let material = comps.object.material();
-
-        if material.reflective > 0. && material.transparency > 0. {
+        let (reflection, refraction) = if material.reflective > 0. && material.transparency > 0. {
             let reflectance = comps.schlick();
 
-            surface_color + reflected_color * reflectance + refracted_color * (1. - reflectance)
+            (
+                reflected_color * reflectance,
+                refracted_color * (1. - reflectance),
+            )
+        } else {
+            (reflected_color, refracted_color)
+        };
+
+        let shadow = if shadows.is_empty() {
+            Color::white()
         } else {
-            surface_color + reflected_color + refracted_color
+            shadows.iter().fold(Color::black(), |c1, c2| c1 + *c2) * (1. / shadows.len() as f64)
+        };
+
+        Shading {
+            albedo: material.color,
+            direct: surface_color,
+            reflection,
+            refraction,
+            shadow,
+            occlusion,
+        }
+    }
+
+    // Fraction of the light that reaches `point`, per channel. Each surface
+    // on the way lets through its `transparency` tinted by its color, and
+    // glass absorbs more the thicker it is.
+    fn shadow_attenuation(
+        &self,
+        point: Tuple,
+        light: Light,
+        time: f64,
+        wavelength: Option<f64>,
+    ) -> Color {
+        let (ray, distance) = ray_to_light(point, light, time, wavelength);
+
+        self.attenuation_along(ray, distance, &self.intersect(ray))
+    }
+
+    // `shadow_attenuation` through the surfaces the ray to the light meets,
+    // `distance` being where the light is.
+    fn attenuation_along(&self, ray: Ray, distance: f64, intersections: &[Intersection]) -> Color {
+        let mut attenuation = Color::white();
+        let mut inside: Vec<(SimpleObject, f64)> = vec![];
+        for i in intersections {
+            // Boundaries of media don't block light, the medium itself does
+            let material = i.object.material;
+            if material.medium.is_some() || !material.casts_shadows {
+                continue;
+            }
+            let t = i.t.clamp(0., distance);
+
+            match inside.iter().position(|(object, _)| *object == i.object) {
+                Some(index) => {
+                    let (_, entered) = inside.remove(index);
+                    attenuation = attenuation * material.absorption_over(t - entered);
+                }
+                None => inside.push((i.object, t)),
+            }
+
+            if 0. <= i.t && i.t < distance {
+                let surface = material.at(i.object, ray.position(i.t));
+                attenuation = attenuation * surface.color * surface.transparency;
+            }
+
+            if attenuation.red.max(attenuation.green).max(attenuation.blue) <= 0. {
+                return Color::black();
+            }
         }
+
+        attenuation
     }
 
-    fn is_shadowed(&self, point: Tuple, light: Light) -> bool {
-        let vector = light.position - point;
-        let distance = vector.magnitude();
+    // Share of the hemisphere above the hit that is open, from 0 (fully
+    // enclosed) to 1. Rays ignore media and objects that don't cast shadows.
+    fn unoccluded_fraction(&self, comps: &ComputedIntersection, settings: AmbientOcclusion) -> f64 {
+        if settings.samples == 0 {
+            return 1.;
+        }
 
-        let ray = Ray::new(point, vector.normalize());
+        let point = comps.over_point;
+        let mut rng = Rng::from_seeds(&[
+            self.seed,
+            point.x.to_bits(),
+            point.y.to_bits(),
+            point.z.to_bits(),
+        ]);
 
-        Intersection::hit(&self.intersect(ray))
-            // Check if light or object is closer
-            .map(|hit| hit.object.material.casts_shadows && hit.t < distance)
-            .unwrap_or(false)
+        let open = (0..settings.samples)
+            .filter(|_| {
+                let direction =
+                    sampling::cosine_hemisphere_direction(&mut rng, comps.normal_vector);
+                let ray = comps.spawn_ray(point, direction);
+
+                !self.intersect(ray).iter().any(|i| {
+                    i.t >= 0.
+                        && i.t < settings.max_distance
+                        && i.object.material.medium.is_none()
+                        && i.object.material.casts_shadows
+                })
+            })
+            .count();
+
+        open as f64 / settings.samples as f64
     }
 
-    fn reflected_color(&self, comps: ComputedIntersection, remaining_depth: i32) -> Color {
+    // Grayscale ambient occlusion seen along `ray`: white where the first
+    // surface hit is fully open, and for rays that hit nothing.
+    pub fn ambient_occlusion_along(&self, ray: Ray, settings: AmbientOcclusion) -> Color {
+        let intersections = self.intersect(ray);
+
+        match Intersection::hit(&intersections) {
+            Some(hit) => {
+                let comps = hit.prepare_computations(ray, &intersections);
+                let open = self.unoccluded_fraction(&comps, settings);
+
+                Color::new(open, open, open)
+            }
+            None => Color::white(),
+        }
+    }
+
+    fn reflected_color(
+        &self,
+        comps: ComputedIntersection,
+        material: &Material,
+        remaining_depth: i32,
+        glossy_samples: usize,
+    ) -> Color {
         let no_depth_remaining = remaining_depth <= 0;
         let default_color = Color::black();
 
         if no_depth_remaining {
             return default_color;
         }
-        let reflective = comps.object.material().reflective;
+        let reflective = material.reflective;
         if reflective > 0. {
-            let reflect_ray = Ray::new(comps.over_point, comps.reflect_vector);
-            let color = self.color_at_with_depth(reflect_ray, remaining_depth - 1);
+            let color = self.glossy_color(
+                comps,
+                material.roughness,
+                comps.over_point,
+                comps.reflect_vector,
+                remaining_depth,
+                glossy_samples,
+            );
 
             color * reflective
         } else {
@@ -120,8 +637,14 @@ impl World {
         }
     }
 
-    fn refracted_color(&self, comps: ComputedIntersection, remaining_depth: i32) -> Color {
-        let object_is_opaque = comps.object.material().transparency == 0.;
+    fn refracted_color(
+        &self,
+        comps: ComputedIntersection,
+        material: &Material,
+        remaining_depth: i32,
+        glossy_samples: usize,
+    ) -> Color {
+        let object_is_opaque = material.transparency == 0.;
         let n_ratio = comps.n1 / comps.n2;
         let cos_i = comps.eye_vector.dot(comps.normal_vector);
         let sin2_t = n_ratio.powi(2) * (1. - cos_i.powi(2));
@@ -134,12 +657,571 @@ impl World {
             let direction =
                 comps.normal_vector * (n_ratio * cos_i - cos_t) - comps.eye_vector * n_ratio;
 
-            let refract_ray = Ray::new(comps.under_point, direction);
+            self.glossy_color(
+                comps,
+                material.roughness,
+                comps.under_point,
+                direction,
+                remaining_depth,
+                glossy_samples,
+            ) * material.transparency
+        }
+    }
+
+    // Color seen along `direction`, or averaged over a cone of directions
+    // around it for rough materials. Sampled directions that would cross
+    // to the wrong side of the surface fall back to `direction`.
+    fn glossy_color(
+        &self,
+        comps: ComputedIntersection,
+        roughness: f64,
+        origin: Tuple,
+        direction: Tuple,
+        remaining_depth: i32,
+        samples: usize,
+    ) -> Color {
+        if roughness <= 0. || self.glossy_samples == 0 {
+            let ray = comps.spawn_ray(origin, direction);
+            return self.trace(ray, remaining_depth - 1, 1);
+        }
+
+        let half_angle = roughness.min(1.) * FRAC_PI_2;
+        let side = direction.dot(comps.normal_vector).signum();
+        let mut rng = Rng::from_seeds(&[
+            self.seed,
+            origin.x.to_bits(),
+            origin.y.to_bits(),
+            origin.z.to_bits(),
+            direction.x.to_bits() ^ direction.y.to_bits() ^ direction.z.to_bits(),
+        ]);
 
-            let color = self.color_at_with_depth(refract_ray, remaining_depth - 1)
-                * comps.object.material().transparency;
+        let total = (0..samples)
+            .map(|_| {
+                let sampled = sampling::cone_direction(&mut rng, direction, half_angle);
+                let sampled = if sampled.dot(comps.normal_vector) * side > 0. {
+                    sampled
+                } else {
+                    direction
+                };
 
-            color
+                self.trace(comps.spawn_ray(origin, sampled), remaining_depth - 1, 1)
+            })
+            .fold(Color::black(), |c1, c2| c1 + c2);
+
+        total * (1. / samples as f64)
+    }
+}
+
+// Ray from `point` towards the light, and how far along it the light is.
+fn ray_to_light(point: Tuple, light: Light, time: f64, wavelength: Option<f64>) -> (Ray, f64) {
+    let vector = light.position - point;
+    let ray = Ray {
+        wavelength,
+        ..Ray::new(point, vector.normalize()).at_time(time)
+    };
+
+    (ray, vector.magnitude())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        math::matrix4::Matrix4,
+        misc::approx_equal,
+        motion::Motion,
+        pattern::Pattern,
+        shape::{sphere::Sphere, BoundingBox, LocalHit, Primitive},
+    };
+
+    // A mirror floor reflecting a ceiling that is white for x < 0 and black
+    // beyond, looked at right above the edge.
+    fn mirror_under_an_edge(roughness: f64) -> World {
+        let mut world = World::new();
+        world.add_light(Light::point_light(
+            Tuple::point(0., 10., 0.),
+            Color::white(),
+        ));
+
+        let mut mirror = Object::plane();
+        let mut material = Material::new();
+        material.color = Color::black();
+        material.ambient = 0.;
+        material.diffuse = 0.;
+        material.specular = 0.;
+        material.reflective = 1.;
+        material.roughness = roughness;
+        mirror.set_material(material);
+        world.add_object(mirror);
+
+        let mut ceiling = Object::plane();
+        ceiling.transform = Matrix4::translation(0., 2., 0.);
+        let mut pattern = Pattern::striped(Color::black(), Color::white());
+        *pattern.transform_mut() = Matrix4::scaling(100., 1., 1.);
+        let mut material = Material::with_pattern(pattern);
+        material.ambient = 1.;
+        material.diffuse = 0.;
+        material.specular = 0.;
+        ceiling.set_material(material);
+        world.add_object(ceiling);
+
+        world
+    }
+
+    fn look_down_at_the_edge(world: &World) -> Color {
+        world.color_at(Ray::new(
+            Tuple::point(0.001, 1., 0.),
+            Tuple::vector(0., -1., 0.),
+        ))
+    }
+
+    #[test]
+    fn a_smooth_mirror_reflects_a_sharp_edge() {
+        let world = mirror_under_an_edge(0.);
+
+        assert_eq!(look_down_at_the_edge(&world), Color::black());
+    }
+
+    #[test]
+    fn a_rough_mirror_blurs_a_sharp_edge() {
+        let mut world = mirror_under_an_edge(0.5);
+        world.glossy_samples = 64;
+
+        let color = look_down_at_the_edge(&world);
+
+        assert!(color.red > 0.3 && color.red < 0.7);
+    }
+
+    #[test]
+    fn rough_reflections_are_deterministic_under_a_seed() {
+        let mut world = mirror_under_an_edge(0.5);
+
+        let first = look_down_at_the_edge(&world);
+        let again = look_down_at_the_edge(&world);
+        world.seed = 1;
+        let other_seed = look_down_at_the_edge(&world);
+
+        assert_eq!(first, again);
+        assert!(first != other_seed);
+    }
+
+    #[test]
+    fn frosted_glass_blurs_what_is_behind_it() {
+        let mut world = mirror_under_an_edge(0.);
+        world.objects.remove(0);
+        world.glossy_samples = 64;
+
+        let mut pane = Object::cube();
+        pane.transform = Matrix4::translation(0., 1., 0.) * Matrix4::scaling(5., 0.1, 5.);
+        let mut material = Material::new();
+        material.color = Color::black();
+        material.ambient = 0.;
+        material.diffuse = 0.;
+        material.specular = 0.;
+        material.transparency = 1.;
+        material.roughness = 0.5;
+        pane.set_material(material);
+        world.add_object(pane);
+
+        let color = world.color_at(Ray::new(
+            Tuple::point(0.001, 0., 0.),
+            Tuple::vector(0., 1., 0.),
+        ));
+
+        assert!(color.red > 0.2 && color.red < 0.8);
+    }
+
+    fn world_with_occluder(material: Material) -> World {
+        let mut world = World::new();
+        world.add_light(Light::point_light(
+            Tuple::point(0., 10., 0.),
+            Color::white(),
+        ));
+        let mut occluder = Object::sphere();
+        occluder.transform = Matrix4::translation(0., 5., 0.);
+        occluder.set_material(material);
+        world.add_object(occluder);
+
+        world
+    }
+
+    #[test]
+    fn an_opaque_object_blocks_all_light() {
+        let world = world_with_occluder(Material::new());
+
+        assert_eq!(
+            world.shadow_attenuation(Tuple::point(0., 0., 0.), world.lights[0], 0., None),
+            Color::black()
+        );
+        assert_eq!(
+            world.shadow_attenuation(Tuple::point(3., 0., 0.), world.lights[0], 0., None),
+            Color::white()
+        );
+    }
+
+    #[test]
+    fn a_glass_sphere_casts_a_colored_shadow() {
+        let mut glass = Material::new();
+        glass.color = Color::new(1., 0.5, 0.2);
+        glass.transparency = 0.8;
+        let world = world_with_occluder(glass);
+
+        // Light goes through the front and the back of the sphere
+        let attenuation =
+            world.shadow_attenuation(Tuple::point(0., 0., 0.), world.lights[0], 0., None);
+
+        assert_eq!(attenuation, Color::new(0.64, 0.64 * 0.25, 0.64 * 0.04));
+    }
+
+    #[test]
+    fn thick_tinted_glass_absorbs_light_on_the_way_to_the_light() {
+        let mut glass = Material::new();
+        glass.transparency = 1.;
+        glass.absorption_color = Color::new(0.5, 1., 1.);
+        glass.absorption_density = 1.;
+        let world = world_with_occluder(glass);
+
+        // Two units of glass, the sphere's diameter
+        let attenuation =
+            world.shadow_attenuation(Tuple::point(0., 0., 0.), world.lights[0], 0., None);
+
+        assert_eq!(attenuation, Color::new(0.25, 1., 1.));
+    }
+
+    #[test]
+    fn objects_that_do_not_cast_shadows_let_light_through() {
+        let mut material = Material::new();
+        material.casts_shadows = false;
+        let world = world_with_occluder(material);
+
+        assert_eq!(
+            world.shadow_attenuation(Tuple::point(0., 0., 0.), world.lights[0], 0., None),
+            Color::white()
+        );
+    }
+
+    // A floor with a wall standing on it along x = 0.
+    fn floor_and_wall() -> World {
+        let mut world = World::new();
+        world.add_light(Light::point_light(
+            Tuple::point(5., 10., 0.),
+            Color::white(),
+        ));
+        world.add_object(Object::plane());
+        let mut wall = Object::plane();
+        wall.transform = Matrix4::rotation_z(FRAC_PI_2);
+        world.add_object(wall);
+
+        world
+    }
+
+    fn look_down_at(x: f64) -> Ray {
+        Ray::new(Tuple::point(x, 5., 0.), Tuple::vector(0., -1., 0.))
+    }
+
+    #[test]
+    fn open_floor_is_not_occluded() {
+        let world = floor_and_wall();
+        let settings = AmbientOcclusion::new(32, 1.);
+
+        assert_eq!(
+            world.ambient_occlusion_along(look_down_at(3.), settings),
+            Color::white()
+        );
+    }
+
+    #[test]
+    fn floor_next_to_a_wall_is_occluded() {
+        let world = floor_and_wall();
+        let settings = AmbientOcclusion::new(64, 1.);
+
+        let corner = world.ambient_occlusion_along(look_down_at(0.1), settings);
+
+        assert!(corner.red > 0.3 && corner.red < 0.9);
+        assert_eq!(
+            corner,
+            world.ambient_occlusion_along(look_down_at(0.1), settings)
+        );
+    }
+
+    #[test]
+    fn ambient_occlusion_darkens_the_ambient_term() {
+        let mut world = floor_and_wall();
+        let ray = look_down_at(0.1);
+        let plain = world.color_at(ray);
+
+        world.ambient_occlusion = Some(AmbientOcclusion::new(64, 1.));
+        let occluded = world.color_at(ray);
+        let open = world.ambient_occlusion_along(ray, AmbientOcclusion::new(64, 1.));
+
+        // Only the ambient term, 0.1 of the white light, is scaled
+        assert_eq!(plain - occluded, Color::white() * (0.1 * (1. - open.red)));
+        assert!(occluded.red < plain.red);
+    }
+
+    // Defined the way a crate using this one would.
+    #[derive(Debug)]
+    struct Ball(Sphere);
+
+    impl Primitive for Ball {
+        fn local_intersect(&self, local_ray: Ray) -> Vec<LocalHit> {
+            self.0.local_intersect(local_ray)
         }
+
+        fn local_normal_at(&self, local_point: Tuple, hit: LocalHit) -> Tuple {
+            self.0.local_normal_at(local_point, hit)
+        }
+
+        fn bounding_box(&self) -> BoundingBox {
+            self.0.bounding_box()
+        }
+    }
+
+    // A unit disc lying in the xz plane.
+    #[derive(Debug)]
+    struct Disc;
+
+    impl Primitive for Disc {
+        fn local_intersect(&self, local_ray: Ray) -> Vec<LocalHit> {
+            let t = -local_ray.origin.y / local_ray.direction.y;
+            let point = local_ray.position(t);
+
+            if t.is_finite() && point.x.powi(2) + point.z.powi(2) <= 1. {
+                vec![LocalHit::new(t)]
+            } else {
+                vec![]
+            }
+        }
+
+        fn local_normal_at(&self, _local_point: Tuple, _hit: LocalHit) -> Tuple {
+            Tuple::vector(0., 1., 0.)
+        }
+
+        fn bounding_box(&self) -> BoundingBox {
+            BoundingBox::new(Tuple::point(-1., 0., -1.), Tuple::point(1., 0., 1.))
+        }
+    }
+
+    fn lit(object: Object) -> World {
+        let mut world = World::new();
+        world.add_light(Light::point_light(
+            Tuple::point(-10., 10., -10.),
+            Color::white(),
+        ));
+        world.add_object(object);
+
+        world
+    }
+
+    #[test]
+    fn a_custom_primitive_renders_like_the_shape_it_wraps() {
+        let mut sphere = Object::sphere();
+        let mut ball = Object::custom(Ball(Sphere {}));
+        for object in [&mut sphere, &mut ball] {
+            object.transform = Matrix4::translation(0.5, 0., 0.) * Matrix4::scaling(1., 2., 1.);
+        }
+        let (sphere, ball) = (lit(sphere), lit(ball));
+
+        let ahead = |x: f64| Ray::new(Tuple::point(x, 0.5, -5.), Tuple::vector(0., 0., 1.));
+
+        for x in [-0.4, 0., 0.9, 1.6] {
+            assert_eq!(ball.color_at(ahead(x)), sphere.color_at(ahead(x)));
+        }
+        assert!(ball.color_at(ahead(0.)).red > 0.);
+        assert_eq!(ball.color_at(ahead(1.6)), Color::black());
+    }
+
+    #[test]
+    fn custom_primitives_use_their_own_intersections_and_normals() {
+        let mut disc = Object::custom(Disc);
+        disc.transform = Matrix4::scaling(2., 2., 2.);
+        let world = lit(disc);
+        let mut plane = Object::plane();
+        plane.transform = Matrix4::scaling(2., 2., 2.);
+        let plane = lit(plane);
+
+        let down = |x: f64| Ray::new(Tuple::point(x, 5., 0.), Tuple::vector(0., -1., 0.));
+
+        assert_eq!(world.color_at(down(1.5)), plane.color_at(down(1.5)));
+        assert_eq!(world.color_at(down(2.5)), Color::black());
+    }
+
+    // A teapot group moved up one unit, with a lid and a body.
+    fn teapot_world() -> World {
+        let mut lid = Object::sphere().named("lid").unwrap();
+        lid.transform = Matrix4::translation(0., 2., 0.);
+        let body = Object::cube().named("body").unwrap();
+        let mut teapot = Object::group(vec![lid, body]).named("teapot").unwrap();
+        teapot.transform = Matrix4::translation(0., 1., 0.);
+
+        let mut world = lit(Object::plane());
+        world.add_object(teapot);
+
+        world
+    }
+
+    #[test]
+    fn objects_are_found_by_path() {
+        let mut world = teapot_world();
+
+        assert_eq!(
+            world.find("teapot/lid").unwrap().name.as_deref(),
+            Some("lid")
+        );
+        assert_eq!(world.find("#1/#1"), world.find("teapot/body"));
+        assert!(world.find("#0").is_some());
+        assert!(world.find("1").is_none());
+        assert!(world.find("teapot/spout").is_none());
+        assert!(world.find("teapot/lid/knob").is_none());
+
+        world.find_mut("teapot/lid").unwrap().transform = Matrix4::identity();
+        assert_eq!(
+            world.find("teapot/lid").unwrap().transform,
+            Matrix4::identity()
+        );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn nodes_carry_their_world_transforms() {
+        let world = teapot_world();
+
+        let nodes = world.nodes();
+
+        let paths: Vec<&str> = nodes.iter().map(|node| node.path.as_str()).collect();
+        assert_eq!(paths, ["#0", "teapot", "teapot/lid", "teapot/body"]);
+        assert_eq!(nodes[2].transform, Matrix4::translation(0., 3., 0.));
+        assert_eq!(
+            world.find("teapot").unwrap().descendants()[0].transform,
+            Matrix4::translation(0., 3., 0.)
+        );
+    }
+
+    #[test]
+    fn paths_find_objects_with_repeated_or_number_names() {
+        let legs = (0..2).map(|_| Object::cube().named("leg").unwrap());
+        let table = Object::group(legs.chain([Object::sphere().named("0").unwrap()]).collect())
+            .named("table")
+            .unwrap();
+        let world = lit(table);
+
+        let nodes = world.nodes();
+
+        let paths: Vec<&str> = nodes.iter().map(|node| node.path.as_str()).collect();
+        assert_eq!(paths, ["table", "table/leg", "table/#1", "table/0"]);
+        for node in &nodes {
+            assert!(std::ptr::eq(world.find(&node.path).unwrap(), node.object));
+        }
+        assert!(Object::cube().named("top/left").is_err());
+        assert!(Object::cube().named("#2").is_err());
+    }
+
+    #[test]
+    fn parents_are_found_by_path() {
+        let world = teapot_world();
+
+        assert_eq!(world.parent("teapot/lid"), world.find("teapot"));
+        assert_eq!(world.parent("#1/#1"), world.find("teapot"));
+        assert!(world.parent("teapot").is_none());
+        assert!(world.parent("teapot/spout").is_none());
+        assert_eq!(
+            world.find("teapot").unwrap().parent("lid"),
+            world.find("teapot")
+        );
+    }
+
+    #[test]
+    fn objects_can_be_removed_and_replaced() {
+        let mut world = teapot_world();
+        let down = Ray::new(Tuple::point(0., 10., 0.), Tuple::vector(0., -1., 0.));
+        let with_lid = world.color_at(down);
+
+        let lid = world.remove("teapot/lid").unwrap();
+        assert_eq!(lid.name.as_deref(), Some("lid"));
+        assert!(world.find("teapot/lid").is_none());
+        assert_eq!(
+            world.find("teapot/#0").unwrap().name.as_deref(),
+            Some("body")
+        );
+
+        let old = world.replace("teapot/body", lid).unwrap();
+        assert_eq!(old.name.as_deref(), Some("body"));
+        assert_eq!(world.color_at(down), with_lid);
+
+        // Emptied groups are still fine to render
+        world.remove("teapot/lid").unwrap();
+        assert!(world.find("teapot").unwrap().children().is_empty());
+        assert_ne!(world.color_at(down), with_lid);
+        assert!(world.remove("teapot/lid").is_none());
+        assert!(world.remove("teapot").is_some());
+        assert_eq!(world.objects.len(), 1);
+    }
+
+    fn camera_looking_at_origin() -> Camera {
+        let mut camera = Camera::new(11, 11, FRAC_PI_2);
+        camera.transform = crate::math::transformations::view_transform(
+            Tuple::point(0., 0., -5.),
+            Tuple::point(0., 0., 0.),
+            Tuple::vector(0., 1., 0.),
+        );
+
+        camera
+    }
+
+    #[test]
+    fn picking_finds_the_nearest_object_under_a_pixel() {
+        let mut ball = Object::sphere().named("ball").unwrap();
+        ball.transform = Matrix4::translation(0., 0., 5.);
+        let mut world = lit(
+            Object::group(vec![Object::cube().named("box").unwrap(), ball])
+                .named("toys")
+                .unwrap(),
+        );
+        let camera = camera_looking_at_origin();
+
+        let pick = world.pick(&camera, 5, 5).unwrap();
+
+        assert_eq!(pick.path, "toys/box");
+        assert!(approx_equal(pick.t, 4.));
+        assert_eq!(pick.point, Tuple::point(0., 0., -1.));
+        assert_eq!(pick.normal, Tuple::vector(0., 0., -1.));
+        assert_eq!(world.find(&pick.path), Some(pick.object));
+
+        world.remove("toys/box");
+        let pick = world.pick(&camera, 5, 5).unwrap();
+        assert_eq!(pick.path, "toys/ball");
+        assert!(approx_equal(pick.t, 9.));
+        assert!(approx_equal(pick.uv.unwrap().1, 0.5));
+
+        assert!(world.pick(&camera, 0, 0).is_none());
+    }
+
+    #[test]
+    fn picking_tells_shapes_with_the_same_name_apart() {
+        let mut far = Object::sphere().named("ball").unwrap();
+        far.transform = Matrix4::translation(0., 0., 5.);
+        let near = Object::sphere().named("ball").unwrap();
+        let world = lit(Object::group(vec![far, near]));
+        let camera = camera_looking_at_origin();
+
+        let pick = world.pick(&camera, 5, 5).unwrap();
+
+        assert_eq!(pick.path, "#0/#1");
+        assert!(std::ptr::eq(world.find(&pick.path).unwrap(), pick.object));
+    }
+
+    #[test]
+    fn picking_sees_moving_objects_as_the_shutter_opens() {
+        let mut ball = Object::sphere();
+        ball.motion = Some(Motion::linear(
+            Matrix4::translation(5., 0., 0.),
+            Matrix4::translation(0., 0., 0.),
+        ));
+        let world = lit(ball);
+        let mut camera = camera_looking_at_origin();
+
+        camera.shutter_open = 1.;
+        assert!(world.pick(&camera, 5, 5).is_some());
+        camera.shutter_open = 0.;
+        assert!(world.pick(&camera, 5, 5).is_none());
+    }
+}